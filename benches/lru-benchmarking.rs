@@ -3,7 +3,7 @@ use std::hint::black_box;
 use std::sync::Arc;
 use std::thread;
 
-use lru_cache::LruCache;
+use lru_cache::{LruCache, ShardedLruCache};
 
 fn bench_concurrent(c: &mut Criterion) {
     c.bench_function("concurrent_4_threads", |b| {
@@ -28,5 +28,28 @@ fn bench_concurrent(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_concurrent);
+fn bench_concurrent_sharded(c: &mut Criterion) {
+    c.bench_function("concurrent_4_threads_sharded", |b| {
+        b.iter(|| {
+            let cache = Arc::new(ShardedLruCache::new(1000, 16));
+            let mut handles = vec![];
+
+            for t in 0..4 {
+                let c = Arc::clone(&cache);
+                handles.push(thread::spawn(move || {
+                    for i in 0..1000 {
+                        c.put(i, t);
+                        black_box(c.get(&i));
+                    }
+                }));
+            }
+
+            for h in handles {
+                h.join().unwrap();
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_concurrent, bench_concurrent_sharded);
 criterion_main!(benches);
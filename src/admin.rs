@@ -0,0 +1,322 @@
+use std::fmt::{Display, Write as _};
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::LruCache;
+
+// keys listed by /keys when no limit is given
+const DEFAULT_LIMIT: usize = 100;
+
+// a client that stalls mid-request is dropped after this
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+// what a request to `AdminHandler` comes back with, the body is always JSON
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminResponse {
+    pub status: u16,
+    pub body: String,
+}
+
+impl AdminResponse {
+    fn ok(body: String) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: format!("{{\"error\":{}}}", json_string(message)),
+        }
+    }
+}
+
+// operational endpoints for a running cache
+//
+//     GET  /stats                counts, sizes and hit rate
+//     GET  /keys?limit=N         the N most recently used keys, 100 by default
+//     POST /invalidate?key=K     drops one key's cached copy
+//     POST /resize?capacity=N    sets the entry bound
+//
+// `handle` answers one request and can be mounted in any http server, `serve`
+// is a minimal blocking server of its own for processes without one. keys are
+// shown with Display and parsed back with FromStr
+pub struct AdminHandler<K, V, S = RandomState> {
+    cache: Arc<LruCache<K, V, S>>,
+}
+
+impl<K, V, S> AdminHandler<K, V, S>
+where
+    K: Eq + Hash + Display + FromStr,
+    S: BuildHasher,
+{
+    pub fn new(cache: Arc<LruCache<K, V, S>>) -> Self {
+        Self { cache }
+    }
+
+    // `target` is the path with its query string, as in the request line
+    pub fn handle(&self, method: &str, target: &str) -> AdminResponse {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let param = |name: &str| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|&(key, _)| key == name)
+                .map(|(_, value)| percent_decode(value))
+        };
+
+        match (method, path) {
+            ("GET", "/stats") => self.stats(),
+            ("GET", "/keys") => match param("limit").map(|limit| limit.parse()) {
+                None => self.keys(DEFAULT_LIMIT),
+                Some(Ok(limit)) => self.keys(limit),
+                Some(Err(_)) => AdminResponse::error(400, "limit is not a number"),
+            },
+            ("POST", "/invalidate") => match param("key").map(|key| key.parse::<K>()) {
+                Some(Ok(key)) => {
+                    // the system of record behind the cache keeps it
+                    let removed = self.cache.invalidate(&key).is_some();
+                    AdminResponse::ok(format!("{{\"removed\":{removed}}}"))
+                }
+                Some(Err(_)) => AdminResponse::error(400, "key does not parse"),
+                None => AdminResponse::error(400, "key is required"),
+            },
+            ("POST", "/resize") => match param("capacity").map(|capacity| capacity.parse()) {
+                Some(Ok(capacity)) => {
+                    self.cache.set_capacity(capacity);
+                    AdminResponse::ok(format!(
+                        "{{\"capacity\":{capacity},\"len\":{}}}",
+                        self.cache.len()
+                    ))
+                }
+                Some(Err(_)) => AdminResponse::error(400, "capacity is not a number"),
+                None => AdminResponse::error(400, "capacity is required"),
+            },
+            (_, "/stats" | "/keys" | "/invalidate" | "/resize") => {
+                AdminResponse::error(405, "method not allowed")
+            }
+            _ => AdminResponse::error(404, "not found"),
+        }
+    }
+
+    fn stats(&self) -> AdminResponse {
+        let stats = self.cache.stats();
+        AdminResponse::ok(format!(
+            "{{\"len\":{},\"capacity\":{},\"weight\":{},\"max_weight\":{},\
+             \"hits\":{},\"misses\":{},\"hit_rate\":{},\"insertions\":{},\
+             \"evictions\":{},\"expirations\":{}}}",
+            self.cache.len(),
+            self.cache.capacity(),
+            self.cache.weight(),
+            self.cache.max_weight(),
+            stats.hits,
+            stats.misses,
+            stats.hit_rate(),
+            stats.insertions,
+            stats.evictions,
+            stats.expirations,
+        ))
+    }
+
+    // formats keys under the read lock without cloning the rest
+    fn keys(&self, limit: usize) -> AdminResponse {
+        let mut body = String::from("{\"keys\":[");
+        let state = self.cache.read();
+        for (i, node) in state
+            .iter_live(state.expiry_cutoff())
+            .take(limit)
+            .enumerate()
+        {
+            if i > 0 {
+                body.push(',');
+            }
+            body.push_str(&json_string(&node.key.to_string()));
+        }
+        drop(state);
+        body.push_str("]}");
+        AdminResponse::ok(body)
+    }
+
+    // answers requests one at a time until accepting fails, each connection
+    // is closed after its response
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            // a client that misbehaves only loses its own request
+            let _ = self.answer(stream?);
+        }
+        Ok(())
+    }
+
+    fn answer(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut reader = BufReader::new(&stream);
+
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        // headers are read and ignored, no endpoint takes a body
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+
+        let mut words = request.split_ascii_whitespace();
+        let response = match (words.next(), words.next()) {
+            (Some(method), Some(target)) => self.handle(method, target),
+            _ => AdminResponse::error(400, "bad request line"),
+        };
+        write!(
+            &stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status,
+            reason(response.status),
+            response.body.len(),
+            response.body,
+        )?;
+        (&stream).flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "",
+    }
+}
+
+// `+` and %XX escapes as browsers and curl send them, malformed escapes are
+// kept as they are
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'+', _) => out.push(b' '),
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 2;
+            }
+            (byte, _) => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn endpoints_inspect_and_manage_the_cache() {
+        let cache = Arc::new(LruCache::new(4));
+        for key in ["a", "b c", "d"] {
+            cache.put(key.to_string(), 1);
+        }
+        cache.get("a");
+        let admin = AdminHandler::new(Arc::clone(&cache));
+
+        let keys = admin.handle("GET", "/keys?limit=2");
+        assert_eq!(keys.body, r#"{"keys":["a","d"]}"#);
+
+        let removed = admin.handle("POST", "/invalidate?key=b+c");
+        assert_eq!(removed.body, r#"{"removed":true}"#);
+        assert!(!cache.contains_key("b c"));
+
+        let resized = admin.handle("POST", "/resize?capacity=1");
+        assert_eq!(resized.body, r#"{"capacity":1,"len":1}"#);
+
+        let stats = admin.handle("GET", "/stats");
+        assert!(stats.body.contains(r#""hits":1"#));
+        assert!(stats.body.contains(r#""capacity":1"#));
+    }
+
+    #[test]
+    fn invalidating_leaves_the_store_alone() {
+        struct NoDeletes;
+
+        impl crate::CacheStore<u32, u32> for NoDeletes {
+            fn write(&self, _: &u32, _: &u32) {}
+
+            fn delete(&self, key: &u32) {
+                panic!("{key} deleted from the store");
+            }
+        }
+
+        let cache = LruCache::builder()
+            .capacity(4)
+            .write_through(NoDeletes)
+            .build()
+            .unwrap();
+        cache.put(1, 1);
+        let admin = AdminHandler::new(Arc::new(cache));
+
+        let removed = admin.handle("POST", "/invalidate?key=1");
+        assert_eq!(removed.body, r#"{"removed":true}"#);
+    }
+
+    #[test]
+    fn bad_requests_are_refused() {
+        let admin: AdminHandler<u32, u32> = AdminHandler::new(Arc::new(LruCache::new(4)));
+
+        assert_eq!(admin.handle("GET", "/invalidate?key=1").status, 405);
+        assert_eq!(admin.handle("POST", "/invalidate?key=x").status, 400);
+        assert_eq!(admin.handle("POST", "/resize").status, 400);
+        assert_eq!(admin.handle("GET", "/nowhere").status, 404);
+    }
+
+    #[test]
+    fn serves_over_http() {
+        let cache = Arc::new(LruCache::new(4));
+        cache.put(7u32, "seven");
+        let admin = AdminHandler::new(cache);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || admin.serve(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /keys HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(r#"{"keys":["7"]}"#));
+    }
+}
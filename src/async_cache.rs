@@ -0,0 +1,306 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{OnceCell, RwLock};
+
+use crate::{CacheStats, LruCache};
+
+// LruCache for async code, every method can be awaited without parking the
+// executor thread
+//
+// the cache's own lock is only held for the few instructions of a lookup or
+// an insert, what stalls an executor is waiting for it while other tasks hold
+// it. async callers queue on an async gate first, a waiting task yields
+// instead of blocking, and whoever gets through takes the cache lock without
+// competing with other tasks. only the janitor and refresh threads still
+// share that lock, and they hold it just as briefly
+//
+// callers holding the gate for reading take the cache's read lock, so peeks
+// and presence checks still run side by side. so do gets when the cache
+// serves hits under its read lock, one meeting an expired entry takes the
+// write lock for a moment to drop it
+pub struct AsyncLruCache<K, V, S = RandomState> {
+    cache: LruCache<K, V, S>,
+    gate: RwLock<()>,
+    // loads in flight for `get_with`, one cell per missing key. the lock is
+    // never held across an await
+    loads: Mutex<HashMap<K, Arc<OnceCell<V>>>>,
+}
+
+impl<K: Eq + Hash, V> AsyncLruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self::from(LruCache::new(capacity))
+    }
+}
+
+// anything the builder can set up, weights, ttls or policies, carries over
+impl<K, V, S> From<LruCache<K, V, S>> for AsyncLruCache<K, V, S> {
+    fn from(cache: LruCache<K, V, S>) -> Self {
+        Self {
+            cache,
+            gate: RwLock::new(()),
+            loads: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> AsyncLruCache<K, V, S> {
+    pub async fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let _gate = self.gate.read().await;
+        self.cache.contains_key(key)
+    }
+
+    pub async fn put(&self, key: K, value: V) -> Option<V> {
+        let _gate = self.gate.write().await;
+        self.cache.put(key, value)
+    }
+
+    pub async fn put_with_ttl(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let _gate = self.gate.write().await;
+        self.cache.put_with_ttl(key, value, ttl)
+    }
+
+    pub async fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let _gate = self.gate.write().await;
+        self.cache.remove(key)
+    }
+
+    // see `LruCache::warm_up`
+    pub async fn warm_up<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let _gate = self.gate.write().await;
+        self.cache.warm_up(entries);
+    }
+
+    // see `LruCache::warm_up_from_store`
+    pub async fn warm_up_from_store<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let _gate = self.gate.write().await;
+        self.cache.warm_up_from_store(entries);
+    }
+
+    // awaits `load` without holding anything, then warms up with its
+    // entries, hottest first
+    pub async fn warm_up_with<F, Fut, I>(&self, load: F)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = I>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let entries = load().await;
+        self.warm_up(entries).await;
+    }
+
+    pub async fn clear(&self) {
+        let _gate = self.gate.write().await;
+        self.cache.clear();
+    }
+
+    pub async fn len(&self) -> usize {
+        let _gate = self.gate.read().await;
+        self.cache.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let _gate = self.gate.read().await;
+        self.cache.stats()
+    }
+
+    pub async fn capacity(&self) -> usize {
+        let _gate = self.gate.read().await;
+        self.cache.capacity()
+    }
+}
+
+impl<K: Eq + Hash, V: Clone, S: BuildHasher> AsyncLruCache<K, V, S> {
+    // a hit promotes the entry, so lookups queue like writes unless the
+    // cache's policy leaves hits to the read lock
+    pub async fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.cache.shared_lookups() {
+            let _gate = self.gate.read().await;
+            return self.cache.get(key);
+        }
+        let _gate = self.gate.write().await;
+        self.cache.get(key)
+    }
+
+    pub async fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let _gate = self.gate.read().await;
+        self.cache.peek(key)
+    }
+
+    // `f` runs with the gate held, keep it to building the value
+    pub async fn get_or_insert_with<F>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce() -> V,
+    {
+        let _gate = self.gate.write().await;
+        self.cache.get_or_insert_with(key, f)
+    }
+
+    // read-through with single-flight loading: of the callers missing on the
+    // same key at once only one runs `load`, the others await its value. if
+    // that caller is dropped mid-load the next waiter starts its own load
+    pub async fn get_with<F, Fut>(&self, key: K, load: F) -> V
+    where
+        K: Clone,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        if let Some(value) = self.get(&key).await {
+            return value;
+        }
+
+        let cell = {
+            let mut loads = self.loads.lock().unwrap();
+            Arc::clone(loads.entry(key.clone()).or_default())
+        };
+        let value = cell
+            .get_or_init(|| async {
+                // a load that finished between the miss and taking the cell
+                // has already cached the value
+                if let Some(value) = self.get(&key).await {
+                    return value;
+                }
+                let value = load().await;
+                self.put(key.clone(), value.clone()).await;
+                value
+            })
+            .await
+            .clone();
+
+        let mut loads = self.loads.lock().unwrap();
+        if loads.get(&key).is_some_and(|done| Arc::ptr_eq(done, &cell)) {
+            loads.remove(&key);
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::Fifo;
+
+    #[tokio::test]
+    async fn behaves_like_the_blocking_cache() {
+        let cache = AsyncLruCache::new(2);
+
+        cache.put(1, "a").await;
+        cache.put(2, "b").await;
+        assert_eq!(cache.get(&1).await, Some("a"));
+        cache.put(3, "c").await;
+
+        assert!(!cache.contains_key(&2).await);
+        assert_eq!(cache.peek(&1).await, Some("a"));
+        assert_eq!(cache.remove(&3).await, Some("c"));
+        assert_eq!(cache.len().await, 1);
+        assert_eq!(cache.stats().await.hits, 1);
+    }
+
+    #[tokio::test]
+    async fn waiting_callers_yield_to_other_tasks() {
+        let cache = AsyncLruCache::new(4);
+        let ran = Cell::new(false);
+
+        // on a single threaded runtime a blocked lookup would never let the
+        // other task run while the gate is held
+        let held = cache.gate.write().await;
+        let lookup = async {
+            cache.put(1, 1).await;
+            ran.get()
+        };
+        let other = async {
+            ran.set(true);
+            drop(held);
+        };
+
+        let (saw_other, ()) = tokio::join!(lookup, other);
+        assert!(saw_other);
+        assert_eq!(cache.get(&1).await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn shared_gets_pass_a_held_read_gate() {
+        let cache = AsyncLruCache::from(
+            LruCache::builder()
+                .capacity(4)
+                .eviction_policy(Fifo::new())
+                .build()
+                .unwrap(),
+        );
+        cache.put(1, 1).await;
+
+        // a get waiting for the write gate would never return
+        let _held = cache.gate.read().await;
+        assert_eq!(cache.get(&1).await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn concurrent_misses_load_once() {
+        let cache = AsyncLruCache::new(4);
+        let loads = AtomicUsize::new(0);
+        let load = || async {
+            loads.fetch_add(1, Ordering::Relaxed);
+            // let the other callers reach the key while this one loads
+            tokio::task::yield_now().await;
+            "loaded"
+        };
+
+        let values = tokio::join!(
+            cache.get_with(1, load),
+            cache.get_with(1, load),
+            cache.get_with(1, load),
+        );
+
+        assert_eq!(values, ("loaded", "loaded", "loaded"));
+        assert_eq!(loads.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.get_with(1, load).await, "loaded");
+        assert_eq!(loads.load(Ordering::Relaxed), 1);
+        assert!(cache.loads.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn warm_up_pulls_from_a_loader() {
+        let cache = AsyncLruCache::new(2);
+
+        cache
+            .warm_up_with(|| async { vec![(1, "a"), (2, "b"), (3, "c")] })
+            .await;
+
+        assert_eq!(cache.len().await, 2);
+        assert_eq!(cache.peek(&1).await, Some("a"));
+        assert!(!cache.contains_key(&3).await);
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::LruCache;
+
+// fetches many keys in one backend round trip, keys it has nothing for are
+// left out of the map
+type LoadMany<K, V> = Box<dyn Fn(&[K]) -> HashMap<K, V> + Send + Sync>;
+
+// read-through cache that coalesces misses, dataloader style
+//
+// the first miss opens a batch and waits `window` for more misses to join it
+// before calling the loader once with every key collected. each batch is
+// loaded by the first caller that waits on it, the others block on its
+// result, and if that caller panics the next one loads the batch instead
+pub struct BatchLoader<K, V, S = RandomState> {
+    cache: LruCache<K, V, S>,
+    load: LoadMany<K, V>,
+    window: Duration,
+    open: Open<K, OnceLock<Loaded<K, V>>>,
+}
+
+// what a batch found for each of its keys
+type Loaded<K, V> = HashMap<K, Option<V>>;
+
+// the batch still taking keys, if any
+type Open<K, C> = Mutex<Option<Arc<Batch<K, C>>>>;
+
+// keys gathered so far and the cell their values are published in
+struct Batch<K, C> {
+    keys: Mutex<Vec<K>>,
+    loaded: C,
+}
+
+// queues `key` on the open batch, opening one if needed
+fn join<K: Eq + Clone, C: Default>(open: &Open<K, C>, key: &K) -> Arc<Batch<K, C>> {
+    let mut open = open.lock().unwrap();
+    let batch = open.get_or_insert_with(|| {
+        Arc::new(Batch {
+            keys: Mutex::new(Vec::new()),
+            loaded: C::default(),
+        })
+    });
+    let mut keys = batch.keys.lock().unwrap();
+    if !keys.contains(key) {
+        keys.push(key.clone());
+    }
+    drop(keys);
+    Arc::clone(batch)
+}
+
+// stops `batch` taking keys and returns the keys it collected
+fn close<K: Clone, C>(open: &Open<K, C>, batch: &Arc<Batch<K, C>>) -> Vec<K> {
+    let mut open = open.lock().unwrap();
+    if open
+        .as_ref()
+        .is_some_and(|current| Arc::ptr_eq(current, batch))
+    {
+        *open = None;
+    }
+    batch.keys.lock().unwrap().clone()
+}
+
+impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher> BatchLoader<K, V, S> {
+    // a window of a millisecond or two is usually enough to gather the
+    // misses of one burst of requests
+    pub fn new<F>(cache: LruCache<K, V, S>, window: Duration, load: F) -> Self
+    where
+        F: Fn(&[K]) -> HashMap<K, V> + Send + Sync + 'static,
+    {
+        Self {
+            cache,
+            load: Box::new(load),
+            window,
+            open: Mutex::new(None),
+        }
+    }
+
+    // for puts, invalidation or stats, loaded values land here
+    pub fn cache(&self) -> &LruCache<K, V, S> {
+        &self.cache
+    }
+
+    // None when the loader had nothing for the key, which is not cached
+    pub fn get(&self, key: &K) -> Option<V> {
+        if let Some(value) = self.cache.get(key) {
+            return Some(value);
+        }
+
+        let batch = join(&self.open, key);
+        let loaded = batch.loaded.get_or_init(|| {
+            thread::sleep(self.window);
+            let keys = close(&self.open, &batch);
+            let mut found = (self.load)(&keys);
+            keys.into_iter()
+                .map(|key| {
+                    let value = found.remove(&key);
+                    if let Some(value) = &value {
+                        self.cache.put(key.clone(), value.clone());
+                    }
+                    (key, value)
+                })
+                .collect()
+        });
+        loaded.get(key).cloned().flatten()
+    }
+}
+
+#[cfg(feature = "async")]
+pub use self::nonblocking::AsyncBatchLoader;
+
+#[cfg(feature = "async")]
+mod nonblocking {
+    use std::collections::HashMap;
+    use std::future::Future;
+    use std::hash::{BuildHasher, Hash, RandomState};
+    use std::pin::Pin;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use tokio::sync::OnceCell;
+
+    use super::{Loaded, Open, close, join};
+    use crate::AsyncLruCache;
+
+    type LoadMany<K, V> =
+        Box<dyn Fn(Vec<K>) -> Pin<Box<dyn Future<Output = HashMap<K, V>> + Send>> + Send + Sync>;
+
+    // BatchLoader for async code. the window is waited out on tokio's timer,
+    // so it needs a tokio runtime with time enabled. a caller dropped while
+    // loading hands the batch to the next waiter
+    pub struct AsyncBatchLoader<K, V, S = RandomState> {
+        cache: AsyncLruCache<K, V, S>,
+        load: LoadMany<K, V>,
+        window: Duration,
+        open: Open<K, OnceCell<Loaded<K, V>>>,
+    }
+
+    impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher> AsyncBatchLoader<K, V, S> {
+        pub fn new<F, Fut>(cache: AsyncLruCache<K, V, S>, window: Duration, load: F) -> Self
+        where
+            F: Fn(Vec<K>) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = HashMap<K, V>> + Send + 'static,
+        {
+            Self {
+                cache,
+                load: Box::new(move |keys| Box::pin(load(keys))),
+                window,
+                open: Mutex::new(None),
+            }
+        }
+
+        pub fn cache(&self) -> &AsyncLruCache<K, V, S> {
+            &self.cache
+        }
+
+        pub async fn get(&self, key: &K) -> Option<V> {
+            if let Some(value) = self.cache.get(key).await {
+                return Some(value);
+            }
+
+            let batch = join(&self.open, key);
+            let loaded = batch
+                .loaded
+                .get_or_init(|| async {
+                    tokio::time::sleep(self.window).await;
+                    let keys = close(&self.open, &batch);
+                    let mut found = (self.load)(keys.clone()).await;
+                    let mut loaded = HashMap::with_capacity(keys.len());
+                    for key in keys {
+                        let value = found.remove(&key);
+                        if let Some(value) = &value {
+                            self.cache.put(key.clone(), value.clone()).await;
+                        }
+                        loaded.insert(key, value);
+                    }
+                    loaded
+                })
+                .await;
+            loaded.get(key).cloned().flatten()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn concurrent_misses_share_one_load() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        let loader = BatchLoader::new(
+            LruCache::new(16),
+            Duration::from_millis(50),
+            move |keys: &[u32]| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                // odd keys are missing upstream
+                keys.iter()
+                    .filter(|&&key| key % 2 == 0)
+                    .map(|&key| (key, key * 10))
+                    .collect()
+            },
+        );
+
+        let values: Vec<_> = thread::scope(|scope| {
+            let loader = &loader;
+            let handles: Vec<_> = (0..8)
+                .map(|key| scope.spawn(move || loader.get(&key)))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        for (key, value) in (0..8).zip(values) {
+            assert_eq!(value, (key % 2 == 0).then_some(key * 10));
+        }
+        assert_eq!(loader.cache().len(), 4);
+        assert_eq!(loader.get(&2), Some(20));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_misses_share_one_load() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        let loader = AsyncBatchLoader::new(
+            crate::AsyncLruCache::new(16),
+            Duration::from_millis(10),
+            move |keys: Vec<u32>| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                async move { keys.into_iter().map(|key| (key, key + 1)).collect() }
+            },
+        );
+
+        let values = tokio::join!(loader.get(&1), loader.get(&2), loader.get(&1));
+
+        assert_eq!(values, (Some(2), Some(3), Some(2)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,361 @@
+// serves an LruCache over the memcached text protocol
+//
+// meant for integration tests, so sidecars and memcached tooling can talk to
+// the cache. covers get, gets, set, delete, flush_all, stats, version and
+// quit, one thread per connection
+//
+//     lru-memcached [--listen 127.0.0.1:11211] [--max-bytes 67108864]
+
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use lru_cache::LruCache;
+
+// memcached's own limit on key length
+const MAX_KEY: usize = 250;
+
+// exptimes above this are unix timestamps rather than seconds from now
+const RELATIVE_LIMIT: i64 = 60 * 60 * 24 * 30;
+
+// memcached's default item size limit
+const MAX_ITEM: u64 = 1024 * 1024;
+
+#[derive(Clone)]
+struct Item {
+    flags: u32,
+    data: Arc<[u8]>,
+}
+
+struct Server {
+    cache: LruCache<String, Item>,
+    // largest data block a set may send, never more than the whole budget
+    max_item: usize,
+    started: Instant,
+}
+
+impl Server {
+    // the budget covers keys and data, as memcached's limit_maxbytes does
+    fn new(max_bytes: u64) -> Self {
+        let cache = LruCache::builder()
+            .max_weight(max_bytes)
+            .weigher(|key: &String, item: &Item| (key.len() + item.data.len()) as u64)
+            .build()
+            .expect("a weight budget always builds");
+        Self {
+            cache,
+            max_item: MAX_ITEM.min(max_bytes) as usize,
+            started: Instant::now(),
+        }
+    }
+
+    // answers commands until the client quits or hangs up
+    fn serve<R: BufRead, W: Write>(&self, mut reader: R, mut writer: W) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let words: Vec<&str> = line.split_ascii_whitespace().collect();
+            let Some((&command, args)) = words.split_first() else {
+                writer.write_all(b"ERROR\r\n")?;
+                writer.flush()?;
+                continue;
+            };
+            match command {
+                "get" => self.get(args, false, &mut writer)?,
+                "gets" => self.get(args, true, &mut writer)?,
+                "set" => self.set(args, &mut reader, &mut writer)?,
+                "delete" => self.delete(args, &mut writer)?,
+                "flush_all" => {
+                    self.cache.clear();
+                    reply(&mut writer, args, "OK")?;
+                }
+                "stats" => self.stats(&mut writer)?,
+                "version" => write!(writer, "VERSION {}\r\n", env!("CARGO_PKG_VERSION"))?,
+                "quit" => return Ok(()),
+                _ => writer.write_all(b"ERROR\r\n")?,
+            }
+            writer.flush()?;
+        }
+    }
+
+    fn get<W: Write>(&self, keys: &[&str], cas: bool, writer: &mut W) -> io::Result<()> {
+        if keys.is_empty() {
+            return writer.write_all(b"ERROR\r\n");
+        }
+        for &key in keys {
+            if let Some(item) = self.cache.get(key) {
+                write!(writer, "VALUE {key} {} {}", item.flags, item.data.len())?;
+                // no cas support, 0 stands in for the unique
+                writer.write_all(if cas { b" 0\r\n" } else { b"\r\n" })?;
+                writer.write_all(&item.data)?;
+                writer.write_all(b"\r\n")?;
+            }
+        }
+        writer.write_all(b"END\r\n")
+    }
+
+    // set <key> <flags> <exptime> <bytes> [noreply], then the data block
+    fn set<R: BufRead, W: Write>(
+        &self,
+        args: &[&str],
+        reader: &mut R,
+        writer: &mut W,
+    ) -> io::Result<()> {
+        let (key, flags, exptime, len) = match args {
+            [key, flags, exptime, len] | [key, flags, exptime, len, "noreply"] => {
+                match (
+                    flags.parse::<u32>(),
+                    exptime.parse::<i64>(),
+                    len.parse::<usize>(),
+                ) {
+                    (Ok(flags), Ok(exptime), Ok(len)) if valid_key(key) => {
+                        (*key, flags, exptime, len)
+                    }
+                    _ => return writer.write_all(b"CLIENT_ERROR bad command line format\r\n"),
+                }
+            }
+            _ => return writer.write_all(b"CLIENT_ERROR bad command line format\r\n"),
+        };
+
+        // the length is the client's word, nothing is allocated for a block
+        // over the limit. the client hears so right away and its bytes are
+        // skipped as they arrive
+        let Some(block_len) = len.checked_add(2).filter(|_| len <= self.max_item) else {
+            writer.write_all(b"SERVER_ERROR object too large for cache\r\n")?;
+            writer.flush()?;
+            io::copy(
+                &mut io::Read::take(&mut *reader, len.saturating_add(2) as u64),
+                &mut io::sink(),
+            )?;
+            return Ok(());
+        };
+        let mut block = vec![0; block_len];
+        reader.read_exact(&mut block)?;
+        if !block.ends_with(b"\r\n") {
+            // skip to the end of the oversized block
+            if block.last() != Some(&b'\n') {
+                reader.read_line(&mut String::new())?;
+            }
+            return writer.write_all(b"CLIENT_ERROR bad data chunk\r\n");
+        }
+        block.truncate(len);
+
+        let item = Item {
+            flags,
+            data: block.into(),
+        };
+        match time_to_live(exptime) {
+            Ttl::Forever => {
+                self.cache.put(key.to_string(), item);
+            }
+            Ttl::For(ttl) => {
+                self.cache.put_with_ttl(key.to_string(), item, ttl);
+            }
+            Ttl::Expired => {
+                self.cache.remove(key);
+            }
+        }
+        reply(writer, args, "STORED")
+    }
+
+    fn delete<W: Write>(&self, args: &[&str], writer: &mut W) -> io::Result<()> {
+        let (&[key] | &[key, "noreply"]) = args else {
+            return writer.write_all(b"CLIENT_ERROR bad command line format\r\n");
+        };
+        let outcome = if self.cache.remove(key).is_some() {
+            "DELETED"
+        } else {
+            "NOT_FOUND"
+        };
+        reply(writer, args, outcome)
+    }
+
+    fn stats<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let stats = self.cache.stats();
+        let lines = [
+            ("pid", u64::from(std::process::id())),
+            ("uptime", self.started.elapsed().as_secs()),
+            ("curr_items", self.cache.len() as u64),
+            ("total_items", stats.insertions),
+            ("bytes", self.cache.weight()),
+            ("limit_maxbytes", self.cache.max_weight()),
+            ("cmd_get", stats.hits + stats.misses),
+            ("get_hits", stats.hits),
+            ("get_misses", stats.misses),
+            ("evictions", stats.evictions),
+            ("expired_unfetched", stats.expirations),
+        ];
+        for (name, value) in lines {
+            write!(writer, "STAT {name} {value}\r\n")?;
+        }
+        writer.write_all(b"END\r\n")
+    }
+}
+
+enum Ttl {
+    Forever,
+    For(Duration),
+    Expired,
+}
+
+// 0 never expires, negative is already expired, up to 30 days is relative
+// and anything larger is an absolute unix time
+fn time_to_live(exptime: i64) -> Ttl {
+    let seconds = match exptime {
+        0 => return Ttl::Forever,
+        ..0 => return Ttl::Expired,
+        1..=RELATIVE_LIMIT => exptime,
+        _ => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs() as i64);
+            exptime - now
+        }
+    };
+    if seconds > 0 {
+        Ttl::For(Duration::from_secs(seconds as u64))
+    } else {
+        Ttl::Expired
+    }
+}
+
+fn valid_key(key: &str) -> bool {
+    key.len() <= MAX_KEY && !key.bytes().any(|b| b.is_ascii_control())
+}
+
+// nothing is written back when the command asked for noreply
+fn reply<W: Write>(writer: &mut W, args: &[&str], outcome: &str) -> io::Result<()> {
+    if args.last() == Some(&"noreply") {
+        return Ok(());
+    }
+    write!(writer, "{outcome}\r\n")
+}
+
+fn main() -> io::Result<()> {
+    let mut listen = "127.0.0.1:11211".to_string();
+    let mut max_bytes: u64 = 64 * 1024 * 1024;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next();
+        match (arg.as_str(), value) {
+            ("--listen", Some(addr)) => listen = addr,
+            ("--max-bytes", Some(bytes)) => {
+                max_bytes = bytes.parse().map_err(|_| usage())?;
+            }
+            _ => return Err(usage()),
+        }
+    }
+
+    let server = Arc::new(Server::new(max_bytes));
+    let listener = TcpListener::bind(&listen)?;
+    eprintln!("lru-memcached listening on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("accept failed: {err}");
+                continue;
+            }
+        };
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            let reader = match stream.try_clone() {
+                Ok(reader) => BufReader::new(reader),
+                Err(_) => return,
+            };
+            // a client hanging up mid-command only ends its own connection
+            let _ = server.serve(reader, BufWriter::new(stream));
+        });
+    }
+    Ok(())
+}
+
+fn usage() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "usage: lru-memcached [--listen ADDR] [--max-bytes N]",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(server: &Server, input: &str) -> String {
+        let mut output = Vec::new();
+        server.serve(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn set_get_delete_round_trip() {
+        let server = Server::new(1024);
+
+        let output = session(
+            &server,
+            "set greeting 5 0 5\r\nhello\r\nget greeting missing\r\n\
+             delete greeting\r\ndelete greeting\r\nget greeting\r\n",
+        );
+
+        assert_eq!(
+            output,
+            "STORED\r\nVALUE greeting 5 5\r\nhello\r\nEND\r\n\
+             DELETED\r\nNOT_FOUND\r\nEND\r\n"
+        );
+    }
+
+    #[test]
+    fn noreply_flush_and_stats() {
+        let server = Server::new(1024);
+
+        let output = session(
+            &server,
+            "set a 0 0 1 noreply\r\nx\r\ngets a\r\nflush_all\r\nget a\r\nstats\r\nbogus\r\n",
+        );
+
+        assert!(output.starts_with("VALUE a 0 1 0\r\nx\r\nEND\r\nOK\r\nEND\r\n"));
+        assert!(output.contains("STAT get_hits 1\r\n"));
+        assert!(output.contains("STAT curr_items 0\r\n"));
+        assert!(output.ends_with("END\r\nERROR\r\n"));
+    }
+
+    #[test]
+    fn malformed_sets_are_rejected() {
+        let server = Server::new(1024);
+
+        let output = session(&server, "set k 0 0 2\r\nabc\r\nset k x 0 1\r\n");
+
+        assert_eq!(
+            output,
+            "CLIENT_ERROR bad data chunk\r\nCLIENT_ERROR bad command line format\r\n"
+        );
+        assert!(server.cache.is_empty());
+    }
+
+    #[test]
+    fn oversized_sets_are_refused_without_allocating() {
+        let server = Server::new(1024);
+        let block = "x".repeat(2000);
+
+        let output = session(
+            &server,
+            &format!(
+                "set big 0 0 2000\r\n{block}\r\nget big\r\nset k 0 0 {}\r\n",
+                usize::MAX
+            ),
+        );
+
+        assert_eq!(
+            output,
+            "SERVER_ERROR object too large for cache\r\nEND\r\n\
+             SERVER_ERROR object too large for cache\r\n"
+        );
+        assert!(server.cache.is_empty());
+    }
+}
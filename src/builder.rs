@@ -0,0 +1,1203 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::io::Write;
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+#[cfg(feature = "sled")]
+use crate::SledStore;
+use crate::doorkeeper::Doorkeeper;
+use crate::hot::HotKeys;
+use crate::lock::RwLock;
+use crate::mrc::HitRateCurve;
+use crate::policy::Ghosts;
+use crate::refresh::Refresh;
+use crate::sharded::split_capacity;
+use crate::stats::{HitRateWindow, LockWaits, Window};
+use crate::store::{Backlog, WriteBehind};
+use crate::trace::TraceWriter;
+use crate::tuning::Tuner;
+use crate::{
+    CacheLoader, CacheState, CacheStore, Clock, EvictionListener, EvictionPolicy, Listener,
+    LoadingCache, LruCache, RemovalCause, ShardedLruCache, UNBOUNDED, Weigher, janitor,
+};
+
+// step by step construction of an `LruCache`, obtained from
+// `LruCache::builder`. nothing is checked until `build`
+pub struct CacheBuilder<K, V, S = RandomState> {
+    capacity: usize,
+    max_weight: Option<u64>,
+    weigher: Option<Weigher<K, V>>,
+    cost: Option<Weigher<K, V>>,
+    backing: Option<Arc<dyn CacheStore<K, V>>>,
+    // the write-behind queue's spill, when `backing` is one
+    backlog: Option<Arc<Backlog<K, V>>>,
+    max_entry_weight: Option<u64>,
+    on_reject: Option<Listener<K, V>>,
+    listener: Option<EvictionListener<K, V>>,
+    time_to_live: Option<Duration>,
+    time_to_idle: Option<Duration>,
+    on_expire: Option<Listener<K, V>>,
+    // sweep interval, the janitor is only started by `build`
+    janitor: Option<Duration>,
+    clock: Option<Arc<dyn Clock>>,
+    ttl_jitter: u32,
+    refresh: Option<RefreshStarter<K, V>>,
+    grace: Duration,
+    hit_rate_window: Option<HitRateWindow>,
+    doorkeeper: Option<usize>,
+    ghosts: Option<usize>,
+    auto_capacity: Option<(usize, usize)>,
+    hot_keys: Option<HotKeys<K>>,
+    hit_curve_rate: Option<f64>,
+    trace: Option<Box<dyn Write + Send + Sync>>,
+    policy: Option<Box<dyn EvictionPolicy>>,
+    #[cfg(feature = "metrics")]
+    name: Option<String>,
+    hasher: S,
+    _marker: PhantomData<fn(K, V)>,
+}
+
+// deferred until `build`, so a discarded builder never starts a thread
+type RefreshStarter<K, V> = Box<dyn FnOnce() -> Refresh<K, V> + Send>;
+
+// rejected builder configurations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    // a weigher was set but nothing bounds the total weight
+    WeigherWithoutMaxWeight,
+    // a weight budget was set but entries have no weight to count against it
+    MaxWeightWithoutWeigher,
+    // a per-entry limit was set but entries have no weight to check
+    MaxEntryWeightWithoutWeigher,
+    // ttl jitter is a percentage and can not exceed 100
+    JitterOutOfRange,
+    // a hit rate window needs room for at least one lookup
+    EmptyHitRateWindow,
+    // the hit rate curve samples a fraction of the keys, above 0 and at
+    // most 1
+    SampleRateOutOfRange,
+    // a doorkeeper has to remember at least one key
+    EmptyDoorkeeperWindow,
+    // auto capacity bounds with the minimum above the maximum
+    InvertedCapacityBounds,
+    // an option `build_sharded` can not split across shards, by method name
+    NotShardable(&'static str),
+    // `build_sharded` asked for zero shards
+    NoShards,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::WeigherWithoutMaxWeight => f.write_str("weigher set without a max_weight"),
+            BuildError::MaxWeightWithoutWeigher => f.write_str("max_weight set without a weigher"),
+            BuildError::MaxEntryWeightWithoutWeigher => {
+                f.write_str("max_entry_weight set without a weigher")
+            }
+            BuildError::JitterOutOfRange => f.write_str("ttl_jitter above 100 percent"),
+            BuildError::EmptyHitRateWindow => f.write_str("hit_rate_window of zero length"),
+            BuildError::SampleRateOutOfRange => {
+                f.write_str("hit rate curve sample rate outside (0, 1]")
+            }
+            BuildError::EmptyDoorkeeperWindow => f.write_str("doorkeeper window of zero keys"),
+            BuildError::InvertedCapacityBounds => {
+                f.write_str("auto_capacity minimum above the maximum")
+            }
+            BuildError::NotShardable(option) => write!(f, "{option} can not be sharded"),
+            BuildError::NoShards => f.write_str("build_sharded with zero shards"),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+impl<K, V> CacheBuilder<K, V> {
+    pub fn new() -> Self {
+        Self {
+            capacity: UNBOUNDED,
+            max_weight: None,
+            weigher: None,
+            cost: None,
+            backing: None,
+            backlog: None,
+            max_entry_weight: None,
+            on_reject: None,
+            listener: None,
+            time_to_live: None,
+            time_to_idle: None,
+            on_expire: None,
+            janitor: None,
+            clock: None,
+            ttl_jitter: 0,
+            refresh: None,
+            grace: Duration::ZERO,
+            hit_rate_window: None,
+            doorkeeper: None,
+            ghosts: None,
+            auto_capacity: None,
+            hot_keys: None,
+            hit_curve_rate: None,
+            trace: None,
+            policy: None,
+            #[cfg(feature = "metrics")]
+            name: None,
+            hasher: RandomState::new(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V> Default for CacheBuilder<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> CacheBuilder<K, V, S> {
+    // upper bound on the number of entries, unbounded unless set
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    // picks which entry goes when the cache is full, least recently used
+    // unless set. `pop_lru` and the iteration order stay by recency either
+    // way
+    pub fn eviction_policy<P: EvictionPolicy + 'static>(mut self, policy: P) -> Self {
+        self.policy = Some(Box::new(policy));
+        self
+    }
+
+    // upper bound on the summed weight of all entries, needs a weigher
+    pub fn max_weight(mut self, max_weight: u64) -> Self {
+        self.max_weight = Some(max_weight);
+        self
+    }
+
+    // sizes each entry, e.g. by its byte length. called on insert and after
+    // every in-place update, so it should be cheap
+    pub fn weigher<F>(mut self, weigher: F) -> Self
+    where
+        F: Fn(&K, &V) -> u64 + Send + Sync + 'static,
+    {
+        self.weigher = Some(Box::new(weigher));
+        self
+    }
+
+    // how expensive each entry is to recompute, for cost aware policies such
+    // as `Gdsf`. asked along with the weigher, entries cost 1 without it
+    pub fn cost<F>(mut self, cost: F) -> Self
+    where
+        F: Fn(&K, &V) -> u64 + Send + Sync + 'static,
+    {
+        self.cost = Some(Box::new(cost));
+        self
+    }
+
+    // front `store` with the cache: every value put, inserted or changed in
+    // place is written to it and every key removed by name deleted from it,
+    // see `CacheStore`. this happens synchronously under the cache lock,
+    // values changed through an entry are written when the entry is dropped
+    pub fn write_through<T>(mut self, store: T) -> Self
+    where
+        T: CacheStore<K, V> + 'static,
+    {
+        self.backing = Some(Arc::new(store));
+        self.backlog = None;
+        self
+    }
+
+    // entries weighing more than this are refused instead of evicting the
+    // rest of the cache to make room for them
+    pub fn max_entry_weight(mut self, max_entry_weight: u64) -> Self {
+        self.max_entry_weight = Some(max_entry_weight);
+        self
+    }
+
+    // called with every entry refused for its weight. runs under the cache
+    // lock, so it must not call back into the cache
+    pub fn on_reject<F>(mut self, on_reject: F) -> Self
+    where
+        F: Fn(K, V) + Send + Sync + 'static,
+    {
+        self.on_reject = Some(Box::new(on_reject));
+        self
+    }
+
+    // called for every entry leaving the cache, with the reason why. entries
+    // handed back by `drain` or by consuming the cache are not reported.
+    // runs under the cache lock, so it must not call back into the cache
+    pub fn eviction_listener<F>(mut self, listener: F) -> Self
+    where
+        F: Fn(&K, &V, RemovalCause) + Send + Sync + 'static,
+    {
+        self.listener = Some(Box::new(listener));
+        self
+    }
+
+    // runs `listener` after any already set, for wrappers that need to see
+    // removals without taking the caller's listener away
+    pub(crate) fn chain_listener<F>(mut self, listener: F) -> Self
+    where
+        K: 'static,
+        V: 'static,
+        F: Fn(&K, &V, RemovalCause) + Send + Sync + 'static,
+    {
+        self.listener = Some(match self.listener.take() {
+            Some(first) => Box::new(move |key, value, cause| {
+                first(key, value, cause);
+                listener(key, value, cause);
+            }),
+            None => Box::new(listener),
+        });
+        self
+    }
+
+    // every write starts the entry's lifetime over, afterwards it reads as
+    // missing. `put_with_ttl` overrides it per entry
+    pub fn time_to_live(mut self, ttl: Duration) -> Self {
+        self.time_to_live = Some(ttl);
+        self
+    }
+
+    // entries that go unused this long expire, independently of
+    // `time_to_live`. reads and writes count as a use, `peek` and
+    // `contains_key` do not
+    pub fn time_to_idle(mut self, tti: Duration) -> Self {
+        self.time_to_idle = Some(tti);
+        self
+    }
+
+    // called with every entry removed for having expired, whether it was
+    // found on access or swept by the janitor. runs under the cache lock, so
+    // it must not call back into the cache
+    pub fn on_expire<F>(mut self, on_expire: F) -> Self
+    where
+        F: Fn(K, V) + Send + Sync + 'static,
+    {
+        self.on_expire = Some(Box::new(on_expire));
+        self
+    }
+
+    // every ttl, the default one as well as those given to `put_with_ttl`,
+    // is scaled by a random factor of up to `percent` either way. entries
+    // inserted in bulk then expire spread out instead of all at once
+    pub fn ttl_jitter(mut self, percent: u32) -> Self {
+        self.ttl_jitter = percent;
+        self
+    }
+
+    // expired entries stay around this much longer for `get_stale_ok`,
+    // every other lookup treats them as missing right away
+    pub fn grace_period(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    // also track the hit rate over recent lookups, reported through
+    // `CacheStats::recent_hit_rate`. a time window goes by the cache clock
+    pub fn hit_rate_window(mut self, window: HitRateWindow) -> Self {
+        self.hit_rate_window = Some(window);
+        self
+    }
+
+    // labels everything the cache reports through the `metrics` facade
+    // with `cache=<name>`
+    #[cfg(feature = "metrics")]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    // estimate the hit rate at other capacities for `hit_rate_curve`, from
+    // the lookups of a `rate` fraction of the keys. the sample tracks every
+    // sampled key ever looked up, so 0.01 or below suits large key spaces
+    pub fn sample_hit_rate_curve(mut self, rate: f64) -> Self {
+        self.hit_curve_rate = Some(rate);
+        self
+    }
+
+    // a put that would evict turns a new key away the first time it is
+    // seen and only admits it when it comes back among the next `window`
+    // new keys, so one-off keys cannot push out reused ones. a cache with
+    // room admits everything, and `get_or_insert_with` and the entry API
+    // always store what they hand back
+    pub fn doorkeeper(mut self, window: usize) -> Self {
+        self.doorkeeper = Some(window);
+        self
+    }
+
+    // remember the hashes of the last `entries` keys evicted for room and
+    // count misses on them as `CacheStats::ghost_hits`. a ghost count close
+    // to the misses says a bigger cache would help. about 50 bytes a ghost
+    pub fn track_ghosts(mut self, entries: usize) -> Self {
+        self.ghosts = Some(entries);
+        self
+    }
+
+    // let the cache pick its own capacity within `min..=max`, starting from
+    // the configured one. every thousand lookups it grows when many misses
+    // were on recently evicted keys and shrinks while there are hardly any,
+    // see `Solution.md`. tracks `max` ghosts unless `track_ghosts` says
+    // otherwise. a shrink is enforced by the following inserts
+    pub fn auto_capacity(mut self, min: usize, max: usize) -> Self {
+        self.auto_capacity = Some((min, max));
+        self
+    }
+
+    // record every get and put to `out`, for `replay`-ing against other
+    // configurations later. records are 9 bytes each and buffered, see
+    // `LruCache::flush_trace`
+    pub fn record_trace<W: Write + Send + Sync + 'static>(mut self, out: W) -> Self {
+        self.trace = Some(Box::new(out));
+        self
+    }
+
+    // starts a thread that removes expired entries every `interval`, so they
+    // do not hold on to memory until evicted. the thread ends with the
+    // cache
+    pub fn janitor(mut self, interval: Duration) -> Self {
+        self.janitor = Some(interval);
+        self
+    }
+
+    // time source for expiry, the system clock unless set. a `MockClock`
+    // makes ttl and idle expiry testable without sleeping
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
+    pub fn hasher<T>(self, hasher: T) -> CacheBuilder<K, V, T> {
+        CacheBuilder {
+            capacity: self.capacity,
+            max_weight: self.max_weight,
+            weigher: self.weigher,
+            cost: self.cost,
+            backing: self.backing,
+            backlog: self.backlog,
+            max_entry_weight: self.max_entry_weight,
+            on_reject: self.on_reject,
+            listener: self.listener,
+            time_to_live: self.time_to_live,
+            time_to_idle: self.time_to_idle,
+            on_expire: self.on_expire,
+            janitor: self.janitor,
+            clock: self.clock,
+            ttl_jitter: self.ttl_jitter,
+            refresh: self.refresh,
+            grace: self.grace,
+            hit_rate_window: self.hit_rate_window,
+            doorkeeper: self.doorkeeper,
+            ghosts: self.ghosts,
+            auto_capacity: self.auto_capacity,
+            hot_keys: self.hot_keys,
+            hit_curve_rate: self.hit_curve_rate,
+            trace: self.trace,
+            policy: self.policy,
+            #[cfg(feature = "metrics")]
+            name: self.name,
+            hasher,
+            _marker: PhantomData,
+        }
+    }
+
+    // the janitor thread shares the cache, hence the bounds
+    pub fn build(self) -> Result<LruCache<K, V, S>, BuildError>
+    where
+        K: Eq + Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        S: BuildHasher + Send + Sync + 'static,
+    {
+        let max_weight = match (&self.weigher, self.max_weight) {
+            (Some(_), Some(max_weight)) => max_weight,
+            (None, None) => u64::MAX,
+            (Some(_), None) => return Err(BuildError::WeigherWithoutMaxWeight),
+            (None, Some(_)) => return Err(BuildError::MaxWeightWithoutWeigher),
+        };
+        if self.max_entry_weight.is_some() && self.weigher.is_none() {
+            return Err(BuildError::MaxEntryWeightWithoutWeigher);
+        }
+        if self.ttl_jitter > 100 {
+            return Err(BuildError::JitterOutOfRange);
+        }
+        if matches!(
+            self.hit_rate_window,
+            Some(HitRateWindow::Samples(0) | HitRateWindow::Duration(Duration::ZERO))
+        ) {
+            return Err(BuildError::EmptyHitRateWindow);
+        }
+        if self
+            .hit_curve_rate
+            .is_some_and(|rate| !(rate > 0.0 && rate <= 1.0))
+        {
+            return Err(BuildError::SampleRateOutOfRange);
+        }
+        if self.doorkeeper == Some(0) {
+            return Err(BuildError::EmptyDoorkeeperWindow);
+        }
+        if self.auto_capacity.is_some_and(|(min, max)| min > max) {
+            return Err(BuildError::InvertedCapacityBounds);
+        }
+        let capacity = match self.auto_capacity {
+            Some((min, max)) => self.capacity.clamp(min, max),
+            None => self.capacity,
+        };
+
+        let mut state = CacheState::with_capacity_and_hasher(capacity, self.hasher);
+        state.weigher = self.weigher;
+        state.cost = self.cost;
+        state.backing = self.backing;
+        state.max_weight = max_weight;
+        state.max_entry_weight = self.max_entry_weight.unwrap_or(u64::MAX);
+        state.on_reject = self.on_reject;
+        state.listener = self.listener;
+        state.time_to_live = self.time_to_live;
+        state.time_to_idle = self.time_to_idle;
+        state.on_expire = self.on_expire;
+        if let Some(clock) = self.clock {
+            state.clock = clock;
+        }
+        state.ttl_jitter = self.ttl_jitter;
+        state.refresh = self.refresh.map(|start| start());
+        state.grace = self.grace;
+        state.jitter_seed = AtomicU64::new(RandomState::new().hash_one(0u64));
+        #[cfg(feature = "metrics")]
+        {
+            state.stats.name = self.name;
+        }
+        state.hot_keys = self.hot_keys;
+        state.hit_curve = self.hit_curve_rate.map(HitRateCurve::new);
+        state.trace = self.trace.map(TraceWriter::new);
+        state.policy = self.policy;
+        state.resize(capacity);
+        state.doorkeeper = self.doorkeeper.map(Doorkeeper::new);
+        let ghosts = self.ghosts.or(self.auto_capacity.map(|(_, max)| max));
+        state.ghosts = ghosts.map(|len| (Ghosts::default(), len));
+        state.tuner = self.auto_capacity.map(|(min, max)| Tuner::new(min, max));
+        state.stats.window = self
+            .hit_rate_window
+            .map(|window| Window::new(window, Arc::clone(&state.clock)));
+
+        let inner = Arc::new(RwLock::new(state));
+        if let Some(interval) = self.janitor {
+            inner.write().janitor = Some(janitor::spawn(&inner, interval));
+        }
+
+        Ok(LruCache {
+            inner,
+            group: OnceLock::new(),
+            waits: LockWaits::default(),
+            loads: Mutex::new(HashMap::new()),
+            backlog: self.backlog,
+        })
+    }
+}
+
+// the closures set on the builder are shared by every shard
+impl<K: Eq + Hash + 'static, V: Clone + 'static, S: BuildHasher + Clone> CacheBuilder<K, V, S> {
+    // a ShardedLruCache whose shards each get this configuration. capacity
+    // and max_weight are split between them like `ShardedLruCache::new`
+    // splits capacity, weighers and listeners are shared and a janitor runs
+    // per shard. options keeping state for the whole cache are refused
+    pub fn build_sharded(self, shards: usize) -> Result<ShardedLruCache<K, V, S>, BuildError>
+    where
+        K: Send + Sync,
+        V: Send + Sync,
+        S: Send + Sync + 'static,
+    {
+        if shards == 0 {
+            return Err(BuildError::NoShards);
+        }
+        let unshardable = [
+            (self.refresh.is_some(), "refresh_after_write"),
+            (self.trace.is_some(), "record_trace"),
+            (self.policy.is_some(), "eviction_policy"),
+            (self.hot_keys.is_some(), "track_hot_keys"),
+            (self.auto_capacity.is_some(), "auto_capacity"),
+            (self.hit_curve_rate.is_some(), "sample_hit_rate_curve"),
+            #[cfg(feature = "metrics")]
+            (self.name.is_some(), "name"),
+        ];
+        if let Some(&(_, option)) = unshardable.iter().find(|(set, _)| *set) {
+            return Err(BuildError::NotShardable(option));
+        }
+
+        let shards = shards.min(self.capacity).max(1);
+        let capacities: Vec<usize> = if self.capacity == UNBOUNDED {
+            vec![UNBOUNDED; shards]
+        } else {
+            split_capacity(self.capacity, shards).collect()
+        };
+        let n = shards as u64;
+        let weights: Vec<Option<u64>> = (0..n)
+            .map(|i| self.max_weight.map(|max| max / n + u64::from(i < max % n)))
+            .collect();
+
+        let weigher = self.weigher.map(Arc::new);
+        let cost = self.cost.map(Arc::new);
+        let on_reject = self.on_reject.map(Arc::new);
+        let listener = self.listener.map(Arc::new);
+        let on_expire = self.on_expire.map(Arc::new);
+
+        let built = capacities
+            .into_iter()
+            .zip(weights)
+            .map(|(capacity, max_weight)| {
+                CacheBuilder {
+                    capacity,
+                    max_weight,
+                    weigher: weigher.as_ref().map(shared_weigher),
+                    cost: cost.as_ref().map(shared_weigher),
+                    backing: self.backing.clone(),
+                    backlog: self.backlog.clone(),
+                    max_entry_weight: self.max_entry_weight,
+                    on_reject: on_reject.as_ref().map(shared_listener),
+                    listener: listener.as_ref().map(|listener| {
+                        let listener = Arc::clone(listener);
+                        Box::new(move |key: &K, value: &V, cause| listener(key, value, cause))
+                            as EvictionListener<K, V>
+                    }),
+                    time_to_live: self.time_to_live,
+                    time_to_idle: self.time_to_idle,
+                    on_expire: on_expire.as_ref().map(shared_listener),
+                    janitor: self.janitor,
+                    clock: self.clock.clone(),
+                    ttl_jitter: self.ttl_jitter,
+                    refresh: None,
+                    grace: self.grace,
+                    hit_rate_window: self.hit_rate_window,
+                    doorkeeper: self.doorkeeper,
+                    ghosts: self.ghosts,
+                    auto_capacity: None,
+                    hot_keys: None,
+                    hit_curve_rate: None,
+                    trace: None,
+                    policy: None,
+                    #[cfg(feature = "metrics")]
+                    name: None,
+                    hasher: self.hasher.clone(),
+                    _marker: PhantomData,
+                }
+                .build()
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(ShardedLruCache::from_shards(built, self.hasher))
+    }
+}
+
+fn shared_weigher<K, V>(weigher: &Arc<Weigher<K, V>>) -> Weigher<K, V>
+where
+    K: 'static,
+    V: 'static,
+{
+    let weigher = Arc::clone(weigher);
+    Box::new(move |key, value| weigher(key, value))
+}
+
+fn shared_listener<K, V>(listener: &Arc<Listener<K, V>>) -> Listener<K, V>
+where
+    K: 'static,
+    V: 'static,
+{
+    let listener = Arc::clone(listener);
+    Box::new(move |key, value| listener(key, value))
+}
+
+// tracked keys are copied out of the cache
+impl<K: Eq + Clone, V, S> CacheBuilder<K, V, S> {
+    // count the `capacity` most hit or inserted keys for `hottest`, a
+    // capacity a few times the number of keys of interest makes the counts
+    // tighter. costs a scan of the tracker whenever a new key is seen
+    pub fn track_hot_keys(mut self, capacity: usize) -> Self {
+        self.hot_keys = Some(HotKeys::new(capacity, K::clone));
+        self
+    }
+}
+
+// a read-through cache clones missing keys into the cache
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> CacheBuilder<K, V, S> {
+    // builds a cache whose `get` calls `loader` for keys it does not hold
+    // and caches what comes back
+    pub fn build_loading<L>(self, loader: L) -> Result<LoadingCache<K, V, S>, BuildError>
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        S: Send + Sync + 'static,
+        L: CacheLoader<K, V> + 'static,
+    {
+        Ok(LoadingCache::new(self.build()?, Box::new(loader)))
+    }
+}
+
+// keys and values are stored in sled as their bytes
+#[cfg(feature = "sled")]
+impl<K, V, S> CacheBuilder<K, V, S>
+where
+    K: AsRef<[u8]> + 'static,
+    V: AsRef<[u8]> + 'static,
+{
+    // writes through to a sled tree, durable once sled has synced it or
+    // `LruCache::flush` has returned
+    pub fn persist_to(self, tree: sled::Tree) -> Self {
+        self.write_through(SledStore::new(tree))
+    }
+}
+
+// write-behind copies what it queues and flushes on its own thread
+impl<K, V, S> CacheBuilder<K, V, S>
+where
+    K: Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    // like `write_through`, but puts and removes only queue the change and
+    // return. a worker thread hands queued changes to `store` in order,
+    // batched every `interval` or once `queue` of them are waiting, and a
+    // put finding `queue` changes not yet picked up waits for the worker
+    // once it has released the cache lock, so other callers are not held up.
+    // call `LruCache::flush` before shutting down, changes still queued when
+    // the process exits are lost
+    pub fn write_behind<T>(mut self, store: T, interval: Duration, queue: usize) -> Self
+    where
+        T: CacheStore<K, V> + 'static,
+    {
+        let write_behind = WriteBehind::start(store, interval, queue);
+        self.backlog = Some(write_behind.backlog());
+        self.backing = Some(Arc::new(write_behind));
+        self
+    }
+}
+
+// reloads run on their own thread and hand back owned keys
+impl<K, V, S> CacheBuilder<K, V, S>
+where
+    K: Clone + Send + 'static,
+    V: Send + 'static,
+{
+    // an entry used once it is older than `after` gets reloaded through
+    // `loader` on a background thread, while the current value keeps being
+    // served. reloads run one at a time, a loader returning None keeps the
+    // old value and the entry is retried on its next use
+    pub fn refresh_after_write<F>(mut self, after: Duration, loader: F) -> Self
+    where
+        F: Fn(&K) -> Option<V> + Send + 'static,
+    {
+        self.refresh = Some(Box::new(move || Refresh::start(after, loader)));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockClock;
+
+    #[test]
+    fn evicts_by_total_weight() {
+        let cache = LruCache::builder()
+            .weigher(|_: &u32, v: &String| v.len() as u64)
+            .max_weight(10)
+            .build()
+            .unwrap();
+
+        cache.put(1, "aaaa".to_string());
+        cache.put(2, "bbbb".to_string());
+        assert_eq!(cache.weight(), 8);
+
+        cache.put(3, "cccccc".to_string()); // only fits once 1 is gone
+        assert_eq!(cache.weight(), 10);
+        assert!(!cache.contains_key(&1));
+
+        cache.put(4, "ddddddddd".to_string());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.weight(), 9);
+    }
+
+    #[test]
+    fn in_place_growth_is_reweighed() {
+        let cache = LruCache::builder()
+            .weigher(|_: &u32, v: &Vec<u8>| v.len() as u64)
+            .max_weight(8)
+            .build()
+            .unwrap();
+
+        cache.put(1, vec![0; 3]);
+        cache.put(2, vec![0; 3]);
+        cache.with_value_mut(&2, |v| v.extend([0; 3]));
+
+        assert_eq!(cache.weight(), 6);
+        assert!(!cache.contains_key(&1));
+
+        cache.entry(2).and_modify(|v| v.truncate(1)).or_default();
+        assert_eq!(cache.weight(), 1);
+    }
+
+    #[test]
+    fn oversized_entries_are_rejected() {
+        use std::sync::{Arc, Mutex};
+
+        let rejected = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&rejected);
+        let cache = LruCache::builder()
+            .weigher(|_: &u32, v: &Vec<u8>| v.len() as u64)
+            .max_weight(100)
+            .max_entry_weight(10)
+            .on_reject(move |k, _| log.lock().unwrap().push(k))
+            .build()
+            .unwrap();
+
+        cache.put(1, vec![0; 5]);
+        cache.put(2, vec![0; 50]);
+        cache.put(3, vec![0; 500]);
+        cache.entry(4).or_insert(vec![0; 20]);
+
+        // nothing else was pushed out to make room
+        assert!(cache.contains_key(&1));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.weight(), 5);
+        assert_eq!(*rejected.lock().unwrap(), vec![2, 3, 4]);
+
+        // growing past the limit in place rejects the entry too
+        cache.with_value_mut(&1, |v| v.resize(11, 0));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn time_to_live_applies_to_every_entry() {
+        let clock = MockClock::new();
+        let cache = LruCache::builder()
+            .capacity(8)
+            .time_to_live(Duration::from_secs(20))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        cache.put(1, "a");
+        cache.entry(2).or_insert("b");
+        cache.put_with_ttl(3, "c", Duration::from_secs(60));
+        assert_eq!(cache.len(), 3);
+
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [(3, "c")]);
+        assert_eq!(cache.into_iter().collect::<Vec<_>>(), [(3, "c")]);
+    }
+
+    #[test]
+    fn idle_entries_expire_while_used_ones_stay() {
+        let clock = MockClock::new();
+        let cache = LruCache::builder()
+            .capacity(8)
+            .time_to_idle(Duration::from_secs(60))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        cache.put("busy", 1);
+        cache.put("idle", 2);
+        for _ in 0..4 {
+            clock.advance(Duration::from_secs(20));
+            assert_eq!(cache.get(&"busy"), Some(1));
+        }
+
+        assert_eq!(cache.get(&"idle"), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn jitter_spreads_expiries() {
+        let clock = MockClock::new();
+        let cache = LruCache::builder()
+            .time_to_live(Duration::from_secs(100))
+            .ttl_jitter(50)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        cache.put_many((0..200).map(|i| (i, i)));
+
+        clock.advance(Duration::from_secs(49));
+        assert_eq!(cache.len(), 200);
+        clock.advance(Duration::from_secs(51));
+        let left = cache.len();
+        assert!(left > 0 && left < 200, "{left} entries left");
+        clock.advance(Duration::from_secs(51));
+        assert_eq!(cache.len(), 0);
+
+        let res = CacheBuilder::<u32, u32>::new().ttl_jitter(101).build();
+        assert_eq!(res.err(), Some(BuildError::JitterOutOfRange));
+    }
+
+    #[test]
+    fn stale_entries_are_reloaded_in_the_background() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let clock = MockClock::new();
+        let loads = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&loads);
+        let cache = LruCache::builder()
+            .refresh_after_write(Duration::from_secs(10), move |k: &u32| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Some(k * 10)
+            })
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        cache.put(1, 1);
+        assert_eq!(cache.get(&1), Some(1));
+        clock.advance(Duration::from_secs(11));
+
+        // the stale value is served while the reload runs
+        assert_eq!(cache.get(&1), Some(1));
+        let mut value = cache.get(&1);
+        for _ in 0..200 {
+            if value == Some(10) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+            value = cache.get(&1);
+        }
+
+        assert_eq!(value, Some(10));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn stale_reads_elect_a_single_refresher() {
+        use crate::MaybeStale;
+
+        let clock = MockClock::new();
+        let cache = LruCache::builder()
+            .time_to_live(Duration::from_secs(10))
+            .grace_period(Duration::from_secs(5))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        cache.put(1, "old");
+        assert_eq!(cache.get_stale_ok(&1), Some(MaybeStale::Fresh("old")));
+
+        clock.advance(Duration::from_secs(12));
+        assert_eq!(cache.get(&1), None);
+        let stale = |refresh| {
+            Some(MaybeStale::Stale {
+                value: "old",
+                refresh,
+            })
+        };
+        assert_eq!(cache.get_stale_ok(&1), stale(true));
+        assert_eq!(cache.get_stale_ok(&1), stale(false));
+
+        cache.put(1, "new");
+        assert_eq!(cache.get_stale_ok(&1), Some(MaybeStale::Fresh("new")));
+
+        clock.advance(Duration::from_secs(16));
+        assert_eq!(cache.get_stale_ok(&1), None);
+    }
+
+    #[test]
+    fn eviction_listener_sees_every_cause() {
+        use std::sync::Mutex;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        let clock = MockClock::new();
+        let cache = LruCache::builder()
+            .capacity(2)
+            .eviction_listener(move |k: &u32, v: &&str, cause| {
+                log.lock().unwrap().push((*k, *v, cause))
+            })
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        cache.put(1, "a");
+        cache.put(1, "b");
+        cache.put(2, "c");
+        cache.put(3, "d");
+        cache.remove(&2);
+        cache.put_with_ttl(4, "e", Duration::from_secs(1));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cache.get(&4), None);
+        cache.clear();
+
+        use RemovalCause::*;
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                (1, "a", Replaced),
+                (1, "b", CapacityEvicted),
+                (2, "c", Explicit),
+                (4, "e", Expired),
+                (3, "d", Explicit),
+            ]
+        );
+    }
+
+    #[test]
+    fn janitor_sweeps_expired_entries() {
+        use std::sync::Mutex;
+
+        let expired = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&expired);
+        let cache = LruCache::builder()
+            .capacity(8)
+            .time_to_live(Duration::from_millis(10))
+            .on_expire(move |k, _| seen.lock().unwrap().push(k))
+            .janitor(Duration::from_millis(5))
+            .build()
+            .unwrap();
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        std::thread::sleep(Duration::from_millis(100));
+
+        // gone without being looked up
+        assert!(cache.inner.read().map.is_empty());
+        let mut expired = expired.lock().unwrap().clone();
+        expired.sort();
+        assert_eq!(expired, [1, 2]);
+    }
+
+    #[test]
+    fn rejects_mismatched_weight_config() {
+        let res = CacheBuilder::<u32, u32>::new().weigher(|_, _| 1).build();
+        assert_eq!(res.err(), Some(BuildError::WeigherWithoutMaxWeight));
+
+        let res = CacheBuilder::<u32, u32>::new().max_weight(5).build();
+        assert_eq!(res.err(), Some(BuildError::MaxWeightWithoutWeigher));
+    }
+
+    #[test]
+    fn doorkeeper_admits_on_the_second_put() {
+        let cache = LruCache::builder()
+            .capacity(2)
+            .doorkeeper(100)
+            .build()
+            .unwrap();
+
+        // room left, nobody is turned away
+        cache.put(1, 1);
+        cache.put(2, 2);
+        cache.put(3, 3);
+        assert!(!cache.contains_key(&3));
+        assert_eq!(cache.len(), 2);
+
+        cache.put(3, 3);
+        assert!(cache.contains_key(&3));
+        assert!(!cache.contains_key(&1));
+
+        let res = CacheBuilder::<u32, u32>::new().doorkeeper(0).build();
+        assert_eq!(res.err(), Some(BuildError::EmptyDoorkeeperWindow));
+    }
+
+    #[test]
+    fn ghost_hits_count_misses_on_evicted_keys() {
+        let cache = LruCache::builder()
+            .capacity(2)
+            .track_ghosts(2)
+            .build()
+            .unwrap();
+
+        for key in 1..=4 {
+            cache.put(key, key);
+        }
+        // 1 and 2 were evicted, 9 was never cached, and a ghost only counts
+        // once
+        for key in [1, 2, 9, 1] {
+            cache.get(&key);
+        }
+
+        assert_eq!(cache.stats().ghost_hits, 2);
+        assert_eq!(cache.stats().misses, 4);
+    }
+
+    #[test]
+    fn recent_hit_rate_follows_the_window() {
+        let clock = MockClock::new();
+        let cache = LruCache::builder()
+            .capacity(4)
+            .clock(clock.clone())
+            .hit_rate_window(HitRateWindow::Duration(Duration::from_secs(60)))
+            .build()
+            .unwrap();
+
+        cache.put(1, 1);
+        for _ in 0..3 {
+            cache.get(&2);
+        }
+        clock.advance(Duration::from_secs(90));
+        cache.get(&1);
+
+        let stats = cache.stats();
+        assert_eq!(stats.hit_rate(), 0.25);
+        assert_eq!(stats.recent_hit_rate(), Some(1.0));
+
+        let res = CacheBuilder::<u32, u32>::new()
+            .hit_rate_window(HitRateWindow::Samples(0))
+            .build();
+        assert_eq!(res.err(), Some(BuildError::EmptyHitRateWindow));
+    }
+
+    #[test]
+    fn hottest_ranks_requested_keys() {
+        let cache = LruCache::builder()
+            .capacity(8)
+            .track_hot_keys(4)
+            .build()
+            .unwrap();
+
+        for i in 0..4 {
+            cache.put(i, i);
+        }
+        for _ in 0..5 {
+            cache.get(&2);
+        }
+        cache.get(&3);
+        cache.get(&9);
+
+        assert_eq!(cache.hottest(2), [(2, 6), (3, 2)]);
+
+        cache.reset_stats();
+        assert!(cache.hottest(2).is_empty());
+        assert!(LruCache::<u32, u32>::new(2).hottest(2).is_empty());
+    }
+
+    #[test]
+    fn hit_rate_curve_predicts_other_capacities() {
+        let cache = LruCache::builder()
+            .capacity(10)
+            .sample_hit_rate_curve(1.0)
+            .build()
+            .unwrap();
+
+        // a loop over 20 keys never hits at 10, every repeat would at 20
+        for _ in 0..5 {
+            for key in 0..20 {
+                cache.get_or_insert_with(key, || key);
+            }
+        }
+
+        assert_eq!(cache.stats().hits, 0);
+        assert_eq!(cache.hit_rate_curve(&[10, 20]), [(10, 0.0), (20, 0.8)]);
+
+        let res = CacheBuilder::<u32, u32>::new()
+            .sample_hit_rate_curve(0.0)
+            .build();
+        assert_eq!(res.err(), Some(BuildError::SampleRateOutOfRange));
+    }
+
+    #[test]
+    fn recorded_trace_replays_at_other_sizes() {
+        use crate::replay;
+        use std::sync::Mutex;
+
+        #[derive(Clone, Default)]
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let out = Shared::default();
+        let cache = LruCache::builder()
+            .capacity(2)
+            .record_trace(out.clone())
+            .build()
+            .unwrap();
+        for _ in 0..3 {
+            for key in 0..3 {
+                cache.get_or_insert_with(key, || key);
+            }
+        }
+        cache.flush_trace().unwrap();
+        assert_eq!(cache.stats().hits, 0);
+
+        let trace = out.0.lock().unwrap().clone();
+        // a header, then a get and a put for each of the nine lookups
+        assert_eq!(trace.len(), 9 + 18 * 9);
+        for (capacity, hits) in [(2, 0), (3, 6)] {
+            let replayed = LruCache::new(capacity);
+            assert_eq!(replay(&trace[..], &replayed).unwrap().hits, hits);
+        }
+    }
+
+    #[test]
+    fn sharded_builds_share_the_configuration() {
+        use std::sync::Mutex;
+
+        let evicted = Arc::new(Mutex::new(0));
+        let seen = Arc::clone(&evicted);
+        let clock = MockClock::new();
+        let cache = LruCache::builder()
+            .weigher(|_: &u32, value: &String| value.len() as u64)
+            .max_weight(40)
+            .time_to_live(Duration::from_secs(5))
+            .clock(clock.clone())
+            .eviction_listener(move |_, _, cause| {
+                if cause == RemovalCause::CapacityEvicted {
+                    *seen.lock().unwrap() += 1;
+                }
+            })
+            .build_sharded(4)
+            .unwrap();
+
+        for key in 0..20 {
+            cache.put(key, "abcde".to_string());
+        }
+        // each shard holds 10 of the 40, two entries of 5
+        assert!(cache.len() <= 8);
+        assert_eq!(*evicted.lock().unwrap(), 20 - cache.len());
+
+        clock.advance(Duration::from_secs(6));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn sharded_builds_refuse_whole_cache_options() {
+        let traced = LruCache::<u32, u32>::builder()
+            .record_trace(std::io::sink())
+            .build_sharded(4);
+        assert_eq!(traced.err(), Some(BuildError::NotShardable("record_trace")));
+
+        let invalid = LruCache::<u32, u32>::builder()
+            .max_weight(10)
+            .build_sharded(4);
+        assert_eq!(invalid.err(), Some(BuildError::MaxWeightWithoutWeigher));
+    }
+
+    #[test]
+    fn shard_counts_are_checked() {
+        let none = LruCache::<u32, u32>::builder().build_sharded(0);
+        assert_eq!(none.err(), Some(BuildError::NoShards));
+
+        let unbounded = LruCache::<u32, u32>::builder().build_sharded(4).unwrap();
+        assert_eq!(unbounded.capacity(), usize::MAX);
+    }
+
+    #[test]
+    fn janitor_may_come_before_the_hasher() {
+        let cache = LruCache::builder()
+            .time_to_live(Duration::from_millis(10))
+            .janitor(Duration::from_millis(5))
+            .hasher(RandomState::new())
+            .build()
+            .unwrap();
+
+        cache.put(1, "a");
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(cache.inner.read().map.is_empty());
+    }
+}
@@ -0,0 +1,302 @@
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use crate::{BuildError, LruCache, ShardedLruCache};
+
+// what application code needs from a cache, so it can be written once and
+// handed an LruCache, a ShardedLruCache or a NoopCache
+//
+// keys are taken by reference to the owned type and nothing is generic, so
+// the trait also works as `dyn Cache<K, V>`
+pub trait Cache<K, V> {
+    fn get(&self, key: &K) -> Option<V>;
+
+    // the previous value, if any
+    fn put(&self, key: K, value: V) -> Option<V>;
+
+    fn remove(&self, key: &K) -> Option<V>;
+
+    fn contains_key(&self, key: &K) -> bool;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn clear(&self);
+}
+
+impl<K: Eq + Hash, V: Clone, S: BuildHasher> Cache<K, V> for LruCache<K, V, S> {
+    fn get(&self, key: &K) -> Option<V> {
+        LruCache::get(self, key)
+    }
+
+    fn put(&self, key: K, value: V) -> Option<V> {
+        LruCache::put(self, key, value)
+    }
+
+    fn remove(&self, key: &K) -> Option<V> {
+        LruCache::remove(self, key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        LruCache::contains_key(self, key)
+    }
+
+    fn len(&self) -> usize {
+        LruCache::len(self)
+    }
+
+    fn clear(&self) {
+        LruCache::clear(self);
+    }
+}
+
+impl<K: Eq + Hash, V: Clone, S: BuildHasher + Clone> Cache<K, V> for ShardedLruCache<K, V, S> {
+    fn get(&self, key: &K) -> Option<V> {
+        ShardedLruCache::get(self, key)
+    }
+
+    fn put(&self, key: K, value: V) -> Option<V> {
+        ShardedLruCache::put(self, key, value)
+    }
+
+    fn remove(&self, key: &K) -> Option<V> {
+        ShardedLruCache::remove(self, key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        ShardedLruCache::contains_key(self, key)
+    }
+
+    fn len(&self) -> usize {
+        ShardedLruCache::len(self)
+    }
+
+    fn clear(&self) {
+        ShardedLruCache::clear(self);
+    }
+}
+
+// a cache picked at runtime, for code that holds whichever one the
+// configuration asked for
+pub type DynCache<K, V> = Box<dyn Cache<K, V> + Send + Sync>;
+
+// so a DynCache can be handed to code generic over `Cache`. not done for
+// every Box or Arc, that would shadow the inherent methods of an
+// Arc<LruCache>
+impl<K, V> Cache<K, V> for DynCache<K, V> {
+    fn get(&self, key: &K) -> Option<V> {
+        (**self).get(key)
+    }
+
+    fn put(&self, key: K, value: V) -> Option<V> {
+        (**self).put(key, value)
+    }
+
+    fn remove(&self, key: &K) -> Option<V> {
+        (**self).remove(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        (**self).contains_key(key)
+    }
+
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn clear(&self) {
+        (**self).clear();
+    }
+}
+
+// which cache to build, as read from a config file. with the serde feature
+// it deserializes from e.g. `{"kind": "sharded", "capacity": 10000, "shards": 16}`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "kind", rename_all = "snake_case")
+)]
+pub enum CacheConfig {
+    Lru { capacity: usize },
+    Sharded { capacity: usize, shards: usize },
+    // caching turned off
+    Noop,
+}
+
+impl CacheConfig {
+    // a config read from a file is not trusted to be valid, zero shards is
+    // an error rather than a panic
+    pub fn build<K, V>(self) -> Result<DynCache<K, V>, BuildError>
+    where
+        K: Eq + Hash + Send + Sync + 'static,
+        V: Clone + Send + Sync + 'static,
+    {
+        Ok(match self {
+            CacheConfig::Lru { capacity } => {
+                Box::new(LruCache::builder().capacity(capacity).build()?)
+            }
+            CacheConfig::Sharded { capacity, shards } => Box::new(
+                LruCache::builder()
+                    .capacity(capacity)
+                    .build_sharded(shards)?,
+            ),
+            CacheConfig::Noop => Box::new(NoopCache::new()),
+        })
+    }
+}
+
+// a cache that keeps nothing, every lookup misses and every value put is
+// dropped. for turning caching off in tests or by configuration
+pub struct NoopCache<K, V> {
+    _marker: PhantomData<fn(K) -> V>,
+}
+
+impl<K, V> NoopCache<K, V> {
+    pub fn new() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V> Default for NoopCache<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Cache<K, V> for NoopCache<K, V> {
+    fn get(&self, _: &K) -> Option<V> {
+        None
+    }
+
+    fn put(&self, _: K, _: V) -> Option<V> {
+        None
+    }
+
+    fn remove(&self, _: &K) -> Option<V> {
+        None
+    }
+
+    fn contains_key(&self, _: &K) -> bool {
+        false
+    }
+
+    fn len(&self) -> usize {
+        0
+    }
+
+    fn clear(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    // stands in for application code that caches a slow computation
+    fn square(cache: &impl Cache<u64, u64>, n: u64, computed: &Cell<u32>) -> u64 {
+        if let Some(hit) = cache.get(&n) {
+            return hit;
+        }
+        computed.set(computed.get() + 1);
+        cache.put(n, n * n);
+        n * n
+    }
+
+    #[test]
+    fn code_generic_over_caches_runs_with_each() {
+        let computed = Cell::new(0);
+
+        let cache = LruCache::new(4);
+        for _ in 0..3 {
+            assert_eq!(square(&cache, 3, &computed), 9);
+        }
+        assert_eq!(computed.get(), 1);
+
+        let cache = ShardedLruCache::new(16, 4);
+        for _ in 0..3 {
+            assert_eq!(square(&cache, 3, &computed), 9);
+        }
+        assert_eq!(computed.get(), 2);
+        assert_eq!(Cache::len(&cache), 1);
+    }
+
+    #[test]
+    fn noop_cache_always_misses() {
+        let cache = NoopCache::new();
+        let computed = Cell::new(0);
+
+        for _ in 0..3 {
+            assert_eq!(square(&cache, 4, &computed), 16);
+        }
+
+        assert_eq!(computed.get(), 3);
+        assert!(!cache.contains_key(&4));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn configured_caches_work_behind_one_type() {
+        let computed = Cell::new(0);
+        let caches: Vec<DynCache<u64, u64>> = [
+            CacheConfig::Lru { capacity: 4 },
+            CacheConfig::Sharded {
+                capacity: 16,
+                shards: 4,
+            },
+            CacheConfig::Noop,
+        ]
+        .into_iter()
+        .map(|config| config.build().unwrap())
+        .collect();
+
+        for cache in &caches {
+            square(cache, 5, &computed);
+            square(cache, 5, &computed);
+        }
+
+        // one computation each for the two real caches, two for the noop
+        assert_eq!(computed.get(), 4);
+        assert_eq!(
+            caches.iter().map(|cache| cache.len()).collect::<Vec<_>>(),
+            [1, 1, 0]
+        );
+    }
+
+    #[test]
+    fn degenerate_configs_do_not_panic() {
+        let empty = CacheConfig::Lru { capacity: 0 }
+            .build::<u64, u64>()
+            .unwrap();
+        empty.put(1, 1);
+        assert!(empty.is_empty());
+
+        let unsharded = CacheConfig::Sharded {
+            capacity: 0,
+            shards: 0,
+        }
+        .build::<u64, u64>();
+        assert_eq!(unsharded.err(), Some(BuildError::NoShards));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn config_reads_from_json() {
+        let config: CacheConfig =
+            serde_json::from_str(r#"{"kind": "sharded", "capacity": 64, "shards": 8}"#).unwrap();
+
+        assert_eq!(
+            config,
+            CacheConfig::Sharded {
+                capacity: 64,
+                shards: 8
+            }
+        );
+    }
+}
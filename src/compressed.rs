@@ -0,0 +1,174 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use crate::{BuildError, CacheBuilder, HeapSize, LruCache};
+
+// a value as a `CompressedCache` stores it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compressed {
+    bytes: Box<[u8]>,
+    // false for values below the threshold or that lz4 could not shrink
+    lz4: bool,
+}
+
+impl Compressed {
+    fn new(value: &[u8], threshold: usize) -> Self {
+        if value.len() >= threshold {
+            let packed = lz4_flex::compress_prepend_size(value);
+            if packed.len() < value.len() {
+                return Self {
+                    bytes: packed.into(),
+                    lz4: true,
+                };
+            }
+        }
+        Self {
+            bytes: value.into(),
+            lz4: false,
+        }
+    }
+
+    // bytes actually held, what the budget is charged
+    pub fn stored_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.lz4
+    }
+
+    fn unpack(&self) -> Vec<u8> {
+        if self.lz4 {
+            // only ever decodes what `new` encoded
+            lz4_flex::decompress_size_prepended(&self.bytes).expect("stored lz4 block is valid")
+        } else {
+            self.bytes.to_vec()
+        }
+    }
+}
+
+impl HeapSize for Compressed {
+    fn heap_size(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+// cache of byte values kept lz4 compressed, decompressed on every get
+//
+// values of at least `threshold` bytes are compressed, smaller ones and
+// those that do not shrink are kept as they are. the budget of `new` is in
+// stored bytes, so compressible payloads such as JSON or HTML fragments fit
+// several times more entries. everything but get and put derefs to the
+// underlying cache
+pub struct CompressedCache<K, V, S = RandomState> {
+    cache: LruCache<K, Compressed, S>,
+    threshold: usize,
+    _marker: PhantomData<fn() -> V>,
+}
+
+impl<K: Eq + Hash, V> CompressedCache<K, V> {
+    // holds at most `max_bytes` of stored values
+    pub fn new(max_bytes: u64, threshold: usize) -> Self
+    where
+        K: Send + Sync + 'static,
+    {
+        let builder = LruCache::builder()
+            .max_weight(max_bytes)
+            .weigher(|_: &K, value: &Compressed| value.stored_len() as u64);
+        Self::with_builder(builder, threshold).expect("a weight budget always builds")
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> CompressedCache<K, V, S> {
+    // for ttls, policies and the rest, a weigher given to `builder` sees
+    // the stored form of each value
+    pub fn with_builder(
+        builder: CacheBuilder<K, Compressed, S>,
+        threshold: usize,
+    ) -> Result<Self, BuildError>
+    where
+        K: Send + Sync + 'static,
+        S: Send + Sync + 'static,
+    {
+        Ok(Self {
+            cache: builder.build()?,
+            threshold,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<K, V, S> CompressedCache<K, V, S>
+where
+    K: Eq + Hash,
+    V: AsRef<[u8]> + From<Vec<u8>>,
+    S: BuildHasher,
+{
+    // the value is compressed before the cache lock is taken
+    pub fn put(&self, key: K, value: V) {
+        let stored = Compressed::new(value.as_ref(), self.threshold);
+        self.cache.put(key, stored);
+    }
+
+    // decompresses a copy outside the cache lock
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let stored = self.cache.get(key)?;
+        Some(V::from(stored.unpack()))
+    }
+}
+
+impl<K, V, S> Deref for CompressedCache<K, V, S> {
+    type Target = LruCache<K, Compressed, S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(n: usize) -> Vec<u8> {
+        format!("<li class=\"item\">entry {n}</li>")
+            .repeat(40)
+            .into_bytes()
+    }
+
+    #[test]
+    fn compressible_values_round_trip_in_less_space() {
+        let cache: CompressedCache<u32, Vec<u8>> = CompressedCache::new(64 * 1024, 64);
+
+        for key in 0..100 {
+            cache.put(key, fragment(key as usize));
+        }
+        cache.put(1000, b"short".to_vec());
+
+        // 100 uncompressed fragments would not fit
+        assert!(100 * fragment(0).len() as u64 > cache.max_weight());
+        assert_eq!(cache.len(), 101);
+        assert_eq!(cache.get(&7), Some(fragment(7)));
+        assert!(cache.peek(&7).unwrap().is_compressed());
+        assert!(!cache.peek(&1000).unwrap().is_compressed());
+        assert_eq!(cache.get(&1000), Some(b"short".to_vec()));
+    }
+
+    #[test]
+    fn incompressible_values_are_kept_verbatim() {
+        let cache: CompressedCache<u32, Vec<u8>> = CompressedCache::new(1 << 20, 16);
+        let noise: Vec<u8> = (0..4096u32)
+            .map(|i| (crate::mrc::splitmix64(u64::from(i)) >> 56) as u8)
+            .collect();
+
+        cache.put(1, noise.clone());
+
+        assert!(!cache.peek(&1).unwrap().is_compressed());
+        assert_eq!(cache.get(&1), Some(noise));
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hash;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::Tier;
+
+// dead bytes a log may carry before it is worth rewriting
+const COMPACT_MIN: u64 = 64 * 1024;
+
+// overflow tier keeping values in a file, for a `TieredCache` whose entries
+// are expensive to recompute
+//
+// values are appended to a log and found through an index in memory, so only
+// the values take up disk space. overwritten and removed values are left in
+// place until they make up most of the file, then the live ones are copied
+// to a fresh file. once `max_bytes` of values are stored the oldest ones are
+// dropped. the file is scratch space, it is truncated when opened and the
+// index is not saved. io errors drop the entry, a failed read is a miss
+pub struct DiskTier<K> {
+    path: PathBuf,
+    max_bytes: u64,
+    log: Mutex<Log<K>>,
+}
+
+struct Log<K> {
+    file: File,
+    end: u64,
+    dead: u64,
+    // dead bytes to wait for after a failed compaction, 0 when none failed
+    retry_at: u64,
+    next: u64,
+    index: HashMap<K, Record>,
+    // keys by append order, oldest first
+    order: BTreeMap<u64, K>,
+}
+
+#[derive(Clone, Copy)]
+struct Record {
+    offset: u64,
+    len: u64,
+    seq: u64,
+}
+
+fn create(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+impl<K: Eq + Hash + Clone> DiskTier<K> {
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let path = path.into();
+        let file = create(&path)?;
+        Ok(Self {
+            path,
+            max_bytes,
+            log: Mutex::new(Log {
+                file,
+                end: 0,
+                dead: 0,
+                retry_at: 0,
+                next: 0,
+                index: HashMap::new(),
+                order: BTreeMap::new(),
+            }),
+        })
+    }
+
+    // bytes of values currently served
+    pub fn bytes(&self) -> u64 {
+        let log = self.log.lock().unwrap();
+        log.end - log.dead
+    }
+
+    pub fn len(&self) -> usize {
+        self.log.lock().unwrap().index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn append(&self, log: &mut Log<K>, key: K, bytes: &[u8]) -> io::Result<()> {
+        log.drop_key(&key);
+        let len = bytes.len() as u64;
+        if len > self.max_bytes {
+            return Ok(());
+        }
+        while log.end - log.dead + len > self.max_bytes {
+            let Some((_, oldest)) = log.order.pop_first() else {
+                break;
+            };
+            log.drop_key(&oldest);
+        }
+
+        log.file.seek(SeekFrom::Start(log.end))?;
+        log.file.write_all(bytes)?;
+        let record = Record {
+            offset: log.end,
+            len,
+            seq: log.next,
+        };
+        log.end += len;
+        log.next += 1;
+        log.order.insert(record.seq, key.clone());
+        log.index.insert(key, record);
+        // the value is stored whether or not the rewrite works out, a failed
+        // one is tried again once twice the dead space has piled up
+        if log.dead >= COMPACT_MIN.max(log.retry_at)
+            && log.dead > log.end - log.dead
+            && self.compact(log).is_err()
+        {
+            log.retry_at = log.dead.saturating_mul(2);
+        }
+        Ok(())
+    }
+
+    // copies the live values to a fresh file that then replaces the log. the
+    // index only moves over once the rename went through, a failure leaves
+    // it pointing into the old file
+    fn compact(&self, log: &mut Log<K>) -> io::Result<()> {
+        let fresh_path = self.path.with_extension("compact");
+        let mut fresh = create(&fresh_path)?;
+        let mut moved = HashMap::with_capacity(log.index.len());
+        let mut end = 0;
+        for key in log.order.values() {
+            let record = log.index[key];
+            let bytes = read(&mut log.file, &record)?;
+            fresh.write_all(&bytes)?;
+            moved.insert(
+                key.clone(),
+                Record {
+                    offset: end,
+                    ..record
+                },
+            );
+            end += record.len;
+        }
+        fs::rename(&fresh_path, &self.path)?;
+        log.file = fresh;
+        log.index = moved;
+        log.end = end;
+        log.dead = 0;
+        log.retry_at = 0;
+        Ok(())
+    }
+}
+
+impl<K: Eq + Hash> Log<K> {
+    fn drop_key(&mut self, key: &K) {
+        if let Some(record) = self.index.remove(key) {
+            self.order.remove(&record.seq);
+            self.dead += record.len;
+        }
+    }
+}
+
+fn read(file: &mut File, record: &Record) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; record.len as usize];
+    file.seek(SeekFrom::Start(record.offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl<K, V> Tier<K, V> for DiskTier<K>
+where
+    K: Eq + Hash + Clone,
+    V: AsRef<[u8]> + From<Vec<u8>>,
+{
+    fn get(&self, key: &K) -> Option<V> {
+        let mut log = self.log.lock().unwrap();
+        let record = *log.index.get(key)?;
+        read(&mut log.file, &record).ok().map(V::from)
+    }
+
+    fn put(&self, key: K, value: V) {
+        let mut log = self.log.lock().unwrap();
+        if self.append(&mut log, key.clone(), value.as_ref()).is_err() {
+            log.drop_key(&key);
+        }
+    }
+
+    fn remove(&self, key: &K) {
+        self.log.lock().unwrap().drop_key(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TieredCache;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("lru-cache-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn evicted_blobs_are_served_from_disk() {
+        let path = scratch("spill");
+        let cache = TieredCache::new(1, DiskTier::open(&path, 1024).unwrap());
+
+        cache.put(1, vec![1u8; 100]);
+        cache.put(2, vec![2u8; 100]);
+        assert!(!cache.l1().contains_key(&1));
+        assert_eq!(cache.l2().bytes(), 100);
+
+        assert_eq!(cache.get(&1), Some(vec![1u8; 100]));
+        assert_eq!(cache.l1().peek(&1), Some(vec![1u8; 100]));
+        assert_eq!(cache.l2().len(), 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn oldest_values_go_first_and_dead_space_is_reclaimed() {
+        let path = scratch("compact");
+        let tier = DiskTier::open(&path, 4 * COMPACT_MIN).unwrap();
+        let blob = vec![7u8; 1024];
+
+        for key in 0..300u32 {
+            Tier::<u32, Vec<u8>>::put(&tier, key, blob.clone());
+        }
+        // only the newest 256 fit
+        assert_eq!(tier.len(), 256);
+        assert_eq!(Tier::<u32, Vec<u8>>::get(&tier, &0), None);
+        assert_eq!(Tier::<u32, Vec<u8>>::get(&tier, &299), Some(blob.clone()));
+
+        for key in 44..200u32 {
+            Tier::<u32, Vec<u8>>::remove(&tier, &key);
+        }
+        Tier::<u32, Vec<u8>>::put(&tier, 1000, blob.clone());
+        assert!(fs::metadata(&path).unwrap().len() < 2 * COMPACT_MIN);
+        assert_eq!(Tier::<u32, Vec<u8>>::get(&tier, &250), Some(blob));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_failed_compaction_keeps_the_old_log_readable() {
+        let path = scratch("stuck");
+        let tier = DiskTier::open(&path, 4 * COMPACT_MIN).unwrap();
+        let blob = |key: u32| vec![key as u8; 1024];
+
+        for key in 0..200u32 {
+            Tier::<u32, Vec<u8>>::put(&tier, key, blob(key));
+        }
+        for key in 0..150u32 {
+            Tier::<u32, Vec<u8>>::remove(&tier, &key);
+        }
+        // a non-empty directory in its place makes the rename fail
+        fs::remove_file(&path).unwrap();
+        fs::create_dir_all(path.join("in-the-way")).unwrap();
+        Tier::<u32, Vec<u8>>::put(&tier, 1000, blob(0));
+
+        assert_eq!(Tier::<u32, Vec<u8>>::get(&tier, &199), Some(blob(199)));
+        assert_eq!(Tier::<u32, Vec<u8>>::get(&tier, &1000), Some(blob(0)));
+        // the next puts do not rewrite the log again right away
+        let retry_at = tier.log.lock().unwrap().retry_at;
+        assert_eq!(retry_at, 2 * 150 * 1024);
+        Tier::<u32, Vec<u8>>::put(&tier, 1001, blob(1));
+        assert_eq!(Tier::<u32, Vec<u8>>::get(&tier, &1001), Some(blob(1)));
+        fs::remove_dir_all(&path).unwrap();
+        fs::remove_file(path.with_extension("compact")).unwrap();
+    }
+}
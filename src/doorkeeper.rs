@@ -0,0 +1,76 @@
+use crate::mrc::splitmix64;
+
+// bits per remembered key and probes per key, about 1% false positives
+const BITS_PER_KEY: usize = 10;
+const PROBES: u64 = 4;
+
+// bloom filter over key hashes, remembering the last `window` keys seen.
+// once that many were added it starts over empty, so a key has to come back
+// within about one window to count as seen twice
+pub(crate) struct Doorkeeper {
+    bits: Vec<u64>,
+    added: usize,
+    window: usize,
+}
+
+impl Doorkeeper {
+    pub(crate) fn new(window: usize) -> Self {
+        let words = (window * BITS_PER_KEY).div_ceil(64).max(1);
+        Self {
+            bits: vec![0; words],
+            added: 0,
+            window,
+        }
+    }
+
+    // bit positions by double hashing, the second hash is a remix of the
+    // first so each key is hashed only once
+    fn positions(&self, hash: u64) -> impl Iterator<Item = usize> + use<> {
+        let len = self.bits.len() as u64 * 64;
+        let step = splitmix64(hash) | 1;
+        (0..PROBES).map(move |i| (hash.wrapping_add(i.wrapping_mul(step)) % len) as usize)
+    }
+
+    // true if the key was already seen in this window, remembers it if not
+    pub(crate) fn check_and_add(&mut self, hash: u64) -> bool {
+        let seen = self
+            .positions(hash)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0);
+        if seen {
+            return true;
+        }
+
+        if self.added >= self.window {
+            self.clear();
+        }
+        for bit in self.positions(hash) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+        self.added += 1;
+        false
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.bits.fill(0);
+        self.added = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_sighting_within_the_window() {
+        let mut door = Doorkeeper::new(100);
+
+        assert!(!door.check_and_add(7));
+        assert!(door.check_and_add(7));
+
+        // a window of other keys, plus some for false positives, forgets 7
+        for hash in 1000..1150 {
+            door.check_and_add(splitmix64(hash));
+        }
+        assert!(!door.check_and_add(7));
+    }
+}
@@ -0,0 +1,323 @@
+use std::hash::{BuildHasher, Hash, RandomState};
+
+use crate::events::EventRef;
+use crate::lock::RwLockWriteGuard;
+use crate::{CacheState, NIL, RemovalCause, TraceOp};
+
+// view into a single key of the cache, obtained from `LruCache::entry`
+//
+// the cache write lock is held for as long as the entry lives, so keep it
+// short lived and never touch the same cache from inside the closures
+pub enum Entry<'a, K: Eq + Hash, V, S: BuildHasher = RandomState> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+pub struct OccupiedEntry<'a, K: Eq + Hash, V, S: BuildHasher = RandomState> {
+    state: RwLockWriteGuard<'a, CacheState<K, V, S>>,
+    idx: usize,
+    // the value was handed out mutably, so the backing store hears of it
+    written: bool,
+    // an insert the cache did not take, held by the handle alone
+    detached: Option<(K, V)>,
+}
+
+pub struct VacantEntry<'a, K: Eq + Hash, V, S: BuildHasher = RandomState> {
+    state: RwLockWriteGuard<'a, CacheState<K, V, S>>,
+    hash: u64,
+    key: K,
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    // runs `f` on the value if the key is present
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+
+    pub fn or_insert(self, default: V) -> V
+    where
+        V: Clone,
+    {
+        self.or_insert_with(|| default)
+    }
+
+    // `f` only runs on a miss, and no other thread can insert the same key
+    // while it does
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> V
+    where
+        V: Clone,
+    {
+        match self {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => entry.insert(f()).get().clone(),
+        }
+    }
+
+    pub fn or_default(self) -> V
+    where
+        V: Clone + Default,
+    {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
+    pub(crate) fn new(state: RwLockWriteGuard<'a, CacheState<K, V, S>>, idx: usize) -> Self {
+        Self {
+            state,
+            idx,
+            written: false,
+            detached: None,
+        }
+    }
+
+    fn detached(state: RwLockWriteGuard<'a, CacheState<K, V, S>>, key: K, value: V) -> Self {
+        Self {
+            state,
+            idx: NIL,
+            written: false,
+            detached: Some((key, value)),
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        match &self.detached {
+            Some((key, _)) => key,
+            None => &self.state.node(self.idx).key,
+        }
+    }
+
+    pub fn get(&self) -> &V {
+        match &self.detached {
+            Some((_, value)) => value,
+            None => &self.state.node(self.idx).value,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        match &mut self.detached {
+            Some((_, value)) => value,
+            None => {
+                self.written = true;
+                &mut self.state.node_mut(self.idx).value
+            }
+        }
+    }
+
+    // swaps in a new value and returns the old one, like a put the entry
+    // starts a fresh time to live
+    pub fn insert(&mut self, value: V) -> V {
+        if let Some((_, held)) = &mut self.detached {
+            return std::mem::replace(held, value);
+        }
+        let hash = self.state.node(self.idx).hash;
+        self.state.record_trace(TraceOp::Put(hash));
+        let ttl = self.state.time_to_live;
+        self.state.set_ttl(self.idx, ttl);
+        self.state.mark_written(self.idx);
+        let old = std::mem::replace(self.get_mut(), value);
+        let node = self.state.node(self.idx);
+        self.state.notify(&node.key, &old, RemovalCause::Replaced);
+        self.state.emit(EventRef::Insert(&node.key, &node.value));
+        old
+    }
+
+    pub fn remove(mut self) -> V {
+        if let Some((_, value)) = self.detached.take() {
+            return value;
+        }
+        let idx = std::mem::replace(&mut self.idx, NIL);
+        let (key, value) = self.state.evict_explicit(idx);
+        self.state.delete_through(&key);
+        value
+    }
+}
+
+// the value may have been changed through `get_mut`, so it is written
+// through and reweighed before the lock is released. an entry inserted into
+// a zero capacity cache, one the doorkeeper turns away or one too heavy to
+// admit only lives as long as the handle, the first two never reach the
+// cache at all
+impl<K: Eq + Hash, V, S: BuildHasher> Drop for OccupiedEntry<'_, K, V, S> {
+    fn drop(&mut self) {
+        if self.detached.is_some() {
+            return;
+        }
+        if self.idx == NIL {
+            self.state.trim();
+        } else {
+            if self.written {
+                self.state.write_back(self.idx);
+            }
+            self.state.reweigh(self.idx);
+            self.state.settle(self.idx);
+        }
+    }
+}
+
+impl<'a, K: Eq + Hash, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
+    pub(crate) fn new(state: RwLockWriteGuard<'a, CacheState<K, V, S>>, hash: u64, key: K) -> Self {
+        Self { state, hash, key }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    // inserts as most recently used, evicting the LRU entry if the cache is
+    // full, and keeps the lock held through the returned entry. like a put
+    // the value is written through even when the cache holds nothing or the
+    // doorkeeper turns the key away, the entry is then the only copy
+    pub fn insert(self, value: V) -> OccupiedEntry<'a, K, V, S> {
+        let Self {
+            mut state,
+            hash,
+            key,
+        } = self;
+
+        state.write_through(&key, &value);
+        if state.capacity == 0 {
+            return OccupiedEntry::detached(state, key, value);
+        }
+        state.record_trace(TraceOp::Put(hash));
+        if !state.admits(hash, &key, &value) {
+            return OccupiedEntry::detached(state, key, value);
+        }
+        let idx = state.insert_new(hash, key, value);
+        OccupiedEntry::new(state, idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Entry, LruCache};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn or_insert_with_only_runs_on_miss() {
+        let cache = LruCache::new(2);
+
+        assert_eq!(cache.entry(1).or_insert_with(|| "a"), "a");
+        assert_eq!(
+            cache.entry(1).or_insert_with(|| panic!("already present")),
+            "a"
+        );
+    }
+
+    #[test]
+    fn and_modify_updates_present_value() {
+        let cache = LruCache::new(2);
+
+        cache.entry("hits").and_modify(|v| *v += 1).or_insert(0);
+        cache.entry("hits").and_modify(|v| *v += 1).or_insert(0);
+
+        assert_eq!(cache.get("hits"), Some(1));
+    }
+
+    #[test]
+    fn occupied_and_vacant_variants() {
+        let cache = LruCache::new(2);
+        cache.put(1, 10);
+
+        match cache.entry(1) {
+            Entry::Occupied(mut entry) => assert_eq!(entry.insert(11), 10),
+            Entry::Vacant(_) => panic!("key 1 is present"),
+        }
+        match cache.entry(1) {
+            Entry::Occupied(entry) => assert_eq!(entry.remove(), 11),
+            Entry::Vacant(_) => panic!("key 1 is present"),
+        }
+        assert!(matches!(cache.entry(1), Entry::Vacant(_)));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn vacant_insert_evicts_lru() {
+        let cache = LruCache::new(2);
+        cache.put(1, "a");
+        cache.put(2, "b");
+
+        cache.entry(3).or_insert("c");
+
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn zero_capacity_inserts_are_silent() {
+        let told = Arc::new(AtomicUsize::new(0));
+        let counter = told.clone();
+        let cache = LruCache::builder()
+            .capacity(0)
+            .eviction_listener(move |_, _, _| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .build()
+            .unwrap();
+
+        cache.put(1, "a");
+        assert_eq!(cache.entry(2).or_insert("b"), "b");
+        match cache.entry(3) {
+            Entry::Vacant(entry) => assert_eq!(entry.insert("c").remove(), "c"),
+            Entry::Occupied(_) => panic!("nothing is cached"),
+        }
+
+        assert!(cache.is_empty());
+        assert_eq!(told.load(Ordering::Relaxed), 0);
+        assert_eq!(cache.stats().insertions, 0);
+        assert_eq!(cache.stats().evictions, 0);
+    }
+
+    #[test]
+    fn doorkeeper_turns_away_vacant_inserts() {
+        let cache = LruCache::builder()
+            .capacity(1)
+            .doorkeeper(100)
+            .build()
+            .unwrap();
+        cache.put(1, "a");
+
+        assert_eq!(cache.get_or_insert_with(2, || "b"), "b");
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [1]);
+        // seen before, so let in
+        assert_eq!(cache.entry(2).or_insert("b"), "b");
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn concurrent_counters_do_not_lose_updates() {
+        let cache = Arc::new(LruCache::new(4));
+        let mut handles = vec![];
+
+        for _ in 0..8 {
+            let c = Arc::clone(&cache);
+            handles.push(thread::spawn(move || {
+                for _ in 0..100 {
+                    c.entry("count").and_modify(|v| *v += 1).or_insert(1);
+                }
+            }));
+        }
+
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        assert_eq!(cache.get("count"), Some(800));
+    }
+}
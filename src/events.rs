@@ -0,0 +1,133 @@
+use std::sync::mpsc::Sender;
+
+use crate::RemovalCause;
+
+// what happened in a cache, as seen by `LruCache::subscribe`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheEvent<K, V> {
+    // a value was written, either for a new key or over an existing one
+    Insert(K, V),
+    Hit(K),
+    // lookups take a borrowed key, so a miss only reports its hash
+    Miss { hash: u64 },
+    Evict(K, V, RemovalCause),
+    Expire(K, V),
+}
+
+// borrowed form kept by the cache, only turned into an owned event when
+// someone is listening
+pub(crate) enum EventRef<'a, K, V> {
+    Insert(&'a K, &'a V),
+    Hit(&'a K),
+    Miss(u64),
+    Removed(&'a K, &'a V, RemovalCause),
+}
+
+// turns a borrowed event into an owned one
+pub(crate) type ToEvent<K, V> = fn(&EventRef<'_, K, V>) -> CacheEvent<K, V>;
+
+// monomorphised where K and V are known to be Clone, the cache itself only
+// stores the function pointer
+pub(crate) fn to_owned<K: Clone, V: Clone>(event: &EventRef<'_, K, V>) -> CacheEvent<K, V> {
+    match *event {
+        EventRef::Insert(key, value) => CacheEvent::Insert(key.clone(), value.clone()),
+        EventRef::Hit(key) => CacheEvent::Hit(key.clone()),
+        EventRef::Miss(hash) => CacheEvent::Miss { hash },
+        EventRef::Removed(key, value, RemovalCause::Expired) => {
+            CacheEvent::Expire(key.clone(), value.clone())
+        }
+        EventRef::Removed(key, value, cause) => {
+            CacheEvent::Evict(key.clone(), value.clone(), cause)
+        }
+    }
+}
+
+// one receiving end registered with the cache
+pub(crate) enum Subscriber<K, V> {
+    // everything, see `LruCache::subscribe`
+    Events(Sender<CacheEvent<K, V>>),
+    // removals only, see `LruCache::evictions`
+    #[cfg(feature = "async")]
+    Evictions(futures_channel::mpsc::UnboundedSender<(K, V, RemovalCause)>),
+}
+
+impl<K, V> Subscriber<K, V> {
+    // false once the receiver is gone and the subscriber can be dropped
+    pub(crate) fn deliver(&self, event: &EventRef<'_, K, V>, to_event: ToEvent<K, V>) -> bool {
+        match self {
+            Subscriber::Events(events) => events.send(to_event(event)).is_ok(),
+            #[cfg(feature = "async")]
+            Subscriber::Evictions(evictions) => {
+                // skip copying events this subscriber does not want
+                if !matches!(event, EventRef::Removed(..)) {
+                    return !evictions.is_closed();
+                }
+                let removed = match to_event(event) {
+                    CacheEvent::Evict(key, value, cause) => (key, value, cause),
+                    CacheEvent::Expire(key, value) => (key, value, RemovalCause::Expired),
+                    _ => unreachable!("removals map to evict or expire"),
+                };
+                evictions.unbounded_send(removed).is_ok()
+            }
+        }
+    }
+}
+
+// evictions as an async stream, for a task that persists or forwards them
+// without holding up the thread that caused them
+#[cfg(feature = "async")]
+pub struct EvictionStream<K, V> {
+    receiver: futures_channel::mpsc::UnboundedReceiver<(K, V, RemovalCause)>,
+}
+
+#[cfg(feature = "async")]
+impl<K, V> EvictionStream<K, V> {
+    pub(crate) fn new(
+        receiver: futures_channel::mpsc::UnboundedReceiver<(K, V, RemovalCause)>,
+    ) -> Self {
+        Self { receiver }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K, V> futures_core::Stream for EvictionStream<K, V> {
+    type Item = (K, V, RemovalCause);
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        std::pin::Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+
+    use futures_core::Stream;
+
+    use crate::{LruCache, RemovalCause};
+
+    #[test]
+    fn evictions_arrive_on_the_stream() {
+        let cache = LruCache::new(1);
+        let mut evictions = cache.evictions();
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.get(&2);
+        cache.remove(&2);
+        drop(cache);
+
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut next = || Pin::new(&mut evictions).poll_next(&mut cx);
+        assert_eq!(
+            next(),
+            Poll::Ready(Some((1, "a", RemovalCause::CapacityEvicted)))
+        );
+        assert_eq!(next(), Poll::Ready(Some((2, "b", RemovalCause::Explicit))));
+        assert_eq!(next(), Poll::Ready(None));
+    }
+}
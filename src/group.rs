@@ -0,0 +1,208 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+use crate::lock::RwLock;
+use crate::{CacheState, LruCache};
+
+// weight budget shared by several caches
+//
+// every registered cache reports its weight changes here, and once the
+// combined weight goes over budget the heaviest member is trimmed from its
+// LRU end. trimming happens right after the write that caused it, with the
+// writer's own lock already released, so only one cache lock is ever held
+// at a time and the budget may be exceeded for that short moment.
+#[derive(Clone)]
+pub struct CacheGroup {
+    shared: Arc<GroupShared>,
+}
+
+pub(crate) struct GroupShared {
+    budget: u64,
+    used: AtomicU64,
+    members: Mutex<Vec<Member>>,
+}
+
+struct Member {
+    weight: Arc<AtomicU64>,
+    cache: Weak<dyn GroupMember>,
+}
+
+// handle a cache state keeps to report its weight to the group
+pub(crate) struct GroupLink {
+    shared: Arc<GroupShared>,
+    weight: Arc<AtomicU64>,
+}
+
+// what the group needs from a member, object safe so caches of different
+// key and value types can share a budget
+trait GroupMember: Send + Sync {
+    // drops the least recently used entry, false once the cache is empty
+    fn evict_lru(&self) -> bool;
+}
+
+impl<K, V, S> GroupMember for RwLock<CacheState<K, V, S>>
+where
+    K: Eq + Hash + Send + Sync,
+    V: Send + Sync,
+    S: BuildHasher + Send + Sync,
+{
+    fn evict_lru(&self) -> bool {
+        let mut state = self.write();
+
+        let Some(victim) = state.victim() else {
+            return false;
+        };
+        state.evict_to_fit(victim);
+        true
+    }
+}
+
+impl CacheGroup {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            shared: Arc::new(GroupShared {
+                budget,
+                used: AtomicU64::new(0),
+                members: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    // adds a cache to the group, its current weight counts right away. a
+    // cache belongs to at most one group, registering it again panics
+    pub fn register<K, V, S>(&self, cache: &LruCache<K, V, S>)
+    where
+        K: Eq + Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        S: BuildHasher + Send + Sync + 'static,
+    {
+        assert!(
+            cache.group.set(Arc::clone(&self.shared)).is_ok(),
+            "cache is already registered with a group"
+        );
+
+        let weight = Arc::new(AtomicU64::new(0));
+        {
+            let mut state = cache.inner.write();
+
+            weight.store(state.weight, Ordering::Relaxed);
+            self.shared.used.fetch_add(state.weight, Ordering::Relaxed);
+            state.group = Some(GroupLink {
+                shared: Arc::clone(&self.shared),
+                weight: Arc::clone(&weight),
+            });
+        }
+
+        let member: Arc<dyn GroupMember> = cache.inner.clone();
+        self.shared.members.lock().unwrap().push(Member {
+            weight,
+            cache: Arc::downgrade(&member),
+        });
+        self.shared.rebalance();
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.shared.budget
+    }
+
+    // combined weight of all live members
+    pub fn weight(&self) -> u64 {
+        self.shared.used.load(Ordering::Relaxed)
+    }
+}
+
+impl GroupShared {
+    // trims the heaviest members until the group fits its budget again
+    pub(crate) fn rebalance(&self) {
+        while self.used.load(Ordering::Relaxed) > self.budget {
+            let victim = {
+                let mut members = self.members.lock().unwrap();
+
+                members.retain(|member| member.cache.strong_count() > 0);
+                members
+                    .iter()
+                    .max_by_key(|member| member.weight.load(Ordering::Relaxed))
+                    .and_then(|member| member.cache.upgrade())
+            };
+
+            match victim {
+                Some(cache) if cache.evict_lru() => {}
+                _ => break,
+            }
+        }
+    }
+}
+
+impl GroupLink {
+    // mirror a change of the member's total weight
+    pub(crate) fn charge(&self, old: u64, new: u64) {
+        if new > old {
+            self.shared.used.fetch_add(new - old, Ordering::Relaxed);
+        } else {
+            self.shared.used.fetch_sub(old - new, Ordering::Relaxed);
+        }
+        self.weight.store(new, Ordering::Relaxed);
+    }
+}
+
+// cheap check run after every write, a no-op for caches outside a group
+pub(crate) fn enforce(group: &OnceLock<Arc<GroupShared>>) {
+    if let Some(shared) = group.get()
+        && shared.used.load(Ordering::Relaxed) > shared.budget
+    {
+        shared.rebalance();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weighted(max_weight: u64) -> LruCache<u32, Vec<u8>> {
+        LruCache::builder()
+            .weigher(|_, v: &Vec<u8>| v.len() as u64)
+            .max_weight(max_weight)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn combined_weight_stays_within_budget() {
+        let group = CacheGroup::new(100);
+        let small = weighted(1000);
+        let big = weighted(1000);
+        group.register(&small);
+        group.register(&big);
+
+        small.put(1, vec![0; 10]);
+        for i in 0..20 {
+            big.put(i, vec![0; 10]);
+        }
+
+        assert!(group.weight() <= 100);
+        assert_eq!(group.weight(), small.weight() + big.weight());
+        // the heavy cache paid for the overflow
+        assert!(small.contains_key(&1));
+        assert!(!big.contains_key(&0));
+    }
+
+    #[test]
+    fn dropped_members_release_their_weight() {
+        let group = CacheGroup::new(100);
+        let a = weighted(1000);
+        group.register(&a);
+        {
+            let b = LruCache::new(10);
+            group.register(&b);
+            b.put("x", 1);
+            a.put(1, vec![0; 30]);
+            assert_eq!(group.weight(), 31);
+        }
+
+        assert_eq!(group.weight(), 30);
+        a.put(2, vec![0; 80]);
+        assert_eq!(a.len(), 1);
+        assert_eq!(group.weight(), 80);
+    }
+}
@@ -0,0 +1,35 @@
+use std::fmt;
+use std::hash::RandomState;
+use std::ops::Deref;
+
+use crate::CacheState;
+use crate::lock::RwLockReadGuard;
+
+// borrowed view of a cached value, obtained from `LruCache::get_ref`
+//
+// holds the cache read lock while alive: other readers carry on but writers
+// wait, so drop it as soon as the value is no longer needed
+pub struct ValueGuard<'a, K, V, S = RandomState> {
+    state: RwLockReadGuard<'a, CacheState<K, V, S>>,
+    idx: usize,
+}
+
+impl<'a, K, V, S> ValueGuard<'a, K, V, S> {
+    pub(crate) fn new(state: RwLockReadGuard<'a, CacheState<K, V, S>>, idx: usize) -> Self {
+        Self { state, idx }
+    }
+}
+
+impl<K, V, S> Deref for ValueGuard<'_, K, V, S> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.state.node(self.idx).value
+    }
+}
+
+impl<K, V: fmt::Debug, S> fmt::Debug for ValueGuard<'_, K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
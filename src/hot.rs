@@ -0,0 +1,114 @@
+use std::cmp::Reverse;
+
+use hashbrown::HashTable;
+
+// space saving heavy hitters over the keys the cache is asked for
+//
+// at most `capacity` keys are counted. a key that is not counted yet takes
+// over the slot of the least counted one and inherits its count, so counts
+// can overestimate but a key seen more often than total / capacity times is
+// never missed. the slot to take over is found by a scan, so the capacity is
+// meant to stay small
+pub(crate) struct HotKeys<K> {
+    capacity: usize,
+    counters: Vec<Counter<K>>,
+    index: HashTable<usize>,
+    // the tracker is generic over any key, copying one in is set up by the
+    // builder where `K: Clone` is known
+    clone_key: fn(&K) -> K,
+}
+
+struct Counter<K> {
+    key: K,
+    hash: u64,
+    count: u64,
+}
+
+impl<K: Eq> HotKeys<K> {
+    pub(crate) fn new(capacity: usize, clone_key: fn(&K) -> K) -> Self {
+        Self {
+            capacity,
+            counters: Vec::with_capacity(capacity),
+            index: HashTable::with_capacity(capacity),
+            clone_key,
+        }
+    }
+
+    pub(crate) fn record(&mut self, hash: u64, key: &K) {
+        let counters = &mut self.counters;
+        if let Some(&i) = self.index.find(hash, |&i| counters[i].key == *key) {
+            counters[i].count += 1;
+            return;
+        }
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = (self.clone_key)(key);
+        if counters.len() < self.capacity {
+            counters.push(Counter {
+                key,
+                hash,
+                count: 1,
+            });
+            let i = counters.len() - 1;
+            self.index.insert_unique(hash, i, |&i| counters[i].hash);
+            return;
+        }
+
+        let min = (0..counters.len())
+            .min_by_key(|&i| counters[i].count)
+            .expect("a full tracker has counters");
+        self.index
+            .find_entry(counters[min].hash, |&i| i == min)
+            .expect("every counter is indexed")
+            .remove();
+        let slot = &mut counters[min];
+        slot.key = key;
+        slot.hash = hash;
+        slot.count += 1;
+        self.index.insert_unique(hash, min, |&i| counters[i].hash);
+    }
+
+    // the n most counted keys, highest count first
+    pub(crate) fn hottest(&self, n: usize) -> Vec<(K, u64)> {
+        let mut top: Vec<&Counter<K>> = self.counters.iter().collect();
+        top.sort_by_key(|counter| Reverse(counter.count));
+        top.into_iter()
+            .take(n)
+            .map(|counter| ((self.clone_key)(&counter.key), counter.count))
+            .collect()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.counters.clear();
+        self.index.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heavy_hitters_survive_a_long_tail() {
+        let mut hot = HotKeys::new(4, u32::clone);
+
+        for round in 0..100u32 {
+            for _ in 0..3 {
+                hot.record(1, &1);
+            }
+            for _ in 0..2 {
+                hot.record(2, &2);
+            }
+            // every tail key is seen once and keeps displacing the others
+            let tail = 1000 + round;
+            hot.record(u64::from(tail), &tail);
+        }
+
+        let top = hot.hottest(2);
+        assert_eq!(top.iter().map(|(k, _)| *k).collect::<Vec<_>>(), [1, 2]);
+        assert!(top[0].1 >= 300);
+        assert!(top[1].1 >= 200);
+    }
+}
@@ -0,0 +1,241 @@
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use redis::{Client, Connection, RedisResult};
+
+use crate::{Encode, LruCache};
+
+// how often the subscriber checks whether its cache is still around
+const POLL: Duration = Duration::from_secs(1);
+
+// wait between attempts to get a lost subscription back
+const RETRY: Duration = Duration::from_secs(1);
+
+// keeps caches in several processes coherent over a redis pub/sub channel
+//
+// writes made through `put` and `invalidate` are applied locally and the key
+// is published, every other instance on the channel then drops its copy and
+// loads the new value on its next miss. messages carry an id of the sending
+// instance so nobody drops its own writes. a subscription lost to a network
+// error is retried, and since invalidations may have been missed meanwhile
+// the local cache is cleared once it is back
+pub struct RedisInvalidation<K, V, S = RandomState> {
+    cache: Arc<LruCache<K, V, S>>,
+    publisher: Mutex<Connection>,
+    channel: String,
+    origin: u64,
+}
+
+impl<K, V, S> RedisInvalidation<K, V, S>
+where
+    K: Eq + Hash + Encode + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    // returns once subscribed, so no invalidation published after that is
+    // missed. the subscriber thread lives as long as the cache
+    pub fn start(
+        cache: Arc<LruCache<K, V, S>>,
+        client: &Client,
+        channel: impl Into<String>,
+    ) -> RedisResult<Self> {
+        let channel = channel.into();
+        let origin = RandomState::new().hash_one((std::process::id(), SystemTime::now()));
+        let publisher = client.get_connection()?;
+
+        let (ready, subscribed) = mpsc::channel();
+        let listen = Listener {
+            cache: Arc::downgrade(&cache),
+            client: client.clone(),
+            channel: channel.clone(),
+            origin,
+        };
+        thread::Builder::new()
+            .name("lru-cache-invalidation".into())
+            .spawn(move || listen.run(ready))
+            .expect("failed to spawn the invalidation thread");
+        subscribed
+            .recv()
+            .expect("the subscriber reports before anything else")?;
+
+        Ok(Self {
+            cache,
+            publisher: Mutex::new(publisher),
+            channel,
+            origin,
+        })
+    }
+
+    pub fn cache(&self) -> &Arc<LruCache<K, V, S>> {
+        &self.cache
+    }
+
+    // the local put happens either way, an error means other instances may
+    // still serve their old copy
+    pub fn put(&self, key: K, value: V) -> RedisResult<Option<V>> {
+        let message = encode(self.origin, &key);
+        let old = self.cache.put(key, value);
+        self.publish(message)?;
+        Ok(old)
+    }
+
+    // drops the cached copy here and everywhere else on the channel, a
+    // shared backing store keeps the key
+    pub fn invalidate(&self, key: &K) -> RedisResult<Option<V>> {
+        let old = self.cache.invalidate(key);
+        self.publish(encode(self.origin, key))?;
+        Ok(old)
+    }
+
+    fn publish(&self, message: Vec<u8>) -> RedisResult<()> {
+        redis::cmd("PUBLISH")
+            .arg(&self.channel)
+            .arg(message)
+            .exec(&mut *self.publisher.lock().unwrap())
+    }
+}
+
+// the sender's id followed by the encoded key
+fn encode<K: Encode>(origin: u64, key: &K) -> Vec<u8> {
+    let mut message = Vec::new();
+    origin.encode(&mut message);
+    key.encode(&mut message);
+    message
+}
+
+// drops the key a message names, unless it came from `origin` itself or
+// does not decode
+fn apply<K, V, S>(cache: &LruCache<K, V, S>, origin: u64, mut message: &[u8])
+where
+    K: Eq + Hash + Encode,
+    S: BuildHasher,
+{
+    let Ok(sender) = u64::decode(&mut message) else {
+        return;
+    };
+    if sender == origin {
+        return;
+    }
+    // the sender already wrote the store, only the local copy is stale
+    if let Ok(key) = K::decode(&mut message) {
+        cache.invalidate(&key);
+    }
+}
+
+struct Listener<K, V, S> {
+    cache: Weak<LruCache<K, V, S>>,
+    client: Client,
+    channel: String,
+    origin: u64,
+}
+
+impl<K, V, S> Listener<K, V, S>
+where
+    K: Eq + Hash + Encode,
+    S: BuildHasher,
+{
+    fn run(self, ready: mpsc::Sender<RedisResult<()>>) {
+        let mut ready = Some(ready);
+        let mut first = true;
+        while self.cache.strong_count() > 0 {
+            let mut connection = match self.subscribe() {
+                Ok(connection) => connection,
+                Err(err) => {
+                    // a failure to subscribe at all is `start`'s to report
+                    if let Some(ready) = ready.take() {
+                        let _ = ready.send(Err(err));
+                        return;
+                    }
+                    thread::sleep(RETRY);
+                    continue;
+                }
+            };
+            if let Some(ready) = ready.take() {
+                let _ = ready.send(Ok(()));
+            }
+            if !first && let Some(cache) = self.cache.upgrade() {
+                cache.clear();
+            }
+            first = false;
+            self.listen(&mut connection);
+        }
+    }
+
+    fn subscribe(&self) -> RedisResult<Connection> {
+        let mut connection = self.client.get_connection()?;
+        connection.set_read_timeout(Some(POLL))?;
+        connection.as_pubsub().subscribe(&self.channel)?;
+        Ok(connection)
+    }
+
+    // applies messages until the cache is gone or the connection fails
+    fn listen(&self, connection: &mut Connection) {
+        let mut pubsub = connection.as_pubsub();
+        loop {
+            match pubsub.get_message() {
+                Ok(message) => {
+                    let Some(cache) = self.cache.upgrade() else {
+                        return;
+                    };
+                    apply(&cache, self.origin, message.get_payload_bytes());
+                }
+                Err(err) if err.is_timeout() => {
+                    if self.cache.strong_count() == 0 {
+                        return;
+                    }
+                }
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CacheStore;
+
+    #[test]
+    fn messages_from_others_invalidate_and_own_are_ignored() {
+        let cache = LruCache::new(4);
+        cache.put("a".to_string(), 1);
+        cache.put("b".to_string(), 2);
+
+        apply(&cache, 7, &encode(7, &"a".to_string()));
+        apply(&cache, 7, &encode(8, &"b".to_string()));
+        apply(&cache, 7, b"garbage");
+
+        assert!(cache.contains_key("a"));
+        assert!(!cache.contains_key("b"));
+    }
+
+    #[derive(Clone, Default)]
+    struct Deletes(Arc<Mutex<Vec<String>>>);
+
+    impl CacheStore<String, i32> for Deletes {
+        fn write(&self, _: &String, _: &i32) {}
+
+        fn delete(&self, key: &String) {
+            self.0.lock().unwrap().push(key.clone());
+        }
+    }
+
+    #[test]
+    fn invalidations_leave_the_store_alone() {
+        let deletes = Deletes::default();
+        let cache = LruCache::builder()
+            .capacity(4)
+            .write_through(deletes.clone())
+            .build()
+            .unwrap();
+        cache.put("a".to_string(), 1);
+
+        apply(&cache, 7, &encode(8, &"a".to_string()));
+
+        assert!(!cache.contains_key("a"));
+        assert!(deletes.0.lock().unwrap().is_empty());
+    }
+}
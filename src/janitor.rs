@@ -0,0 +1,38 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::CacheState;
+use crate::lock::RwLock;
+
+// starts a thread sweeping expired entries out of the cache every
+// `interval`. it only holds a weak handle, and the returned sender is kept by
+// the state so dropping the cache wakes the thread up and ends it
+pub(crate) fn spawn<K, V, S>(
+    state: &Arc<RwLock<CacheState<K, V, S>>>,
+    interval: Duration,
+) -> Sender<()>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    let (stop, stopped) = mpsc::channel::<()>();
+    let state = Arc::downgrade(state);
+
+    thread::Builder::new()
+        .name("lru-cache-janitor".into())
+        .spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(state) = state.upgrade() else {
+                    break;
+                };
+                state.write().purge_expired();
+            }
+        })
+        .expect("failed to spawn the janitor thread");
+
+    stop
+}
@@ -0,0 +1,206 @@
+use std::future::{self, Future};
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::LruCache;
+
+// None leaves the request uncached, say for anything but a GET
+type KeyFn<Req, K> = Arc<dyn Fn(&Req) -> Option<K> + Send + Sync>;
+
+type CacheIf<Res> = Arc<dyn Fn(&Res) -> bool + Send + Sync>;
+
+// tower middleware answering repeated requests from a cache
+//
+// `key` picks what a request is cached under. a hit is answered without
+// calling the inner service, a miss is passed on and a successful response
+// stored. errors are never cached. responses have to be Clone, for axum that
+// means caching below a `map_response` that buffers the body into Bytes
+pub struct CacheLayer<Req, K, Res, S = RandomState> {
+    cache: Arc<LruCache<K, Res, S>>,
+    key: KeyFn<Req, K>,
+    cache_if: Option<CacheIf<Res>>,
+    ttl: Option<Duration>,
+}
+
+impl<Req, K, Res, S> CacheLayer<Req, K, Res, S> {
+    // the cache may be shared, to inspect or invalidate it from elsewhere
+    pub fn new<F>(cache: impl Into<Arc<LruCache<K, Res, S>>>, key: F) -> Self
+    where
+        F: Fn(&Req) -> Option<K> + Send + Sync + 'static,
+    {
+        Self {
+            cache: cache.into(),
+            key: Arc::new(key),
+            cache_if: None,
+            ttl: None,
+        }
+    }
+
+    // how long a stored response is served, the cache's own ttl otherwise
+    pub fn time_to_live(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    // only responses passing `cache_if` are stored, e.g. just 200s
+    pub fn cache_if<F>(mut self, cache_if: F) -> Self
+    where
+        F: Fn(&Res) -> bool + Send + Sync + 'static,
+    {
+        self.cache_if = Some(Arc::new(cache_if));
+        self
+    }
+
+    pub fn cache(&self) -> &Arc<LruCache<K, Res, S>> {
+        &self.cache
+    }
+}
+
+impl<Req, K, Res, S> Clone for CacheLayer<Req, K, Res, S> {
+    fn clone(&self) -> Self {
+        Self {
+            cache: Arc::clone(&self.cache),
+            key: Arc::clone(&self.key),
+            cache_if: self.cache_if.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl<T, Req, K, Res, S> Layer<T> for CacheLayer<Req, K, Res, S> {
+    type Service = CacheService<T, Req, K, Res, S>;
+
+    fn layer(&self, inner: T) -> Self::Service {
+        CacheService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+// the service `CacheLayer` wraps around another
+pub struct CacheService<T, Req, K, Res, S = RandomState> {
+    inner: T,
+    layer: CacheLayer<Req, K, Res, S>,
+}
+
+impl<T: Clone, Req, K, Res, S> Clone for CacheService<T, Req, K, Res, S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<T, Req, K, Res, S> Service<Req> for CacheService<T, Req, K, Res, S>
+where
+    T: Service<Req, Response = Res>,
+    T::Future: Send + 'static,
+    T::Error: Send + 'static,
+    K: Eq + Hash + Send + Sync + 'static,
+    Res: Clone + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    type Response = Res;
+    type Error = T::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Res, T::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Req) -> Self::Future {
+        let key = (self.layer.key)(&request);
+        if let Some(key) = &key
+            && let Some(hit) = self.layer.cache.get(key)
+        {
+            return Box::pin(future::ready(Ok(hit)));
+        }
+
+        let response = self.inner.call(request);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let response = response.await?;
+            let cacheable = layer.cache_if.as_ref().is_none_or(|ok| ok(&response));
+            if let Some(key) = key
+                && cacheable
+            {
+                match layer.ttl {
+                    Some(ttl) => layer.cache.put_with_ttl(key, response.clone(), ttl),
+                    None => layer.cache.put(key, response.clone()),
+                };
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::future::Ready;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::MockClock;
+
+    // echoes the path back with the number of calls it has taken so far
+    #[derive(Clone, Default)]
+    struct Echo(Arc<AtomicUsize>);
+
+    impl Service<&'static str> for Echo {
+        type Response = String;
+        type Error = Infallible;
+        type Future = Ready<Result<String, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, path: &'static str) -> Self::Future {
+            let calls = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            future::ready(Ok(format!("{path}#{calls}")))
+        }
+    }
+
+    // paths under /live are never cached
+    fn by_path(path: &&'static str) -> Option<&'static str> {
+        (!path.starts_with("/live")).then_some(*path)
+    }
+
+    #[tokio::test]
+    async fn repeated_requests_are_answered_from_the_cache() {
+        let echo = Echo::default();
+        let mut service = CacheLayer::new(LruCache::new(8), by_path).layer(echo.clone());
+
+        assert_eq!(service.call("/a").await.unwrap(), "/a#1");
+        assert_eq!(service.call("/a").await.unwrap(), "/a#1");
+        assert_eq!(service.call("/live").await.unwrap(), "/live#2");
+        assert_eq!(service.call("/live").await.unwrap(), "/live#3");
+        assert_eq!(echo.0.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn ttl_and_predicate_limit_what_is_kept() {
+        let clock = MockClock::new();
+        let cache = LruCache::builder().clock(clock.clone()).build().unwrap();
+        let layer = CacheLayer::new(cache, by_path)
+            .time_to_live(Duration::from_secs(10))
+            .cache_if(|response: &String| !response.starts_with("/skip"));
+        let mut service = layer.layer(Echo::default());
+
+        service.call("/a").await.unwrap();
+        service.call("/skip").await.unwrap();
+        assert_eq!(layer.cache().len(), 1);
+
+        clock.advance(Duration::from_secs(11));
+        assert_eq!(service.call("/a").await.unwrap(), "/a#3");
+    }
+}
@@ -1,121 +1,3313 @@
-use std::collections::{HashMap, VecDeque};
-use std::hash::Hash;
-use std::sync::RwLock;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use hashbrown::HashTable;
+
+use doorkeeper::Doorkeeper;
+use events::{EventRef, Subscriber, ToEvent};
+use group::{GroupLink, GroupShared};
+use hot::HotKeys;
+use lock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use mrc::HitRateCurve;
+use policy::Ghosts;
+use priority::Classes;
+use refresh::Refresh;
+use stats::{LockWaits, StatsCounter};
+use trace::TraceWriter;
+use tuning::Tuner;
+
+#[cfg(feature = "admin")]
+mod admin;
+#[cfg(feature = "async")]
+mod async_cache;
+mod batch;
+mod builder;
+mod cache;
+mod clock;
+#[cfg(feature = "compression")]
+mod compressed;
+#[cfg(feature = "disk")]
+mod disk;
+mod doorkeeper;
+mod entry;
+mod events;
+mod group;
+mod guard;
+mod hot;
+#[cfg(feature = "redis-invalidation")]
+mod invalidation;
+mod janitor;
+#[cfg(feature = "tower")]
+mod layer;
+mod loader;
+mod lock;
+mod mrc;
+mod negative;
+mod policy;
+mod priority;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod refresh;
+#[cfg(feature = "serde")]
+mod serialize;
+mod sharded;
+mod size;
+#[cfg(feature = "sled")]
+mod sled_store;
+mod snapshot;
+mod stats;
+mod store;
+mod tiered;
+mod trace;
+mod tuning;
+
+#[cfg(feature = "admin")]
+pub use admin::{AdminHandler, AdminResponse};
+#[cfg(feature = "async")]
+pub use async_cache::AsyncLruCache;
+#[cfg(feature = "async")]
+pub use batch::AsyncBatchLoader;
+pub use batch::BatchLoader;
+pub use builder::{BuildError, CacheBuilder};
+pub use cache::{Cache, CacheConfig, DynCache, NoopCache};
+pub use clock::{Clock, MockClock, SystemClock};
+#[cfg(feature = "compression")]
+pub use compressed::{Compressed, CompressedCache};
+#[cfg(feature = "disk")]
+pub use disk::DiskTier;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use events::CacheEvent;
+#[cfg(feature = "async")]
+pub use events::EvictionStream;
+pub use group::CacheGroup;
+pub use guard::ValueGuard;
+#[cfg(feature = "redis-invalidation")]
+pub use invalidation::RedisInvalidation;
+#[cfg(feature = "tower")]
+pub use layer::{CacheLayer, CacheService};
+pub use loader::{CacheLoader, LoadingCache};
+pub use negative::{Cached, Lookup, NegativeLruCache};
+pub use policy::{
+    ArcPolicy, EvictionPolicy, Fifo, Gdsf, Lfu, Lru, LruK, Mru, Sampled, SecondChance, Slru,
+    TinyLfu, TwoQueue,
+};
+pub use priority::Priority;
+#[cfg(feature = "serde")]
+pub use serialize::CacheSnapshot;
+pub use sharded::{ShardStats, ShardedLruCache};
+pub use size::HeapSize;
+#[cfg(feature = "sled")]
+pub use sled_store::SledStore;
+pub use snapshot::Encode;
+pub use stats::{CacheStats, HitRateWindow, LockContention};
+use store::Backlog;
+pub use store::CacheStore;
+pub use tiered::{Tier, TieredCache};
+pub use trace::{TraceOp, TraceReader, replay};
+
+// marks a missing link in the recency list
+const NIL: usize = usize::MAX;
+
+// capacity of a cache that never evicts on insert
+const UNBOUNDED: usize = usize::MAX;
 
 // cache struct
-pub struct LruCache<K, V> {
+//
+// the state sits behind an Arc so a `CacheGroup` can hold a weak handle to
+// it for coordinated eviction
+pub struct LruCache<K, V, S = RandomState> {
+    inner: Arc<RwLock<CacheState<K, V, S>>>,
+    group: OnceLock<Arc<GroupShared>>,
+    waits: LockWaits,
+    loads: Loads<K, V>,
+    // ops a write-behind queue could not take under the lock
+    backlog: Option<Arc<Backlog<K, V>>>,
+}
+
+// loads in flight for `get_or_load`, one cell per missing key
+type Loads<K, V> = Mutex<HashMap<K, Arc<OnceLock<Option<V>>>>>;
+
+// sizes an entry for weight based eviction
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> u64 + Send + Sync>;
+
+// handed entries leaving the cache for one particular reason
+type Listener<K, V> = Box<dyn Fn(K, V) + Send + Sync>;
+
+// sees every entry leaving the cache along with the reason
+type EvictionListener<K, V> = Box<dyn Fn(&K, &V, RemovalCause) + Send + Sync>;
+
+// cache storing values behind an Arc, a get only bumps a reference count so
+// multi megabyte values are never copied and V itself need not be Clone
+pub type SharedLruCache<K, V, S = RandomState> = LruCache<K, Arc<V>, S>;
+
+// a single step of an atomic batch, see `LruCache::apply`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheOp<K, V> {
+    Put(K, V),
+    Remove(K),
+    // promote the key if it is present
+    Touch(K),
+}
+
+// why an entry left the cache, see `CacheBuilder::eviction_listener`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RemovalCause {
+    // pushed out to make room, or refused for its weight
+    CapacityEvicted,
+    Expired,
+    // removed by a call like `remove`, `retain` or `clear`
+    Explicit,
+    // the value was overwritten by a new one for the same key
+    Replaced,
+}
+
+// value returned by `LruCache::get_stale_ok`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MaybeStale<V> {
+    Fresh(V),
+    // expired but within the grace period. `refresh` is true for exactly one
+    // caller, which is expected to write a new value
+    Stale { value: V, refresh: bool },
+}
+
+impl<V> MaybeStale<V> {
+    pub fn is_stale(&self) -> bool {
+        matches!(self, MaybeStale::Stale { .. })
+    }
+
+    pub fn into_value(self) -> V {
+        match self {
+            MaybeStale::Fresh(value) | MaybeStale::Stale { value, .. } => value,
+        }
+    }
+}
+
+// structure to keep state of the cache
+//
+// entries live in a slab and are chained into a doubly linked list by index,
+// the map only points a key at its slot so promotion and eviction are O(1).
+// each node remembers its key hash, so the table can rehash and drop slots
+// without ever hashing a key again
+struct CacheState<K, V, S> {
     capacity: usize,
-    inner: RwLock<CacheState<K, V>>,
+    // entries weigh 1 each unless a weigher is configured
+    weigher: Option<Weigher<K, V>>,
+    // recomputation cost handed to the policy, 1 unless configured
+    cost: Option<Weigher<K, V>>,
+    // written through on puts and removes
+    backing: Option<Arc<dyn CacheStore<K, V>>>,
+    max_weight: u64,
+    weight: u64,
+    // heavier entries are refused rather than admitted
+    max_entry_weight: u64,
+    on_reject: Option<Listener<K, V>>,
+    listener: Option<EvictionListener<K, V>>,
+    // channels handed out by `subscribe`, and how to copy an event for them
+    subscribers: Mutex<Vec<Subscriber<K, V>>>,
+    to_event: Option<ToEvent<K, V>>,
+    stats: StatsCounter,
+    // counts the most requested keys, if tracking is enabled
+    hot_keys: Option<HotKeys<K>>,
+    // what-if hit rates for other capacities, if sampling is enabled
+    hit_curve: Option<HitRateCurve>,
+    // every get and put, if a trace is being recorded
+    trace: Option<TraceWriter>,
+    // set once the cache joins a shared budget
+    group: Option<GroupLink>,
+    // default lifetime of every inserted entry
+    time_to_live: Option<Duration>,
+    // entries not used for this long expire as well
+    time_to_idle: Option<Duration>,
+    // entries with an expiry, lets `len` skip the scan when there are none
+    expiring: usize,
+    on_expire: Option<Listener<K, V>>,
+    // dropped with the state, which stops the sweeping thread
+    janitor: Option<Sender<()>>,
+    // time source for every expiry decision
+    clock: Arc<dyn Clock>,
+    // ttls are spread by up to this percentage either way
+    ttl_jitter: u32,
+    jitter_seed: AtomicU64,
+    // background reload of entries that have been around for a while
+    refresh: Option<Refresh<K, V>>,
+    // how long expired entries can still be read with `get_stale_ok`
+    grace: Duration,
+    map: HashTable<usize>,
+    hasher: S,
+    entries: Vec<Option<Node<K, V>>>,
+    free: Vec<usize>,
+    // picks eviction victims instead of the recency list, if set
+    policy: Option<Box<dyn EvictionPolicy>>,
+    // one list per priority, once an entry was given one
+    classes: Option<Classes>,
+    // new keys seen once, if admission needs a second sighting
+    doorkeeper: Option<Doorkeeper>,
+    // hashes of keys recently evicted for room, and how many to keep
+    ghosts: Option<(Ghosts, usize)>,
+    // adjusts the capacity from the lookups, if auto tuning is on
+    tuner: Option<Tuner>,
+    // least recently used
+    head: usize,
+    // most recently used
+    tail: usize,
+}
+
+// a single slot in the slab
+struct Node<K, V> {
+    key: K,
+    value: V,
+    hash: u64,
+    weight: u64,
+    // entries without one live until evicted
+    expires_at: Option<Instant>,
+    // lifetime the expiry was computed from, restarted by `touch_ttl`
+    ttl: Option<Duration>,
+    // pushed back on every use while a time to idle is configured
+    idle_at: Option<Instant>,
+    // a use after this asks for a reload, if refreshing is configured
+    refresh_at: Option<Instant>,
+    // a reload is in flight, so uses do not queue up another one
+    refreshing: bool,
+    // kept out of eviction, and out of the policy and priority classes
+    pinned: bool,
+    // last put or reload, for the age shown by `dump_json`
+    written_at: Instant,
+    prev: usize,
+    next: usize,
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> CacheState<K, V, S> {
+    // slot holding the key, hashing it once
+    fn find<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find_hashed(self.hasher.hash_one(key), key)
+    }
+
+    fn find_hashed<Q>(&self, hash: u64, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map
+            .find(hash, |&idx| self.node(idx).key.borrow() == key)
+            .copied()
+    }
+
+    // slot holding the key unless it has expired, for lookups that must not
+    // change anything
+    fn find_live<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).filter(|&idx| !self.is_expired(idx))
+    }
+
+    // slot holding the key, for lookups under the write lock. an expired
+    // entry reads as missing and is dropped once its grace period is over
+    fn find_fresh<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find_fresh_hashed(self.hasher.hash_one(key), key)
+    }
+
+    fn find_fresh_hashed<Q>(&mut self, hash: u64, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.apply_refreshes();
+
+        let idx = self.find_hashed(hash, key)?;
+        if self.is_expired(idx) {
+            if self.is_dead(idx) {
+                self.expire(idx);
+            }
+            return None;
+        }
+        Some(idx)
+    }
+
+    // like `find_hashed`, but an expired entry is evicted on the way and
+    // reported missing, even within its grace period. for writes that
+    // replace the entry anyway
+    fn find_or_expire<Q>(&mut self, hash: u64, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // reloads may have replaced values since the last write
+        self.apply_refreshes();
+
+        let idx = self.find_hashed(hash, key)?;
+        if self.is_expired(idx) {
+            self.expire(idx);
+            return None;
+        }
+        Some(idx)
+    }
+
+    // store the values reloaded in the background. a failed reload leaves
+    // the entry stale so its next use tries again, results for entries
+    // written or expired in the meantime are dropped
+    fn apply_refreshes(&mut self) {
+        let Some(refresh) = &self.refresh else {
+            return;
+        };
+
+        for (key, value) in refresh.finished() {
+            let Some(idx) = self.find(&key) else {
+                continue;
+            };
+            if !self.node(idx).refreshing || self.is_expired(idx) {
+                continue;
+            }
+
+            let Some(value) = value else {
+                self.node_mut(idx).refreshing = false;
+                continue;
+            };
+            // counts as a write, but not as a use
+            let old = std::mem::replace(&mut self.node_mut(idx).value, value);
+            self.notify(&key, &old, RemovalCause::Replaced);
+            self.emit(EventRef::Insert(&key, &self.node(idx).value));
+            let ttl = self.node(idx).ttl;
+            self.set_ttl(idx, ttl);
+            self.mark_written(idx);
+            self.reweigh(idx);
+            self.settle(idx);
+        }
+    }
+
+    // drop an expired entry and tell the listener about it
+    fn expire(&mut self, idx: usize) {
+        let (key, value) = self.evict(idx);
+        self.notify(&key, &value, RemovalCause::Expired);
+        if let Some(on_expire) = &self.on_expire {
+            on_expire(key, value);
+        }
+    }
+
+    // remove every entry that has expired by now, returning how many
+    // entries still within their grace period are kept
+    fn purge_expired(&mut self) -> usize {
+        let Some(cutoff) = self
+            .expiry_cutoff()
+            .and_then(|now| now.checked_sub(self.grace))
+        else {
+            return 0;
+        };
+
+        let mut purged = 0;
+        for idx in 0..self.entries.len() {
+            if self.entries[idx]
+                .as_ref()
+                .is_some_and(|node| node.is_expired_at(Some(cutoff)))
+            {
+                self.expire(idx);
+                purged += 1;
+            }
+        }
+        purged
+    }
+
+    // unlink a slot and forget its key
+    fn evict(&mut self, idx: usize) -> (K, V) {
+        let entry = self.remove_node(idx);
+        // matched by slot, the key itself is never compared
+        if let Ok(found) = self.map.find_entry(entry.hash, |&i| i == idx) {
+            found.remove();
+        }
+        (entry.key, entry.value)
+    }
+
+    // evict an entry to make room for others
+    fn evict_to_fit(&mut self, idx: usize) {
+        if let Some((ghosts, len)) = &mut self.ghosts {
+            ghosts.push(self.entries[idx].as_ref().expect("victims are cached").hash);
+            ghosts.truncate(*len);
+        }
+        let (key, value) = self.evict(idx);
+        self.notify(&key, &value, RemovalCause::CapacityEvicted);
+    }
+
+    // evict until both the entry count and the total weight are back within
+    // bounds
+    fn trim(&mut self) {
+        while self.map.len() > self.capacity || self.weight > self.max_weight {
+            let Some(victim) = self.victim() else {
+                break;
+            };
+            self.evict_to_fit(victim);
+        }
+    }
+
+    // overwrite a value as a use of the entry, handing back the old one
+    fn replace(&mut self, idx: usize, value: V) -> V {
+        let old = std::mem::replace(&mut self.node_mut(idx).value, value);
+        let node = self.node(idx);
+        self.notify(&node.key, &old, RemovalCause::Replaced);
+        self.emit(EventRef::Insert(&node.key, &node.value));
+        self.update(idx, |_| ());
+        old
+    }
+
+    // write access to a value that counts as a use, the entry is reweighed
+    // afterwards and may get evicted if it grew past the weight budget
+    fn update<R>(&mut self, idx: usize, f: impl FnOnce(&mut V) -> R) -> R {
+        self.promote(idx);
+        let out = f(&mut self.node_mut(idx).value);
+        self.reweigh(idx);
+        self.settle(idx);
+        out
+    }
+
+    // `update` for a caller's change, which reaches the backing store
+    // before the entry is reweighed and perhaps rejected
+    fn update_through<R>(&mut self, idx: usize, f: impl FnOnce(&mut V) -> R) -> R {
+        self.promote(idx);
+        let out = f(&mut self.node_mut(idx).value);
+        self.write_back(idx);
+        self.reweigh(idx);
+        self.settle(idx);
+        out
+    }
+
+    fn write_through(&self, key: &K, value: &V) {
+        if let Some(backing) = &self.backing {
+            backing.write(key, value);
+        }
+    }
+
+    // the entry's value as it is now, after being changed in place
+    fn write_back(&self, idx: usize) {
+        let node = self.node(idx);
+        self.write_through(&node.key, &node.value);
+    }
+
+    fn delete_through(&self, key: &K) {
+        if let Some(backing) = &self.backing {
+            backing.delete(key);
+        }
+    }
+
+    // enforce the bounds after an entry was inserted or changed. an entry
+    // over the per-entry limit, or heavier than the whole budget, is
+    // rejected on its own instead of flushing everything else out
+    fn settle(&mut self, idx: usize) {
+        let weight = self.node(idx).weight;
+        if weight > self.max_entry_weight || weight > self.max_weight {
+            let (key, value) = self.evict(idx);
+            self.notify(&key, &value, RemovalCause::CapacityEvicted);
+            if let Some(on_reject) = &self.on_reject {
+                on_reject(key, value);
+            }
+        }
+        self.trim();
+    }
+
+    // refresh the weight of an entry whose value may have changed in place
+    fn reweigh(&mut self, idx: usize) {
+        if self.weigher.is_some() {
+            let weight = self.weigh(&self.node(idx).key, &self.node(idx).value);
+            let node = self.entries[idx]
+                .as_mut()
+                .expect("linked slot must be occupied");
+            let old = std::mem::replace(&mut node.weight, weight);
+            self.set_weight(self.weight - old + weight);
+        }
+        self.report_weight(idx);
+    }
+
+    // lookup that counts as a use of the entry
+    fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let idx = self.lookup(key)?;
+        Some(&self.node(idx).value)
+    }
+
+    // find and promote the entry, reporting the hit or miss
+    fn lookup<Q>(&mut self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let Some(idx) = self.find_fresh_hashed(hash, key) else {
+            self.record_miss(hash);
+            return None;
+        };
+
+        // most recently used
+        self.promote(idx);
+        self.record_hit(idx);
+        Some(idx)
+    }
+
+    // everything that counts lookups hears about this one
+    fn record_hit(&mut self, idx: usize) {
+        self.stats.record_recent(true);
+        self.record_hot(idx);
+        let hash = self.node(idx).hash;
+        if let Some(curve) = &mut self.hit_curve {
+            curve.record(hash);
+        }
+        self.record_trace(TraceOp::Get(hash));
+        self.report_hit(idx);
+        self.tune(true, false);
+    }
+
+    // the part of `record_hit` that needs no write lock
+    fn report_hit(&self, idx: usize) {
+        self.stats.hit();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(hash = self.node(idx).hash, hit = true, "lookup");
+        self.emit(EventRef::Hit(&self.node(idx).key));
+    }
+
+    fn record_trace(&mut self, op: TraceOp) {
+        if let Some(trace) = &mut self.trace {
+            trace.record(op);
+        }
+    }
+
+    fn record_miss(&mut self, hash: u64) {
+        self.stats.record_recent(false);
+        let ghost_hit = self
+            .ghosts
+            .as_mut()
+            .is_some_and(|(ghosts, _)| ghosts.remove(hash));
+        if ghost_hit {
+            self.stats.ghost_hit();
+        }
+        self.tune(false, ghost_hit);
+        if let Some(curve) = &mut self.hit_curve {
+            curve.record(hash);
+        }
+        self.record_trace(TraceOp::Get(hash));
+        self.report_miss(hash);
+    }
+
+    fn report_miss(&self, hash: u64) {
+        self.stats.miss();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(hash, hit = false, "lookup");
+        self.emit(EventRef::Miss(hash));
+    }
+
+    // a smaller capacity is left to the next insert to enforce, evicting
+    // here could take the entry the lookup is about to return
+    fn tune(&mut self, hit: bool, ghost_hit: bool) {
+        if let Some(tuner) = &mut self.tuner
+            && let Some(capacity) = tuner.observe(self.capacity, hit, ghost_hit)
+        {
+            self.resize(capacity);
+        }
+    }
+
+    // a new bound, passed on so the policy can size its segments. evicting
+    // down to it is up to the caller
+    fn resize(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        if let Some(policy) = &mut self.policy {
+            policy.on_resize(capacity);
+        }
+    }
+
+    // whether a lookup can run under the read lock, which takes a policy
+    // that ignores accesses and nothing else that is updated on every use
+    fn shared_lookups(&self) -> bool {
+        self.policy
+            .as_ref()
+            .is_some_and(|policy| !policy.tracks_access())
+            && self.time_to_idle.is_none()
+            && self.refresh.is_none()
+            && self.stats.window.is_none()
+            && self.hot_keys.is_none()
+            && self.hit_curve.is_none()
+            && self.trace.is_none()
+            && self.ghosts.is_none()
+            && self.tuner.is_none()
+    }
+
+    // a lookup under the read lock, for when `shared_lookups` holds. None
+    // if it has to be redone under the write lock, which drops an expired
+    // entry
+    fn get_shared<Q>(&self, key: &Q) -> Option<Option<&V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let Some(idx) = self.find_hashed(hash, key) else {
+            self.report_miss(hash);
+            return Some(None);
+        };
+        if self.is_expired(idx) {
+            return None;
+        }
+        self.note_access(idx);
+        self.report_hit(idx);
+        Some(Some(&self.node(idx).value))
+    }
+
+    // a borrowed key only reaches the store when it is cached, see
+    // `remove_owned` for one that may not be
+    fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let idx = self.find_or_expire(self.hasher.hash_one(key), key)?;
+        let (key, value) = self.evict_explicit(idx);
+        self.delete_through(&key);
+        Some(value)
+    }
+
+    // priming puts, the first entry ends up most recently used. the
+    // listener and subscribers are not told about entries the warm-up
+    // itself pushes out, `on_reject` and `on_expire` still hear of the
+    // entries they are for. `through` writes the values to the backing
+    // store, in the order they are put
+    fn warm_up(&mut self, entries: Vec<(K, V)>, through: bool) {
+        if through {
+            for (key, value) in entries.iter().rev() {
+                self.write_through(key, value);
+            }
+        }
+        self.restore(
+            entries
+                .into_iter()
+                .rev()
+                .map(|(key, value)| (key, value, None)),
+        );
+    }
+
+    // the warm-up for entries read back from a snapshot, oldest first and
+    // each with the time it had left. one saved without any gets the
+    // cache's default
+    fn restore<I>(&mut self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V, Option<Duration>)>,
+    {
+        let listener = self.listener.take();
+        let to_event = self.to_event.take();
+        for (key, value, ttl) in entries {
+            let ttl = ttl.or(self.time_to_live);
+            self.fill(key, value, ttl, None);
+        }
+        self.listener = listener;
+        self.to_event = to_event;
+    }
+
+    // an owned key reaches the backing store even when it is not cached
+    fn remove_owned(&mut self, key: &K) -> Option<V> {
+        self.delete_through(key);
+        let idx = self.find_or_expire(self.hasher.hash_one(key), key)?;
+        Some(self.evict_explicit(idx).1)
+    }
+
+    fn notify(&self, key: &K, value: &V, cause: RemovalCause) {
+        match cause {
+            RemovalCause::CapacityEvicted => self.stats.eviction(),
+            RemovalCause::Expired => self.stats.expiration(),
+            RemovalCause::Explicit | RemovalCause::Replaced => {}
+        }
+        // the key is only rehashed when the event is enabled
+        #[cfg(feature = "tracing")]
+        tracing::debug!(hash = self.hasher.hash_one(key), ?cause, "removed");
+        if let Some(listener) = &self.listener {
+            listener(key, value, cause);
+        }
+        self.emit(EventRef::Removed(key, value, cause));
+    }
+
+    fn record_hot(&mut self, idx: usize) {
+        if let Some(hot) = &mut self.hot_keys {
+            let node = self.entries[idx]
+                .as_ref()
+                .expect("linked slot must be occupied");
+            hot.record(node.hash, &node.key);
+        }
+    }
+
+    // removal asked for by the caller
+    fn evict_explicit(&mut self, idx: usize) -> (K, V) {
+        let (key, value) = self.evict(idx);
+        self.notify(&key, &value, RemovalCause::Explicit);
+        (key, value)
+    }
+
+    fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        for idx in 0..self.entries.len() {
+            let keep = match self.entries[idx].as_mut() {
+                Some(entry) => f(&entry.key, &mut entry.value),
+                None => continue,
+            };
+
+            if keep {
+                self.reweigh(idx);
+            } else {
+                self.evict_explicit(idx);
+            }
+        }
+        self.trim();
+    }
+
+    // insert or update under an already held lock
+    fn put(&mut self, key: K, value: V) -> Option<V> {
+        self.put_with_ttl(key, value, self.time_to_live)
+    }
+
+    // an update replaces the expiry as well, an expired entry being
+    // overwritten counts as missing
+    fn put_with_ttl(&mut self, key: K, value: V, ttl: Option<Duration>) -> Option<V> {
+        self.store(key, value, ttl, None)
+    }
+
+    // a put that also moves the entry to a priority class, an overwrite
+    // without one keeps the entry's class
+    fn store(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+        priority: Option<Priority>,
+    ) -> Option<V> {
+        self.write_through(&key, &value);
+        self.fill(key, value, ttl, priority)
+    }
+
+    // a put that leaves the backing store alone, for values read from it
+    fn fill(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<Duration>,
+        priority: Option<Priority>,
+    ) -> Option<V> {
+        if self.capacity == 0 {
+            return None;
+        }
+
+        let hash = self.hasher.hash_one(&key);
+        self.record_trace(TraceOp::Put(hash));
+        if let Some(idx) = self.find_or_expire(hash, &key) {
+            self.set_ttl(idx, ttl);
+            self.mark_written(idx);
+            self.set_priority(idx, priority);
+            return Some(self.replace(idx, value));
+        }
+        if !self.admits(hash, &key, &value) {
+            return None;
+        }
+
+        let idx = self.insert_new(hash, key, value);
+        self.set_ttl(idx, ttl);
+        self.set_priority(idx, priority);
+        self.settle(idx);
+        None
+    }
+
+    // whether the doorkeeper lets a new key in, it only gets a say when the
+    // key would evict something
+    fn admits(&mut self, hash: u64, key: &K, value: &V) -> bool {
+        if self.doorkeeper.is_none() {
+            return true;
+        }
+        let full = self.map.len() >= self.capacity
+            || self.weight.saturating_add(self.weigh(key, value)) > self.max_weight;
+        match &mut self.doorkeeper {
+            Some(door) if full => door.check_and_add(hash),
+            _ => true,
+        }
+    }
+
+    // insert a key known to be absent, making room first if the cache is
+    // full. the caller settles the new entry once it is done with it, an
+    // oversized one evicts nothing as it is about to be rejected anyway
+    fn insert_new(&mut self, hash: u64, key: K, value: V) -> usize {
+        let weight = self.weigh(&key, &value);
+        let oversized = weight > self.max_entry_weight || weight > self.max_weight;
+        while !oversized
+            && (self.map.len() >= self.capacity
+                || self.weight.saturating_add(weight) > self.max_weight)
+        {
+            let Some(victim) = self.victim() else {
+                break;
+            };
+            self.evict_to_fit(victim);
+        }
+
+        let idx = self.insert_node(hash, key, value, weight);
+        let entries = &self.entries;
+        self.map.insert_unique(hash, idx, |&i| {
+            entries[i]
+                .as_ref()
+                .expect("indexed slot must be occupied")
+                .hash
+        });
+        self.stats.insertion();
+        self.record_hot(idx);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(hash, "inserted");
+        let node = self.node(idx);
+        self.emit(EventRef::Insert(&node.key, &node.value));
+        idx
+    }
 }
 
-// structure to keep state of the cache
-struct CacheState<K, V> {
-    map: HashMap<K, V>,
-    order: VecDeque<K>,
-}
+impl<K, V, S> CacheState<K, V, S> {
+    fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        // an unbounded cache grows on demand instead
+        let prealloc = if capacity == UNBOUNDED { 0 } else { capacity };
+
+        Self {
+            capacity,
+            weigher: None,
+            cost: None,
+            backing: None,
+            max_weight: u64::MAX,
+            weight: 0,
+            max_entry_weight: u64::MAX,
+            on_reject: None,
+            listener: None,
+            subscribers: Mutex::new(Vec::new()),
+            to_event: None,
+            stats: StatsCounter::default(),
+            hot_keys: None,
+            hit_curve: None,
+            trace: None,
+            group: None,
+            time_to_live: None,
+            time_to_idle: None,
+            expiring: 0,
+            on_expire: None,
+            janitor: None,
+            clock: Arc::new(SystemClock),
+            ttl_jitter: 0,
+            jitter_seed: AtomicU64::new(0),
+            refresh: None,
+            grace: Duration::ZERO,
+            map: HashTable::with_capacity(prealloc),
+            hasher,
+            entries: Vec::with_capacity(prealloc),
+            free: Vec::new(),
+            policy: None,
+            classes: None,
+            doorkeeper: None,
+            ghosts: None,
+            tuner: None,
+            head: NIL,
+            tail: NIL,
+        }
+    }
+
+    // hand an event to every subscriber, dropping those whose receiver is
+    // gone
+    fn emit(&self, event: EventRef<'_, K, V>) {
+        let Some(to_event) = self.to_event else {
+            return;
+        };
+
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.deliver(&event, to_event));
+    }
+
+    // every change of the total goes through here so a group sees it too
+    fn set_weight(&mut self, weight: u64) {
+        if let Some(group) = &self.group {
+            group.charge(self.weight, weight);
+        }
+        self.weight = weight;
+    }
+
+    fn weigh(&self, key: &K, value: &V) -> u64 {
+        self.weigher
+            .as_ref()
+            .map_or(1, |weigher| weigher(key, value))
+    }
+
+    fn node(&self, idx: usize) -> &Node<K, V> {
+        self.entries[idx]
+            .as_ref()
+            .expect("linked slot must be occupied")
+    }
+
+    // the clock is only read for entries that can expire at all
+    fn is_expired(&self, idx: usize) -> bool {
+        let node = self.node(idx);
+        node.can_expire() && node.is_expired_at(Some(self.clock.now()))
+    }
+
+    // expired and past the grace period, nothing may read it any more
+    fn is_dead(&self, idx: usize) -> bool {
+        let node = self.node(idx);
+        node.can_expire() && node.is_expired_at(self.clock.now().checked_sub(self.grace))
+    }
+
+    // a ttl too long to represent never expires
+    fn expiry_after(&self, ttl: Duration) -> Option<Instant> {
+        self.clock.now().checked_add(self.jittered(ttl))
+    }
+
+    // scales a ttl by a random factor within the configured jitter, so
+    // entries written together do not all expire together
+    fn jittered(&self, ttl: Duration) -> Duration {
+        if self.ttl_jitter == 0 {
+            return ttl;
+        }
+
+        // splitmix64, statistical quality is all that matters here
+        let mut z = self
+            .jitter_seed
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        // uniform in [-1, 1)
+        let unit = (z >> 11) as f64 / (1u64 << 52) as f64 - 1.0;
+        let factor = 1.0 + f64::from(self.ttl_jitter) / 100.0 * unit;
+        Duration::try_from_secs_f64(ttl.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+    }
+
+    // the entry expires `ttl` from now, or never without one
+    fn set_ttl(&mut self, idx: usize, ttl: Option<Duration>) {
+        let expires_at = ttl.and_then(|ttl| self.expiry_after(ttl));
+        self.retime(idx, |node| {
+            node.ttl = ttl;
+            node.expires_at = expires_at;
+        });
+    }
+
+    // restart the idle timer of an entry that was just used
+    fn refresh_idle(&mut self, idx: usize) {
+        if let Some(tti) = self.time_to_idle {
+            let idle_at = self.clock.now().checked_add(tti);
+            self.retime(idx, |node| node.idle_at = idle_at);
+        }
+    }
+
+    // change the deadlines of a node, keeping `expiring` in step
+    fn retime(&mut self, idx: usize, f: impl FnOnce(&mut Node<K, V>)) {
+        let node = self.node_mut(idx);
+        let before = node.can_expire();
+        f(node);
+        let after = node.can_expire();
+        self.expiring = self.expiring + usize::from(after) - usize::from(before);
+    }
+
+    // point in time entries count as expired at, None when nothing can
+    fn expiry_cutoff(&self) -> Option<Instant> {
+        (self.expiring > 0).then(|| self.clock.now())
+    }
+
+    // entries that have not expired yet
+    fn live_len(&self) -> usize {
+        match self.expiry_cutoff() {
+            Some(now) => self.iter_live(Some(now)).count(),
+            None => self.map.len(),
+        }
+    }
+
+    // like `iter_mru`, skipping entries expired at `cutoff`
+    fn iter_live(&self, cutoff: Option<Instant>) -> impl Iterator<Item = &Node<K, V>> {
+        self.iter_mru()
+            .filter(move |node| !node.is_expired_at(cutoff))
+    }
+
+    // walks the recency list from most to least recently used
+    fn iter_mru(&self) -> impl Iterator<Item = &Node<K, V>> {
+        std::iter::successors((self.tail != NIL).then(|| self.node(self.tail)), |entry| {
+            (entry.prev != NIL).then(|| self.node(entry.prev))
+        })
+    }
+
+    fn node_mut(&mut self, idx: usize) -> &mut Node<K, V> {
+        self.entries[idx]
+            .as_mut()
+            .expect("linked slot must be occupied")
+    }
+
+    // take a slot out of the recency list, leaving it in the slab
+    fn unlink(&mut self, idx: usize) {
+        let (prev, next) = {
+            let entry = self.node(idx);
+            (entry.prev, entry.next)
+        };
+
+        if prev == NIL {
+            self.head = next;
+        } else {
+            self.node_mut(prev).next = next;
+        }
+
+        if next == NIL {
+            self.tail = prev;
+        } else {
+            self.node_mut(next).prev = prev;
+        }
+    }
+
+    // append a slot as the most recently used one
+    fn push_back(&mut self, idx: usize) {
+        let tail = self.tail;
+        {
+            let entry = self.node_mut(idx);
+            entry.prev = tail;
+            entry.next = NIL;
+        }
+
+        if tail == NIL {
+            self.head = idx;
+        } else {
+            self.node_mut(tail).next = idx;
+        }
+        self.tail = idx;
+    }
+
+    // a use seen by a policy that does not track accesses, which may come
+    // under the read lock
+    fn note_access(&self, idx: usize) {
+        if let Some(policy) = &self.policy
+            && !self.node(idx).pinned
+        {
+            policy.on_shared_access(idx);
+        }
+    }
+
+    // record a use of the entry
+    fn promote(&mut self, idx: usize) {
+        // a policy that ignores accesses keeps the list in insertion order
+        let tracks_access = self.policy.as_ref().is_none_or(|p| p.tracks_access());
+        if self.tail != idx && tracks_access {
+            self.unlink(idx);
+            self.push_back(idx);
+        }
+        if tracks_access && !self.node(idx).pinned {
+            if let Some(policy) = &mut self.policy {
+                policy.on_access(idx);
+            }
+            if let Some(classes) = &mut self.classes {
+                classes.touch(idx);
+            }
+        } else if !tracks_access {
+            self.note_access(idx);
+        }
+        self.refresh_idle(idx);
+        self.refresh_if_stale(idx);
+    }
+
+    // a fresh value starts its age and the wait for the next reload over
+    fn mark_written(&mut self, idx: usize) {
+        let now = self.clock.now();
+        self.node_mut(idx).written_at = now;
+        self.schedule_refresh(idx, now);
+    }
+
+    fn schedule_refresh(&mut self, idx: usize, now: Instant) {
+        let Some(refresh) = &self.refresh else {
+            return;
+        };
+
+        let refresh_at = now.checked_add(refresh.after);
+        let node = self.node_mut(idx);
+        node.refresh_at = refresh_at;
+        node.refreshing = false;
+    }
+
+    // queue a reload of a used entry that is due for one, the current value
+    // keeps being served until it lands
+    fn refresh_if_stale(&mut self, idx: usize) {
+        let Some(refresh) = &self.refresh else {
+            return;
+        };
+
+        let node = self.node(idx);
+        if node.refreshing || node.refresh_at.is_none_or(|at| at > self.clock.now()) {
+            return;
+        }
+        refresh.request(&node.key);
+        self.node_mut(idx).refreshing = true;
+    }
+
+    // tell the policy what an entry weighs and costs now
+    fn report_weight(&mut self, idx: usize) {
+        let Some(policy) = &mut self.policy else {
+            return;
+        };
+        let node = self.entries[idx]
+            .as_ref()
+            .expect("linked slot must be occupied");
+        let cost = self
+            .cost
+            .as_ref()
+            .map_or(1, |cost| cost(&node.key, &node.value));
+        policy.on_weigh(idx, node.weight, cost);
+    }
+
+    // store a new entry in a free slot and mark it most recently used
+    fn insert_node(&mut self, hash: u64, key: K, value: V, weight: u64) -> usize {
+        self.set_weight(self.weight + weight);
+        let now = self.clock.now();
+        let entry = Node {
+            key,
+            value,
+            hash,
+            weight,
+            expires_at: None,
+            ttl: None,
+            idle_at: None,
+            refresh_at: None,
+            refreshing: false,
+            pinned: false,
+            written_at: now,
+            prev: NIL,
+            next: NIL,
+        };
+
+        let idx = match self.free.pop() {
+            Some(idx) => {
+                self.entries[idx] = Some(entry);
+                idx
+            }
+            None => {
+                self.entries.push(Some(entry));
+                self.entries.len() - 1
+            }
+        };
+
+        self.push_back(idx);
+        if let Some(policy) = &mut self.policy {
+            policy.on_insert(idx, hash);
+        }
+        if let Some(classes) = &mut self.classes {
+            classes.insert(idx, Priority::Normal);
+        }
+        // a cached key is no ghost
+        if let Some((ghosts, _)) = &mut self.ghosts {
+            ghosts.remove(hash);
+        }
+        self.report_weight(idx);
+        self.stats.entries_added(1);
+        self.set_ttl(idx, self.time_to_live);
+        self.refresh_idle(idx);
+        self.schedule_refresh(idx, now);
+        idx
+    }
+
+    fn clear(&mut self) {
+        self.stats.entries_removed(self.map.len());
+        self.map.clear();
+        self.entries.clear();
+        self.free.clear();
+        self.set_weight(0);
+        self.expiring = 0;
+        self.head = NIL;
+        self.tail = NIL;
+        if let Some(policy) = &mut self.policy {
+            policy.clear();
+        }
+        if let Some(classes) = &mut self.classes {
+            classes.clear();
+        }
+        if let Some(door) = &mut self.doorkeeper {
+            door.clear();
+        }
+        if let Some((ghosts, _)) = &mut self.ghosts {
+            ghosts.clear();
+        }
+    }
+
+    // the classes are set up on first use, with every cached entry normal
+    fn set_priority(&mut self, idx: usize, priority: Option<Priority>) {
+        let Some(priority) = priority else {
+            return;
+        };
+        if self.classes.is_none() && priority == Priority::Normal {
+            return;
+        }
+        let classes = match &mut self.classes {
+            Some(classes) => classes,
+            None => {
+                let mut classes = Classes::new();
+                let mut slot = self.head;
+                while slot != NIL {
+                    if self.node(slot).pinned {
+                        classes.assign(slot, Priority::Normal);
+                    } else {
+                        classes.insert(slot, Priority::Normal);
+                    }
+                    slot = self.node(slot).next;
+                }
+                self.classes.insert(classes)
+            }
+        };
+        if self.entries[idx].as_ref().is_some_and(|node| node.pinned) {
+            classes.assign(idx, priority);
+        } else {
+            classes.set(idx, priority);
+        }
+    }
+
+    // take an entry out of eviction, false if it already was
+    fn pin(&mut self, idx: usize) -> bool {
+        if self.node(idx).pinned {
+            return false;
+        }
+        self.untrack(idx);
+        self.node_mut(idx).pinned = true;
+        true
+    }
+
+    // hand a pinned entry back to eviction, as if it had just been inserted
+    fn unpin(&mut self, idx: usize) -> bool {
+        if !self.node(idx).pinned {
+            return false;
+        }
+        self.node_mut(idx).pinned = false;
+        let hash = self.node(idx).hash;
+        if let Some(policy) = &mut self.policy {
+            policy.on_insert(idx, hash);
+        }
+        if let Some(classes) = &mut self.classes {
+            classes.insert(idx, classes.class(idx));
+        }
+        self.report_weight(idx);
+        true
+    }
+
+    // forget a slot in the policy and the classes
+    fn untrack(&mut self, idx: usize) {
+        if let Some(policy) = &mut self.policy {
+            policy.on_remove(idx);
+        }
+        if let Some(classes) = &mut self.classes {
+            classes.remove(idx);
+        }
+    }
+
+    // pinned entries are meant to be few, they are simply skipped
+    fn oldest_unpinned(&self) -> Option<usize> {
+        let mut idx = self.head;
+        while idx != NIL && self.node(idx).pinned {
+            idx = self.node(idx).next;
+        }
+        (idx != NIL).then_some(idx)
+    }
+
+    // next entry to make room, the least recently used one unless a policy
+    // says otherwise
+    fn victim(&mut self) -> Option<usize> {
+        // while more than one class is cached the lowest goes first whatever
+        // the order says, its least recently used entry. the policy is not
+        // asked then, naming a victim can change its state and the pick
+        // could be overruled
+        if let Some(classes) = &self.classes
+            && classes.mixed()
+        {
+            return classes.victim().map(|(_, oldest)| oldest);
+        }
+        match &mut self.policy {
+            Some(policy) => policy.choose_victim(),
+            None => self.oldest_unpinned(),
+        }
+    }
+
+    // drop a slot from the list and the slab, the caller fixes up the map
+    fn remove_node(&mut self, idx: usize) -> Node<K, V> {
+        self.unlink(idx);
+        if !self.node(idx).pinned {
+            self.untrack(idx);
+        }
+        let entry = self.entries[idx]
+            .take()
+            .expect("linked slot must be occupied");
+        self.free.push(idx);
+        self.stats.entries_removed(1);
+        self.set_weight(self.weight - entry.weight);
+        self.expiring -= usize::from(entry.can_expire());
+        entry
+    }
+}
+
+impl<K: HeapSize, V: HeapSize, S> CacheState<K, V, S> {
+    // the lock and everything behind it, see `LruCache::memory_usage`
+    fn memory_usage(&self) -> usize {
+        let fixed = size_of::<RwLock<Self>>();
+        let slab = self.entries.capacity() * size_of::<Option<Node<K, V>>>();
+        // a table bucket holds a slot index and a control byte
+        let table = self.map.capacity() * (size_of::<usize>() + 1);
+        let free = self.free.capacity() * size_of::<usize>();
+        let owned: usize = self
+            .iter_mru()
+            .map(|node| node.key.heap_size() + node.value.heap_size())
+            .sum();
+
+        fixed + slab + table + free + owned
+    }
+}
+
+impl<K, V> Node<K, V> {
+    // whichever of the two deadlines comes first
+    fn deadline(&self) -> Option<Instant> {
+        [self.expires_at, self.idle_at].into_iter().flatten().min()
+    }
+
+    fn can_expire(&self) -> bool {
+        self.expires_at.is_some() || self.idle_at.is_some()
+    }
+
+    // past either deadline
+    fn is_expired_at(&self, cutoff: Option<Instant>) -> bool {
+        let Some(now) = cutoff else {
+            return false;
+        };
+        [self.expires_at, self.idle_at]
+            .into_iter()
+            .flatten()
+            .any(|at| at <= now)
+    }
+}
+
+impl<K: Eq + Hash, V> LruCache<K, V> {
+    // a capacity of 0 caches nothing, puts are dropped and gets always miss
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, RandomState::new())
+    }
+
+    // tracks recency like any other cache but never evicts on insert, it can
+    // be bounded later with `set_capacity` or drained in LRU order
+    pub fn unbounded() -> Self {
+        Self::new(UNBOUNDED)
+    }
+
+    // for anything beyond a plain entry count, e.g. weight based eviction
+    pub fn builder() -> CacheBuilder<K, V> {
+        CacheBuilder::new()
+    }
+}
+
+impl<K: Eq + Hash, V> SharedLruCache<K, V> {
+    pub fn new_shared(capacity: usize) -> Self {
+        Self::new(capacity)
+    }
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> SharedLruCache<K, V, S> {
+    // wraps the value and returns the previous one, if any
+    pub fn put_shared(&self, key: K, value: V) -> Option<Arc<V>> {
+        self.put(key, Arc::new(value))
+    }
+}
+
+// operations that never need to copy a key or a value
+impl<K: Eq + Hash, V, S: BuildHasher> LruCache<K, V, S> {
+    // plug in a faster hasher, or a keyed one when keys come from untrusted
+    // input
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(CacheState::with_capacity_and_hasher(
+                capacity, hasher,
+            ))),
+            group: OnceLock::new(),
+            waits: LockWaits::default(),
+            loads: Mutex::new(HashMap::new()),
+            backlog: None,
+        }
+    }
+
+    // the lock is only timed when it is actually contended, an uncontended
+    // acquisition costs a single try
+    fn read(&self) -> RwLockReadGuard<'_, CacheState<K, V, S>> {
+        if let Some(state) = self.inner.try_read() {
+            return state;
+        }
+        let start = Instant::now();
+        let state = self.inner.read();
+        self.waits.record(start.elapsed());
+        state
+    }
+
+    // what is left to do once a write has released the lock: the group's
+    // budget, and write-behind ops that did not fit the queue
+    fn after_write(&self) {
+        group::enforce(&self.group);
+        self.relieve();
+    }
+
+    fn relieve(&self) {
+        if let Some(backlog) = &self.backlog {
+            backlog.relieve();
+        }
+    }
+
+    // whether `get` serves hits under the read lock, fixed once built
+    #[cfg(feature = "async")]
+    pub(crate) fn shared_lookups(&self) -> bool {
+        self.read().shared_lookups()
+    }
+
+    // anything left spilled by a write that could not relieve it, such as
+    // one through an entry, is handed over before the lock is taken
+    fn write(&self) -> RwLockWriteGuard<'_, CacheState<K, V, S>> {
+        self.relieve();
+        if let Some(state) = self.inner.try_write() {
+            return state;
+        }
+        let start = Instant::now();
+        let state = self.inner.write();
+        self.waits.record(start.elapsed());
+        state
+    }
+
+    // promotes under the write lock, then downgrades it so the returned guard
+    // derefs to the stored value without copying it while only blocking
+    // writers
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "lru_cache.get_ref", skip_all)
+    )]
+    pub fn get_ref<Q>(&self, key: &Q) -> Option<ValueGuard<'_, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut state = self.write();
+
+        let idx = state.lookup(key)?;
+        Some(ValueGuard::new(lock::downgrade(state), idx))
+    }
+
+    // presence check under the read lock, no clone and no promotion
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.read().find_live(key).is_some()
+    }
+
+    // point in time a live entry expires at, taking both the time to live
+    // and the time to idle into account. None for a missing or expired key
+    // as well as for one that never expires
+    pub fn expires_at<Q>(&self, key: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let state = self.read();
+
+        let idx = state.find_live(key)?;
+        state.node(idx).deadline()
+    }
+
+    // time left until `expires_at`, e.g. for a Cache-Control max-age
+    pub fn ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let state = self.read();
+
+        let idx = state.find_live(key)?;
+        let deadline = state.node(idx).deadline()?;
+        Some(deadline.saturating_duration_since(state.clock.now()))
+    }
+
+    // drops the entry from both the map and the recency list under one lock
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "lru_cache.remove", skip_all)
+    )]
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.write().remove(key)
+    }
+
+    // `remove` for an owned key, which is deleted from the backing store
+    // whether or not it is still cached
+    pub fn delete(&self, key: &K) -> Option<V> {
+        self.write().remove_owned(key)
+    }
+
+    // drops only the cached copy, a backing store keeps the key. for
+    // invalidations saying the copy is stale rather than the value gone
+    pub fn invalidate<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut state = self.write();
+
+        let hash = state.hasher.hash_one(key);
+        let idx = state.find_or_expire(hash, key)?;
+        Some(state.evict_explicit(idx).1)
+    }
+
+    // mutates the stored value in place under the write lock and promotes
+    // it, so large values never need a clone round-trip through get/put
+    pub fn with_value_mut<Q, R, F>(&self, key: &Q, f: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&mut V) -> R,
+    {
+        let out = {
+            let mut state = self.write();
+
+            let idx = state.find_fresh(key)?;
+            state.update_through(idx, f)
+        };
+        self.after_write();
+        Some(out)
+    }
+
+    // only overwrites an existing key, returning the old value. a missing key
+    // is left missing
+    pub fn replace_if_present(&self, key: &K, value: V) -> Option<V> {
+        let old = {
+            let mut state = self.write();
+
+            let idx = state.find_fresh(key)?;
+            state.write_through(&state.node(idx).key, &value);
+            state.replace(idx, value)
+        };
+        self.after_write();
+        Some(old)
+    }
+
+    // replaces the value only if it still equals `expected`, the check and
+    // the write happen under one lock so concurrent updates are never lost
+    pub fn compare_and_swap<Q>(&self, key: &Q, expected: &V, new: V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq,
+    {
+        {
+            let mut state = self.write();
+
+            let Some(idx) = state.find_fresh(key) else {
+                return false;
+            };
+            if state.node(idx).value != *expected {
+                return false;
+            }
+
+            state.write_through(&state.node(idx).key, &new);
+            state.replace(idx, new);
+        }
+        self.after_write();
+        true
+    }
+
+    // gives a live entry a new time to live starting now, the value and its
+    // recency are left alone. false if the key is missing or expired
+    pub fn set_ttl<Q>(&self, key: &Q, ttl: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut state = self.write();
+
+        let Some(idx) = state.find_fresh(key) else {
+            return false;
+        };
+        state.set_ttl(idx, Some(ttl));
+        true
+    }
+
+    // restarts the entry's current time to live from now, e.g. to keep a
+    // session alive on activity. an entry without one stays without
+    pub fn touch_ttl<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut state = self.write();
+
+        let Some(idx) = state.find_fresh(key) else {
+            return false;
+        };
+        let ttl = state.node(idx).ttl;
+        state.set_ttl(idx, ttl);
+        true
+    }
+
+    // keeps the entry resident until `unpin`, whatever the capacity asks
+    // for. it still counts toward the capacity and the weight budget and
+    // still expires. once pins fill the cache, new entries are evicted as
+    // soon as they are put. false if the key is missing or already pinned
+    pub fn pin<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut state = self.write();
+
+        state.find_fresh(key).is_some_and(|idx| state.pin(idx))
+    }
+
+    // makes a pinned entry evictable again. a cache that was shrunk below
+    // its pinned entries is trimmed right away
+    pub fn unpin<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut state = self.write();
+
+        let unpinned = state.find_fresh(key).is_some_and(|idx| state.unpin(idx));
+        state.trim();
+        unpinned
+    }
+
+    // takes out the least recently used entry. like `retain`, `drain` and
+    // `clear` this only empties the cache, a backing store keeps the key
+    pub fn pop_lru(&self) -> Option<(K, V)> {
+        let mut state = self.write();
+
+        let head = state.head;
+        (head != NIL).then(|| state.evict_explicit(head))
+    }
+
+    // takes out the most recently used entry
+    pub fn pop_mru(&self) -> Option<(K, V)> {
+        let mut state = self.write();
+
+        let tail = state.tail;
+        (tail != NIL).then(|| state.evict_explicit(tail))
+    }
+
+    // drops every entry the predicate rejects, under a single lock
+    pub fn retain<F>(&self, f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.write().retain(f);
+    }
+
+    // atomically empties the cache and hands back the entries from least to
+    // most recently used, so replaying them into another cache keeps order
+    pub fn drain(&self) -> IntoIter<K, V> {
+        IntoIter::new(&mut self.write())
+    }
+
+    // maintenance for callers driving it from their own scheduler instead
+    // of a janitor thread: drops every expired entry, and trims the group
+    // budget if an entry handle left it exceeded. returns how many entries
+    // expired
+    pub fn cleanup(&self) -> usize {
+        let purged = {
+            let mut state = self.write();
+
+            state.apply_refreshes();
+            state.purge_expired()
+        };
+        self.after_write();
+        purged
+    }
+
+    // empties the map and the recency list in one lock acquisition, every
+    // entry is reported to the eviction listener as removed explicitly
+    pub fn clear(&self) {
+        let mut state = self.write();
+
+        // the listener and subscribers both hear of every entry
+        for node in state.iter_mru() {
+            state.notify(&node.key, &node.value, RemovalCause::Explicit);
+        }
+        state.clear();
+    }
+
+    // counted by lookups that count as a use: `get`, `get_ref`, `entry` and
+    // the methods built on it. `peek` and `contains_key` are not counted
+    pub fn stats(&self) -> CacheStats {
+        self.read().stats.snapshot()
+    }
+
+    // also clears the lock contention totals
+    pub fn reset_stats(&self) {
+        let mut state = self.write();
+        state.stats.reset();
+        if let Some(hot) = &mut state.hot_keys {
+            hot.clear();
+        }
+        if let Some(curve) = &mut state.hit_curve {
+            curve.clear();
+        }
+        self.waits.reset();
+    }
+
+    // hit rate the lookups seen so far would have had at each of the given
+    // capacities, with plain LRU eviction and no expiry. empty unless
+    // `CacheBuilder::sample_hit_rate_curve` was set
+    pub fn hit_rate_curve(&self, capacities: &[usize]) -> Vec<(usize, f64)> {
+        let state = self.read();
+        let Some(curve) = &state.hit_curve else {
+            return Vec::new();
+        };
+        capacities
+            .iter()
+            .map(|&capacity| (capacity, curve.hit_rate(capacity)))
+            .collect()
+    }
+
+    // returns once every change queued by `CacheBuilder::write_behind` has
+    // reached the store, nothing to wait for with any other store
+    pub fn flush(&self) {
+        let backing = self.read().backing.clone();
+        if let Some(backing) = backing {
+            backing.flush();
+        }
+    }
+
+    // writes out what `CacheBuilder::record_trace` has buffered, or returns
+    // the error that stopped the recording
+    pub fn flush_trace(&self) -> std::io::Result<()> {
+        match &mut self.write().trace {
+            Some(trace) => trace.flush(),
+            None => Ok(()),
+        }
+    }
+
+    // time callers spent blocked on the cache lock, a cheap way to tell
+    // whether sharding would pay off
+    pub fn lock_contention(&self) -> LockContention {
+        self.waits.snapshot()
+    }
+
+    // usize::MAX for an unbounded cache
+    pub fn capacity(&self) -> usize {
+        self.read().capacity
+    }
+
+    // total weight of the cached entries, the entry count without a weigher
+    pub fn weight(&self) -> u64 {
+        self.read().weight
+    }
+
+    // u64::MAX unless a weight budget was configured
+    pub fn max_weight(&self) -> u64 {
+        self.read().max_weight
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.capacity() == UNBOUNDED
+    }
+
+    // grows or shrinks the cache at runtime, shrinking evicts least recently
+    // used entries until the new bound holds. usize::MAX lifts the bound
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut state = self.write();
+
+        state.resize(capacity);
+        state.trim();
+    }
+
+    // expired entries still hold a slot until overwritten or evicted, but
+    // are not counted
+    pub fn len(&self) -> usize {
+        self.read().live_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // returns the value previously stored under the key so callers can
+    // release whatever it was holding on to
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "lru_cache.put", skip_all)
+    )]
+    pub fn put(&self, key: K, value: V) -> Option<V> {
+        let old = self.write().put(key, value);
+        self.after_write();
+        old
+    }
+
+    // the entry expires `ttl` after this put and reads as missing from then
+    // on. it still takes its slot until evicted or overwritten
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "lru_cache.put_with_ttl", skip_all)
+    )]
+    pub fn put_with_ttl(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        let old = {
+            let mut state = self.write();
+
+            state.put_with_ttl(key, value, Some(ttl))
+        };
+        self.after_write();
+        old
+    }
+
+    // a put that also places the entry in an eviction class, low entries
+    // are all evicted before normal ones and those before high ones. a plain
+    // put of a new key is normal, overwriting keeps the class
+    pub fn put_with_priority(&self, key: K, value: V, priority: Priority) -> Option<V> {
+        let old = {
+            let mut state = self.write();
+
+            let ttl = state.time_to_live;
+            state.store(key, value, ttl, Some(priority))
+        };
+        self.after_write();
+        old
+    }
+
+    // bulk insert under a single write lock acquisition
+    pub fn put_many<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        {
+            let mut state = self.write();
+
+            for (key, value) in entries {
+                state.put(key, value);
+            }
+        }
+        self.after_write();
+    }
+
+    // primes the cache in one critical section, hottest entries first. when
+    // more are given than fit, the ones at the end are the ones left out.
+    // the eviction listener and subscribers stay quiet meanwhile, but every
+    // value is written through like a put
+    pub fn warm_up<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let entries: Vec<_> = entries.into_iter().collect();
+        self.write().warm_up(entries, true);
+        self.after_write();
+    }
+
+    // `warm_up` with values read from the backing store, which are not
+    // written back to it
+    pub fn warm_up_from_store<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let entries: Vec<_> = entries.into_iter().collect();
+        self.write().warm_up(entries, false);
+        self.after_write();
+    }
+
+    // what the snapshot loaders put entries back with, see
+    // `CacheState::restore`
+    pub(crate) fn restore<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V, Option<Duration>)>,
+    {
+        self.write().restore(entries);
+        self.after_write();
+    }
+
+    // runs every op in one critical section, other threads observe either
+    // none or all of them
+    pub fn apply<I>(&self, ops: I)
+    where
+        I: IntoIterator<Item = CacheOp<K, V>>,
+    {
+        {
+            let mut state = self.write();
+
+            for op in ops {
+                match op {
+                    CacheOp::Put(key, value) => {
+                        state.put(key, value);
+                    }
+                    CacheOp::Remove(key) => {
+                        state.remove_owned(&key);
+                    }
+                    CacheOp::Touch(key) => {
+                        state.get(&key);
+                    }
+                }
+            }
+        }
+        self.after_write();
+    }
+
+    // check-then-act access to a single key, the write lock is held until the
+    // returned entry is dropped so nothing can slip in between. an existing
+    // entry counts as used and is promoted. a group budget is enforced on
+    // the next write, not when the entry is dropped
+    pub fn entry(&self, key: K) -> Entry<'_, K, V, S> {
+        let mut state = self.write();
+
+        // hashed once here, a vacant entry reuses it on insert
+        let hash = state.hasher.hash_one(&key);
+        match state.find_or_expire(hash, &key) {
+            Some(idx) => {
+                state.promote(idx);
+                state.record_hit(idx);
+                Entry::Occupied(OccupiedEntry::new(state, idx))
+            }
+            None => {
+                state.record_miss(hash);
+                Entry::Vacant(VacantEntry::new(state, hash, key))
+            }
+        }
+    }
+}
+
+// lookups hand back owned copies of the stored values
+impl<K: Eq + Hash, V: Clone, S: BuildHasher> LruCache<K, V, S> {
+    // like HashMap, lookups accept any borrowed form of the key, so a
+    // String keyed cache can be queried with a &str
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", name = "lru_cache.get", skip_all)
+    )]
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        {
+            let state = self.read();
+            if state.shared_lookups()
+                && let Some(found) = state.get_shared(key)
+            {
+                return found.cloned();
+            }
+        }
+        let mut state = self.write();
+
+        state.get(key).cloned()
+    }
+
+    // looks up every key under a single write lock, results line up with
+    // the input order
+    pub fn get_many<Q>(&self, keys: &[Q]) -> Vec<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+    {
+        let mut state = self.write();
+
+        keys.iter().map(|key| state.get(key).cloned()).collect()
+    }
+
+    // like `get`, but an entry that expired less than the grace period ago
+    // is still handed out, flagged as stale. the first caller to see it
+    // stale is told to refresh it, the others keep getting the stale value
+    // until a new one is written. a stale read does not count as a use
+    pub fn get_stale_ok<Q>(&self, key: &Q) -> Option<MaybeStale<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut state = self.write();
+
+        state.apply_refreshes();
+        let hash = state.hasher.hash_one(key);
+        let Some(idx) = state.find_hashed(hash, key) else {
+            state.record_miss(hash);
+            return None;
+        };
+        if !state.is_expired(idx) {
+            state.promote(idx);
+            state.record_hit(idx);
+            return Some(MaybeStale::Fresh(state.node(idx).value.clone()));
+        }
+        if state.is_dead(idx) {
+            state.expire(idx);
+            state.record_miss(hash);
+            return None;
+        }
+
+        state.record_hit(idx);
+        let node = state.node_mut(idx);
+        let refresh = !std::mem::replace(&mut node.refreshing, true);
+        Some(MaybeStale::Stale {
+            value: node.value.clone(),
+            refresh,
+        })
+    }
+
+    // reads under the read lock only, LRU order is left untouched
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let state = self.read();
+
+        let idx = state.find_live(key)?;
+        Some(state.node(idx).value.clone())
+    }
+
+    // compute-on-miss under one lock acquisition, threads racing on the same
+    // missing key run `f` exactly once between them
+    pub fn get_or_insert_with<F>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce() -> V,
+    {
+        let value = self.entry(key).or_insert_with(f);
+        self.after_write();
+        value
+    }
+
+    // inserts only if the key is missing and returns None, otherwise the
+    // current value is handed back and left in place
+    pub fn put_if_absent(&self, key: K, value: V) -> Option<V> {
+        let current = match self.entry(key) {
+            Entry::Occupied(entry) => Some(entry.get().clone()),
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+                None
+            }
+        };
+        self.after_write();
+        current
+    }
+
+    // read-through with single-flight loading. unlike `get_or_insert_with`
+    // the loader runs outside the cache lock, other keys stay usable while
+    // it does, and of the threads missing on the same key at once only one
+    // runs `load` while the rest block until its value is cached. a loader
+    // that panics leaves the key to the next waiter
+    pub fn get_or_load<F>(&self, key: K, load: F) -> V
+    where
+        K: Clone,
+        F: FnOnce() -> V,
+    {
+        self.load_once(key, || Some(load()))
+            .expect("the loader always returns a value")
+    }
+
+    // single-flight core shared with read-through caches, a load returning
+    // None caches nothing and hands None to every waiter
+    pub(crate) fn load_once<F>(&self, key: K, load: F) -> Option<V>
+    where
+        K: Clone,
+        F: FnOnce() -> Option<V>,
+    {
+        if let Some(value) = self.get(&key) {
+            return Some(value);
+        }
+
+        let cell = {
+            let mut loads = self.loads.lock().unwrap();
+            Arc::clone(loads.entry(key.clone()).or_default())
+        };
+        let value = cell
+            .get_or_init(|| {
+                // a load that finished between the miss and taking the cell
+                // has already cached the value
+                if let Some(value) = self.get(&key) {
+                    return Some(value);
+                }
+                let value = load()?;
+                {
+                    let mut state = self.write();
+                    let ttl = state.time_to_live;
+                    state.fill(key.clone(), value.clone(), ttl, None);
+                }
+                self.after_write();
+                Some(value)
+            })
+            .clone();
+
+        let mut loads = self.loads.lock().unwrap();
+        if loads.get(&key).is_some_and(|done| Arc::ptr_eq(done, &cell)) {
+            loads.remove(&key);
+        }
+        value
+    }
+
+    // fallible loader, an error is handed back to the caller and nothing is
+    // cached so the next call tries again
+    pub fn get_or_try_insert_with<F, E>(&self, key: K, f: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        let value = match self.entry(key) {
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => entry.insert(f()?).get().clone(),
+        };
+        self.after_write();
+        Ok(value)
+    }
+
+    // cloned snapshot of the values, most recently used first
+    pub fn values(&self) -> std::vec::IntoIter<V> {
+        let state = self.read();
+
+        state
+            .iter_live(state.expiry_cutoff())
+            .map(|entry| entry.value.clone())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+// snapshots that copy the keys out as well
+impl<K: Eq + Hash + Clone, V, S: BuildHasher> LruCache<K, V, S> {
+    // snapshot of the entries from most to least recently used, taken under
+    // the read lock so the cache is free for writers while it is consumed
+    pub fn iter(&self) -> std::vec::IntoIter<(K, V)>
+    where
+        V: Clone,
+    {
+        let state = self.read();
+
+        state
+            .iter_live(state.expiry_cutoff())
+            .map(|entry| (entry.key.clone(), entry.value.clone()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    // cloned snapshot of the keys, most recently used first
+    pub fn keys(&self) -> std::vec::IntoIter<K> {
+        let state = self.read();
+
+        state
+            .iter_live(state.expiry_cutoff())
+            .map(|entry| entry.key.clone())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    // the n keys hit or inserted most often, with their approximate counts
+    // and highest first. empty unless `CacheBuilder::track_hot_keys` was set,
+    // keys may since have been evicted
+    pub fn hottest(&self, n: usize) -> Vec<(K, u64)> {
+        self.read()
+            .hot_keys
+            .as_ref()
+            .map_or_else(Vec::new, |hot| hot.hottest(n))
+    }
+}
+
+// events are owned copies of what happened
+impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher> LruCache<K, V, S> {
+    // stream of inserts, hits, misses, evictions and expirations from now
+    // on. events are sent under the cache lock into an unbounded channel,
+    // so a subscriber that stops reading should drop its receiver
+    pub fn subscribe(&self) -> Receiver<CacheEvent<K, V>> {
+        let (events, receiver) = mpsc::channel();
+        self.add_subscriber(Subscriber::Events(events));
+        receiver
+    }
+
+    // every entry leaving the cache from now on, as a futures Stream. like
+    // `subscribe` the channel is unbounded, drop the stream when done
+    #[cfg(feature = "async")]
+    pub fn evictions(&self) -> EvictionStream<K, V> {
+        let (evictions, receiver) = futures_channel::mpsc::unbounded();
+        self.add_subscriber(Subscriber::Evictions(evictions));
+        EvictionStream::new(receiver)
+    }
+
+    pub(crate) fn add_subscriber(&self, subscriber: Subscriber<K, V>) {
+        let mut state = self.write();
+
+        state.to_event = Some(events::to_owned::<K, V>);
+        state.subscribers.get_mut().unwrap().push(subscriber);
+    }
+}
+
+// memory accounting needs to know what keys and values own on the heap
+impl<K: Eq + Hash + HeapSize, V: HeapSize, S: BuildHasher> LruCache<K, V, S> {
+    // estimated bytes held by the cache: the cache struct, the slab with its
+    // free slots, the table buckets and free list, plus whatever the stored
+    // keys and values own on the heap
+    pub fn memory_usage(&self) -> usize {
+        size_of::<Self>() + self.read().memory_usage()
+    }
+}
+
+// consuming iterator, yields entries from least to most recently used
+//
+// walks the detached slab along the recency links, the table is not needed
+// any more at this point
+pub struct IntoIter<K, V> {
+    entries: Vec<Option<Node<K, V>>>,
+    next: usize,
+    len: usize,
+    // entries expired by the time the cache was emptied are skipped
+    cutoff: Option<Instant>,
+}
+
+impl<K, V> IntoIter<K, V> {
+    fn new<S>(state: &mut CacheState<K, V, S>) -> Self {
+        let cutoff = state.expiry_cutoff();
+        let len = match cutoff {
+            Some(now) => state.iter_live(Some(now)).count(),
+            None => state.map.len(),
+        };
+        let iter = Self {
+            entries: std::mem::take(&mut state.entries),
+            next: state.head,
+            len,
+            cutoff,
+        };
+
+        state.clear();
+        iter
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next != NIL {
+            let node = self.entries[self.next]
+                .take()
+                .expect("linked slot must be occupied");
+            self.next = node.next;
+
+            if !node.is_expired_at(self.cutoff) {
+                self.len -= 1;
+                return Some((node.key, node.value));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K: Eq + Hash, V, S: BuildHasher> IntoIterator for LruCache<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        // a group only keeps a weak handle, but may be upgrading it right now
+        match Arc::try_unwrap(self.inner) {
+            Ok(lock) => IntoIter::new(&mut lock.into_inner()),
+            Err(shared) => IntoIter::new(&mut shared.write()),
+        }
+    }
+}
+
+// bulk insert under a single lock acquisition, later items win and the
+// usual eviction applies once the cache is full
+impl<K: Eq + Hash, V, S: BuildHasher> Extend<(K, V)> for LruCache<K, V, S> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.put_many(iter);
+    }
+}
+
+// a state leaving the cache takes its weight out of the group with it
+impl<K, V, S> Drop for CacheState<K, V, S> {
+    fn drop(&mut self) {
+        if let Some(group) = &self.group {
+            group.charge(self.weight, 0);
+        }
+    }
+}
+
+// Our generic unit test cases to test insertion, eviction and concurrency
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn simple_insert_and_get() {
+        let cache = LruCache::new(2);
+
+        cache.put(1, "one");
+        cache.put(2, "two");
+
+        assert_eq!(cache.get(&1), Some("one"));
+        assert_eq!(cache.get(&2), Some("two"));
+    }
+
+    #[test]
+    fn should_evict_oldest() {
+        let cache = LruCache::new(2);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c"); // this should evict 1
+
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), Some("b"));
+        assert_eq!(cache.get(&3), Some("c"));
+    }
+
+    #[test]
+    fn update_value() {
+        let cache = LruCache::new(2);
+
+        assert_eq!(cache.put(1, "a"), None);
+        assert_eq!(cache.put(1, "b"), Some("a"));
+
+        assert_eq!(cache.get(&1), Some("b"));
+    }
+
+    #[test]
+    fn get_refreshes_recency() {
+        let cache = LruCache::new(2);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        assert_eq!(cache.get(&1), Some("a"));
+        cache.put(3, "c"); // 2 is now the oldest
+
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some("a"));
+        assert_eq!(cache.get(&3), Some("c"));
+    }
+
+    #[test]
+    fn slots_are_reused_after_eviction() {
+        let cache = LruCache::new(3);
+
+        for i in 0..100 {
+            cache.put(i, i * 10);
+        }
+
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.inner.read().entries.len(), 3);
+        assert_eq!(cache.get(&97), Some(970));
+        assert_eq!(cache.get(&98), Some(980));
+        assert_eq!(cache.get(&99), Some(990));
+    }
+
+    #[test]
+    fn lookup_by_borrowed_key() {
+        let cache = LruCache::new(2);
+
+        cache.put("one".to_string(), 1);
+
+        assert_eq!(cache.get("one"), Some(1));
+        assert_eq!(cache.get("two"), None);
+    }
+
+    #[test]
+    fn remove_returns_value() {
+        let cache = LruCache::new(2);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+
+        assert_eq!(cache.remove(&1), Some("a"));
+        assert_eq!(cache.remove(&1), None);
+        assert_eq!(cache.len(), 1);
+
+        // the freed slot must not cost us an eviction
+        cache.put(3, "c");
+        assert_eq!(cache.get(&2), Some("b"));
+        assert_eq!(cache.get(&3), Some("c"));
+    }
+
+    #[test]
+    fn clear_empties_cache() {
+        let cache = LruCache::new(2);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.clear();
+
+        assert!(cache.is_empty());
+        assert_eq!(cache.get(&1), None);
+
+        cache.put(3, "c");
+        assert_eq!(cache.get(&3), Some("c"));
+    }
+
+    #[test]
+    fn peek_does_not_promote() {
+        let cache = LruCache::new(2);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        assert_eq!(cache.peek(&1), Some("a"));
+        cache.put(3, "c"); // 1 is still the oldest
+
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.peek(&2), Some("b"));
+    }
+
+    #[test]
+    fn contains_key_does_not_promote() {
+        let cache = LruCache::new(2);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&5));
+        cache.put(3, "c");
+
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+    }
+
+    #[test]
+    fn pop_in_recency_order() {
+        let cache = LruCache::new(3);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        cache.get(&1);
+
+        assert_eq!(cache.pop_lru(), Some((2, "b")));
+        assert_eq!(cache.pop_mru(), Some((1, "a")));
+        assert_eq!(cache.pop_lru(), Some((3, "c")));
+        assert_eq!(cache.pop_lru(), None);
+        assert_eq!(cache.pop_mru(), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn iter_from_most_recent() {
+        let cache = LruCache::new(3);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        cache.get(&1);
+
+        let entries: Vec<_> = cache.iter().collect();
+        assert_eq!(entries, vec![(1, "a"), (3, "c"), (2, "b")]);
+
+        // iterating must not reorder anything
+        assert_eq!(cache.pop_lru(), Some((2, "b")));
+    }
+
+    #[test]
+    fn keys_and_values_snapshots() {
+        let cache = LruCache::new(3);
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.get(&1);
+
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(cache.values().collect::<Vec<_>>(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn extend_then_into_iter() {
+        let mut cache = LruCache::new(3);
+
+        cache.extend([(1, "a"), (2, "b"), (3, "c"), (4, "d")]);
+        cache.get(&2);
+
+        let entries: Vec<_> = cache.into_iter().collect();
+        assert_eq!(entries, vec![(3, "c"), (4, "d"), (2, "b")]);
+    }
+
+    #[test]
+    fn retain_drops_rejected_entries() {
+        let cache = LruCache::new(4);
+
+        for i in 0..4 {
+            cache.put(i, i * 10);
+        }
+        cache.retain(|k, v| {
+            *v += 1;
+            k % 2 == 0
+        });
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![2, 0]);
+        assert_eq!(cache.get(&2), Some(21));
+        assert_eq!(cache.get(&1), None);
+    }
 
-// our implementation of get and put
-impl<K: Eq + Hash + Clone, V: Clone> LruCache<K, V> {
-    pub fn new(capacity: usize) -> Self {
-        assert!(capacity > 0);
+    #[test]
+    fn drain_hands_back_entries() {
+        let cache = LruCache::new(3);
 
-        Self {
-            capacity,
-            inner: RwLock::new(CacheState {
-                map: HashMap::with_capacity(capacity),
-                order: VecDeque::with_capacity(capacity),
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        cache.get(&1);
+
+        let drained: Vec<_> = cache.drain().collect();
+        assert_eq!(drained, vec![(2, "b"), (3, "c"), (1, "a")]);
+        assert!(cache.is_empty());
+
+        cache.put(4, "d");
+        assert_eq!(cache.get(&4), Some("d"));
+    }
+
+    #[test]
+    fn get_or_insert_with_computes_once() {
+        let cache = Arc::new(LruCache::new(4));
+        let computed = Arc::new(AtomicUsize::new(0));
+        let mut handles = vec![];
+
+        for _ in 0..8 {
+            let c = Arc::clone(&cache);
+            let computed = Arc::clone(&computed);
+            handles.push(thread::spawn(move || {
+                c.get_or_insert_with(1, || {
+                    computed.fetch_add(1, Ordering::SeqCst);
+                    "one"
+                })
+            }));
+        }
+
+        for h in handles {
+            assert_eq!(h.join().unwrap(), "one");
+        }
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn warm_up_keeps_the_hottest_quietly() {
+        let evicted = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&evicted);
+        let cache = LruCache::builder()
+            .capacity(3)
+            .eviction_listener(move |_: &u32, _: &u32, _| {
+                counted.fetch_add(1, Ordering::SeqCst);
+            })
+            .build()
+            .unwrap();
+
+        cache.warm_up((1..=5).map(|key| (key, key * 10)));
+
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(evicted.load(Ordering::SeqCst), 0);
+        // the listener is back afterwards
+        cache.put(6, 60);
+        assert_eq!(evicted.load(Ordering::SeqCst), 1);
+        assert!(!cache.contains_key(&3));
+    }
+
+    #[test]
+    fn get_or_load_runs_the_loader_once() {
+        let cache = Arc::new(LruCache::new(4));
+        let loads = Arc::new(AtomicUsize::new(0));
+        let mut handles = vec![];
+
+        for _ in 0..50 {
+            let c = Arc::clone(&cache);
+            let loads = Arc::clone(&loads);
+            handles.push(thread::spawn(move || {
+                c.get_or_load(1, || {
+                    loads.fetch_add(1, Ordering::SeqCst);
+                    // slow enough for the other threads to miss as well
+                    thread::sleep(Duration::from_millis(50));
+                    "one"
+                })
+            }));
+        }
+        // other keys are served while the load runs
+        cache.put(2, "two");
+        assert_eq!(cache.get(&2), Some("two"));
+
+        for h in handles {
+            assert_eq!(h.join().unwrap(), "one");
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(cache.loads.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_loader_caches_nothing() {
+        let cache = LruCache::new(2);
+
+        let res: Result<i32, &str> = cache.get_or_try_insert_with(1, || Err("db down"));
+        assert_eq!(res, Err("db down"));
+        assert!(!cache.contains_key(&1));
+
+        let res: Result<i32, &str> = cache.get_or_try_insert_with(1, || Ok(10));
+        assert_eq!(res, Ok(10));
+        let res: Result<i32, &str> = cache.get_or_try_insert_with(1, || Err("not called"));
+        assert_eq!(res, Ok(10));
+    }
+
+    #[test]
+    fn with_value_mut_updates_in_place() {
+        let cache: LruCache<u32, Vec<u8>> = LruCache::new(2);
+
+        cache.put(1, vec![1]);
+        cache.put(2, vec![2]);
+
+        assert_eq!(
+            cache.with_value_mut(&1, |buf| {
+                buf.push(9);
+                buf.len()
             }),
+            Some(2)
+        );
+        assert_eq!(cache.with_value_mut(&5, |buf| buf.len()), None);
+
+        cache.put(3, vec![3]); // 1 was promoted, so 2 goes
+        assert_eq!(cache.get(&1), Some(vec![1, 9]));
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn compare_and_swap_checks_current_value() {
+        let cache = LruCache::new(2);
+        cache.put(1, 10);
+
+        assert!(!cache.compare_and_swap(&1, &11, 12));
+        assert!(cache.compare_and_swap(&1, &10, 12));
+        assert!(!cache.compare_and_swap(&2, &10, 12));
+        assert_eq!(cache.get(&1), Some(12));
+    }
+
+    #[test]
+    fn concurrent_compare_and_swap_loses_no_updates() {
+        let cache = Arc::new(LruCache::new(2));
+        cache.put("n", 0);
+        let mut handles = vec![];
+
+        for _ in 0..4 {
+            let c = Arc::clone(&cache);
+            handles.push(thread::spawn(move || {
+                for _ in 0..100 {
+                    loop {
+                        let current = c.peek("n").unwrap();
+                        if c.compare_and_swap("n", &current, current + 1) {
+                            break;
+                        }
+                    }
+                }
+            }));
+        }
+
+        for h in handles {
+            h.join().unwrap();
         }
+
+        assert_eq!(cache.get("n"), Some(400));
     }
 
-    pub fn get(&self, key: &K) -> Option<V> {
-        let mut state = self.inner.write().unwrap();
+    #[test]
+    fn conditional_inserts() {
+        let cache = LruCache::new(2);
+
+        assert_eq!(cache.replace_if_present(&1, "a"), None);
+        assert!(!cache.contains_key(&1));
 
-        if let Some(value) = state.map.get(key).cloned() {
-            // most recently used
-            if let Some(pos) = state.order.iter().position(|k| k == key) {
-                state.order.remove(pos);
-            }
-            state.order.push_back(key.clone());
+        assert_eq!(cache.put_if_absent(1, "a"), None);
+        assert_eq!(cache.put_if_absent(1, "b"), Some("a"));
+        assert_eq!(cache.replace_if_present(&1, "c"), Some("a"));
+        assert_eq!(cache.get(&1), Some("c"));
+    }
 
-            Some(value)
-        } else {
-            None
+    #[test]
+    fn batch_get_and_put() {
+        let cache = LruCache::new(3);
+
+        cache.put_many([(1, "a"), (2, "b"), (3, "c"), (4, "d")]);
+
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get_many(&[4, 1, 2]), vec![Some("d"), None, Some("b")]);
+    }
+
+    #[test]
+    fn apply_batch_of_ops() {
+        let cache = LruCache::new(3);
+        cache.put_many([(1, "a"), (2, "b"), (3, "c")]);
+
+        cache.apply([
+            CacheOp::Remove(2),
+            CacheOp::Put(4, "d"),
+            CacheOp::Touch(1),
+            CacheOp::Touch(9),
+        ]);
+
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![1, 4, 3]);
+    }
+
+    #[test]
+    fn resize_at_runtime() {
+        let cache = LruCache::new(4);
+        cache.put_many([(1, "a"), (2, "b"), (3, "c"), (4, "d")]);
+        cache.get(&1);
+
+        cache.set_capacity(2);
+        assert_eq!(cache.capacity(), 2);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![1, 4]);
+
+        cache.set_capacity(3);
+        cache.put(5, "e");
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn unbounded_never_evicts_on_insert() {
+        let cache = LruCache::unbounded();
+        assert!(cache.is_unbounded());
+
+        for i in 0..1000 {
+            cache.put(i, i);
         }
+        cache.get(&0);
+        assert_eq!(cache.len(), 1000);
+
+        // bounding it later keeps the most recently used entries
+        cache.set_capacity(2);
+        assert!(!cache.is_unbounded());
+        assert_eq!(cache.keys().collect::<Vec<_>>(), vec![0, 999]);
     }
 
-    pub fn put(&self, key: K, value: V) {
-        let mut state = self.inner.write().unwrap();
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let cache = LruCache::new(0);
 
-        if state.map.contains_key(&key) {
-            state.map.insert(key.clone(), value);
+        assert_eq!(cache.put(1, "a"), None);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get_or_insert_with(2, || "b"), "b");
+        assert_eq!(cache.put_if_absent(3, "c"), None);
+        assert!(cache.is_empty());
+    }
 
-            if let Some(pos) = state.order.iter().position(|k| k == &key) {
-                state.order.remove(pos);
-            }
+    #[test]
+    fn custom_hasher() {
+        use std::hash::BuildHasherDefault;
 
-            state.order.push_back(key);
-            return;
+        // deterministic hasher, stands in for fxhash/ahash
+        let cache: LruCache<&str, i32, BuildHasherDefault<std::hash::DefaultHasher>> =
+            LruCache::with_hasher(2, BuildHasherDefault::default());
+
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("c"), Some(3));
+        assert_eq!(cache.drain().count(), 2);
+    }
+
+    // counts how often a key gets hashed
+    #[derive(Clone, Default)]
+    struct CountingHasher(Arc<AtomicUsize>);
+
+    impl BuildHasher for CountingHasher {
+        type Hasher = std::hash::DefaultHasher;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            std::hash::DefaultHasher::new()
         }
+    }
+
+    #[test]
+    fn keys_are_hashed_once_per_operation() {
+        let hasher = CountingHasher::default();
+        let hashed = Arc::clone(&hasher.0);
+        let cache = LruCache::with_hasher(2, hasher);
+
+        cache.put("a".to_string(), 1);
+        cache.put("b".to_string(), 2);
+        assert_eq!(hashed.load(Ordering::SeqCst), 2);
+
+        cache.get("a");
+        assert_eq!(hashed.load(Ordering::SeqCst), 3);
+
+        // evicting "b" and growing the table reuse the cached hashes
+        cache.put("c".to_string(), 3);
+        cache.set_capacity(64);
+        cache.put_many((0..40).map(|i| (i.to_string(), i)));
+        assert_eq!(hashed.load(Ordering::SeqCst), 44);
+        assert_eq!(cache.get("b"), None);
+    }
+
+    #[test]
+    fn shared_values_are_not_cloned() {
+        // deliberately not Clone
+        struct Blob(Vec<u8>);
+
+        let cache = LruCache::new_shared(2);
+        cache.put_shared("big", Blob(vec![7; 1 << 20]));
+
+        let a = cache.get("big").unwrap();
+        let b = cache.get("big").unwrap();
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(a.0.len(), 1 << 20);
+    }
+
+    #[test]
+    fn get_ref_borrows_without_clone() {
+        // deliberately not Clone
+        struct Blob(Vec<u8>);
+
+        let cache = LruCache::new(2);
+        cache.put(1, Blob(vec![1, 2, 3]));
+        cache.put(2, Blob(vec![4]));
 
-        if state.map.len() == self.capacity
-            && let Some(lru_key) = state.order.pop_front()
         {
-            state.map.remove(&lru_key);
+            let blob = cache.get_ref(&1).unwrap();
+            assert_eq!(blob.0, [1, 2, 3]);
+            // other readers are not blocked by the guard
+            assert!(cache.contains_key(&2));
         }
+        assert!(cache.get_ref(&3).is_none());
 
-        state.map.insert(key.clone(), value);
-        state.order.push_back(key);
+        cache.put(3, Blob(vec![])); // 1 was promoted, so 2 goes
+        assert!(!cache.contains_key(&2));
     }
 
-    pub fn len(&self) -> usize {
-        self.inner.read().unwrap().map.len()
+    #[test]
+    fn shared_str_keys_are_removed_by_str() {
+        let cache: LruCache<Arc<str>, u32> = LruCache::new(2);
+        cache.put("a".into(), 1);
+
+        assert_eq!(cache.remove("a"), Some(1));
+        assert!(cache.is_empty());
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    #[test]
+    fn keys_need_not_be_clone() {
+        #[derive(PartialEq, Eq, Hash)]
+        struct Handle(u32);
+
+        let cache = LruCache::new(2);
+        cache.put(Handle(1), "a");
+        cache.put(Handle(2), "b");
+        cache.entry(Handle(3)).or_insert("c");
+
+        assert_eq!(cache.get(&Handle(1)), None);
+        assert_eq!(cache.get(&Handle(3)), Some("c"));
+        assert_eq!(cache.pop_lru().map(|(k, v)| (k.0, v)), Some((2, "b")));
+        assert_eq!(cache.remove(&Handle(3)), Some("c"));
     }
-}
 
-// Our generic unit test cases to test insertion, eviction and concurrency
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::Arc;
-    use std::thread;
+    #[test]
+    fn entries_expire_after_their_ttl() {
+        let cache = LruCache::new(4);
+
+        cache.put_with_ttl("short", 1, Duration::from_millis(20));
+        cache.put_with_ttl("long", 2, Duration::from_secs(60));
+        cache.put("forever", 3);
+        assert_eq!(cache.get(&"short"), Some(1));
+
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(cache.get(&"short"), None);
+        assert_eq!(cache.peek(&"short"), None);
+        assert!(!cache.contains_key(&"short"));
+        assert_eq!(cache.get(&"long"), Some(2));
+        assert_eq!(cache.get(&"forever"), Some(3));
+
+        // overwriting an expired key is an insert
+        assert_eq!(cache.put("short", 4), None);
+        assert_eq!(cache.get(&"short"), Some(4));
+    }
 
     #[test]
-    fn simple_insert_and_get() {
+    fn ttl_can_be_changed_and_restarted() {
+        let cache = LruCache::new(4);
+
+        cache.put_with_ttl("session", 1, Duration::from_millis(100));
+        cache.put("plain", 2);
+        assert!(cache.set_ttl(&"plain", Duration::from_millis(10)));
+        assert!(!cache.set_ttl(&"missing", Duration::from_secs(1)));
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(cache.touch_ttl(&"session"));
+        assert!(!cache.touch_ttl(&"plain"));
+
+        // past the original deadline, but it was restarted halfway
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.get(&"session"), Some(1));
+    }
+
+    #[test]
+    fn remaining_ttl_is_reported() {
+        let cache = LruCache::new(4);
+
+        cache.put_with_ttl(1, "a", Duration::from_secs(60));
+        cache.put(2, "b");
+
+        let left = cache.ttl(&1).unwrap();
+        assert!(left <= Duration::from_secs(60) && left > Duration::from_secs(59));
+        assert!(cache.expires_at(&1).unwrap() > Instant::now());
+        assert_eq!(cache.ttl(&2), None);
+        assert_eq!(cache.ttl(&3), None);
+    }
+
+    #[test]
+    fn expired_entries_are_dropped_on_access() {
+        let cache = LruCache::new(4);
+
+        cache.put_with_ttl(1, "a", Duration::from_millis(10));
+        cache.put(2, "b");
+        thread::sleep(Duration::from_millis(30));
+
+        // peeking only hides it, a real lookup frees the slot
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.inner.read().map.len(), 2);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.inner.read().map.len(), 1);
+        assert_eq!(cache.cleanup(), 0);
+    }
+
+    #[test]
+    fn cleanup_drops_expired_entries() {
+        let cache = LruCache::new(4);
+
+        cache.put_with_ttl(1, "a", Duration::from_millis(10));
+        cache.put_with_ttl(2, "b", Duration::from_secs(60));
+        cache.put(3, "c");
+        assert_eq!(cache.cleanup(), 0);
+
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.cleanup(), 1);
+        assert_eq!(cache.inner.read().map.len(), 2);
+    }
+
+    #[test]
+    fn subscribers_see_cache_events() {
+        let cache = LruCache::new(1);
+        let events = cache.subscribe();
+
+        cache.put(1, "a");
+        cache.get(&1);
+        cache.get(&2);
+        cache.put(2, "b");
+        drop(cache);
+
+        let events: Vec<_> = events.iter().collect();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                CacheEvent::Insert(1, "a"),
+                CacheEvent::Hit(1),
+                CacheEvent::Miss { .. },
+                CacheEvent::Evict(1, "a", RemovalCause::CapacityEvicted),
+                CacheEvent::Insert(2, "b"),
+            ]
+        ));
+    }
+
+    #[test]
+    fn subscribers_see_a_clear() {
         let cache = LruCache::new(2);
+        let events = cache.subscribe();
 
-        cache.put(1, "one");
-        cache.put(2, "two");
+        cache.put(1, "a");
+        cache.clear();
+        drop(cache);
 
-        assert_eq!(cache.get(&1), Some("one"));
-        assert_eq!(cache.get(&2), Some("two"));
+        let events: Vec<_> = events.iter().collect();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                CacheEvent::Insert(1, "a"),
+                CacheEvent::Evict(1, "a", RemovalCause::Explicit),
+            ]
+        ));
     }
 
     #[test]
-    fn should_evict_oldest() {
+    fn stats_count_lookups_and_removals() {
         let cache = LruCache::new(2);
 
+        cache.put(1, 1);
+        cache.put(2, 2);
+        cache.put(2, 20);
+        cache.put(3, 3);
+        cache.put_with_ttl(4, 4, Duration::ZERO);
+        cache.get(&3);
+        cache.get(&1);
+        cache.get(&4);
+        cache.get_or_insert_with(5, || 5);
+        cache.peek(&5);
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 1,
+                misses: 3,
+                insertions: 5,
+                evictions: 2,
+                expirations: 1,
+                ..CacheStats::default()
+            }
+        );
+        assert_eq!(cache.stats().hit_rate(), 0.25);
+
+        cache.reset_stats();
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn lock_contention_counts_blocked_time() {
+        let cache = Arc::new(LruCache::new(2));
+        cache.put(1, 1);
+        assert_eq!(cache.lock_contention(), LockContention::default());
+
+        let held = cache.inner.write();
+        let reader = {
+            let cache = Arc::clone(&cache);
+            thread::spawn(move || cache.get(&1))
+        };
+        thread::sleep(Duration::from_millis(50));
+        drop(held);
+
+        assert_eq!(reader.join().unwrap(), Some(1));
+        let contention = cache.lock_contention();
+        assert_eq!(contention.contended, 1);
+        assert!(contention.max_wait >= Duration::from_millis(40));
+        assert_eq!(contention.total_wait, contention.max_wait);
+
+        cache.reset_stats();
+        assert_eq!(cache.lock_contention(), LockContention::default());
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_reports_lookups_and_removals() {
+        use std::fmt::Debug;
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        // writes every span name and event as a line of `name=value` pairs
+        #[derive(Clone, Default)]
+        struct Lines(Arc<Mutex<Vec<String>>>);
+
+        struct Line(String);
+
+        impl Visit for Line {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.0.push_str(&format!(" {}={value:?}", field.name()));
+            }
+        }
+
+        impl Subscriber for Lines {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let name = span.metadata().name();
+                self.0.lock().unwrap().push(format!("span {name}"));
+                Id::from_u64(1)
+            }
+            fn record(&self, _: &Id, _: &Record<'_>) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, event: &Event<'_>) {
+                let mut line = Line("event".to_owned());
+                event.record(&mut line);
+                self.0.lock().unwrap().push(line.0);
+            }
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let lines = Lines::default();
+        tracing::subscriber::with_default(lines.clone(), || {
+            let cache = LruCache::new(1);
+            cache.put(1, 1);
+            cache.get(&2);
+            cache.put(2, 2);
+        });
+
+        // hashes are random per cache, everything else is fixed
+        let lines: Vec<_> = lines
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|line| {
+                let words: Vec<_> = line
+                    .split(' ')
+                    .filter(|w| !w.starts_with("hash="))
+                    .collect();
+                words.join(" ")
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "span lru_cache.put",
+                "event message=inserted",
+                "span lru_cache.get",
+                "event message=lookup hit=false",
+                "span lru_cache.put",
+                "event message=removed cause=CapacityEvicted",
+                "event message=inserted",
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_usage_tracks_owned_bytes() {
+        let cache = LruCache::new(4);
+        let empty = cache.memory_usage();
+
+        cache.put(1u32, String::with_capacity(1000));
+        let one = cache.memory_usage();
+        assert!(one >= empty + 1000);
+
+        // the slab is preallocated, so only the buffer itself is new
+        cache.put(2, String::with_capacity(500));
+        assert_eq!(cache.memory_usage(), one + 500);
+
+        // the free list may grow to track the released slot
+        cache.remove(&1);
+        assert!(cache.memory_usage() < one);
+    }
+
+    #[test]
+    fn low_priority_goes_before_recency() {
+        let cache = LruCache::new(3);
+
+        cache.put_with_priority(1, "critical", Priority::High);
+        cache.put(2, "normal");
+        cache.put_with_priority(3, "best effort", Priority::Low);
+        cache.get(&3);
+
+        cache.put(4, "normal");
+        assert!(!cache.contains_key(&3));
+        // normal entries go in LRU order, high ones last
+        cache.put(5, "normal");
+        cache.put(6, "normal");
+        let mut keys: Vec<_> = cache.keys().collect();
+        keys.sort_unstable();
+        assert_eq!(keys, [1, 5, 6]);
+    }
+
+    #[test]
+    fn classes_decide_without_asking_the_policy() {
+        struct Counted(policy::Lru, Arc<AtomicUsize>);
+
+        impl EvictionPolicy for Counted {
+            fn on_insert(&mut self, slot: usize, hash: u64) {
+                self.0.on_insert(slot, hash);
+            }
+            fn on_access(&mut self, slot: usize) {
+                self.0.on_access(slot);
+            }
+            fn on_remove(&mut self, slot: usize) {
+                self.0.on_remove(slot);
+            }
+            fn choose_victim(&mut self) -> Option<usize> {
+                self.1.fetch_add(1, Ordering::Relaxed);
+                self.0.choose_victim()
+            }
+            fn clear(&mut self) {
+                self.0.clear();
+            }
+        }
+
+        let asked = Arc::new(AtomicUsize::new(0));
+        let cache = LruCache::builder()
+            .capacity(2)
+            .eviction_policy(Counted(policy::Lru::new(), asked.clone()))
+            .build()
+            .unwrap();
         cache.put(1, "a");
+        cache.put_with_priority(2, "b", Priority::High);
+        cache.put(3, "c");
+        assert_eq!(asked.load(Ordering::Relaxed), 0);
+        assert!(cache.contains_key(&2));
+
+        cache.put_with_priority(4, "d", Priority::High);
+        assert_eq!(asked.load(Ordering::Relaxed), 0);
+        // one class left, the policy picks among it
+        cache.put_with_priority(5, "e", Priority::High);
+        assert_eq!(asked.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn overwrite_keeps_the_priority() {
+        let cache = LruCache::new(2);
+
+        cache.put_with_priority(1, 1, Priority::Low);
+        cache.put(2, 2);
+        cache.put(1, 10);
+        cache.put(3, 3);
+
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+    }
+
+    #[test]
+    fn pinned_entries_are_never_evicted() {
+        let cache = LruCache::new(2);
+
+        cache.put(1, "config");
+        assert!(cache.pin(&1));
+        assert!(!cache.pin(&9));
         cache.put(2, "b");
-        cache.put(3, "c"); // this should evict 1
+        cache.put(3, "c");
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
 
-        assert_eq!(cache.get(&1), None);
-        assert_eq!(cache.get(&2), Some("b"));
-        assert_eq!(cache.get(&3), Some("c"));
+        // back to plain LRU, 1 is the oldest
+        assert!(cache.unpin(&1));
+        cache.put(4, "d");
+        assert!(!cache.contains_key(&1));
     }
 
     #[test]
-    fn update_value() {
+    fn pins_hold_against_a_policy() {
+        let cache = LruCache::builder()
+            .capacity(2)
+            .eviction_policy(Lfu::new())
+            .build()
+            .unwrap();
+
+        cache.put(1, 1);
+        cache.pin(&1);
+        for key in 2..10 {
+            cache.get_or_insert_with(key, || key);
+            cache.get(&key);
+        }
+
+        assert!(cache.contains_key(&1));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn pinned_entries_can_fill_the_cache() {
         let cache = LruCache::new(2);
 
-        cache.put(1, "a");
-        cache.put(1, "b");
+        cache.put(1, 1);
+        cache.put(2, 2);
+        cache.pin(&1);
+        cache.pin(&2);
+        // a newcomer is the only thing that can go
+        cache.put(3, 3);
+        assert!(!cache.contains_key(&3));
 
-        assert_eq!(cache.get(&1), Some("b"));
+        // shrinking cannot evict pins, unpinning catches up
+        cache.set_capacity(1);
+        assert_eq!(cache.len(), 2);
+        cache.unpin(&1);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [2]);
     }
 
     #[test]
@@ -0,0 +1,90 @@
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::ops::Deref;
+
+use crate::LruCache;
+
+// source of truth behind a read-through cache
+//
+// any `Fn(&K) -> Option<V>` closure is a loader already, implement the trait
+// for a type that keeps a connection or client around
+pub trait CacheLoader<K, V>: Send + Sync {
+    // None when the source has nothing for the key, nothing is cached then
+    fn load(&self, key: &K) -> Option<V>;
+}
+
+impl<K, V, F> CacheLoader<K, V> for F
+where
+    F: Fn(&K) -> Option<V> + Send + Sync,
+{
+    fn load(&self, key: &K) -> Option<V> {
+        self(key)
+    }
+}
+
+// cache whose `get` loads missing keys through its loader, see
+// `CacheBuilder::build_loading`
+//
+// loads run outside the cache lock and single-flight, threads missing on the
+// same key wait for one load between them. everything else derefs to the
+// underlying cache, so puts and removes still go straight to it
+pub struct LoadingCache<K, V, S = RandomState> {
+    cache: LruCache<K, V, S>,
+    loader: Box<dyn CacheLoader<K, V>>,
+}
+
+impl<K, V, S> LoadingCache<K, V, S> {
+    pub(crate) fn new(cache: LruCache<K, V, S>, loader: Box<dyn CacheLoader<K, V>>) -> Self {
+        Self { cache, loader }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher> LoadingCache<K, V, S> {
+    // a lookup always takes the owned key type, it is cloned into the cache
+    // on a miss
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.cache.load_once(key.clone(), || self.loader.load(key))
+    }
+}
+
+impl<K, V, S> Deref for LoadingCache<K, V, S> {
+    type Target = LruCache<K, V, S>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::LruCache;
+
+    #[test]
+    fn plain_get_reads_through() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&loads);
+        let cache = LruCache::builder()
+            .capacity(2)
+            .build_loading(move |key: &u32| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                (*key < 100).then(|| key.to_string())
+            })
+            .unwrap();
+
+        assert_eq!(cache.get(&1), Some("1".to_string()));
+        assert_eq!(cache.get(&1), Some("1".to_string()));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        // misses upstream are not cached and asked for again
+        assert_eq!(cache.get(&500), None);
+        assert_eq!(cache.get(&500), None);
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+        assert_eq!(cache.len(), 1);
+
+        cache.put(2, "two".to_string());
+        assert_eq!(cache.get(&2), Some("two".to_string()));
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,104 @@
+// the lock around a cache's state
+//
+// std's RwLock by default, parking_lot's with the parking_lot feature, which
+// does not poison and spins briefly before parking. both are used through
+// the same methods, so nothing else depends on the choice. with std a panic
+// while holding the lock still poisons it, and the next caller to take it
+// panics as well
+
+#[cfg(not(feature = "parking_lot"))]
+pub(crate) use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(feature = "parking_lot")]
+pub(crate) use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(not(feature = "parking_lot"))]
+type Inner<T> = std::sync::RwLock<T>;
+
+#[cfg(feature = "parking_lot")]
+type Inner<T> = parking_lot::RwLock<T>;
+
+pub(crate) struct RwLock<T>(Inner<T>);
+
+#[cfg(not(feature = "parking_lot"))]
+impl<T> RwLock<T> {
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.0.read().unwrap()
+    }
+
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.0.write().unwrap()
+    }
+
+    // None when the lock is held elsewhere, or poisoned so the caller's
+    // blocking attempt reports it
+    pub(crate) fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        self.0.try_read().ok()
+    }
+
+    pub(crate) fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.0.try_write().ok()
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.0.into_inner().unwrap()
+    }
+}
+
+#[cfg(feature = "parking_lot")]
+impl<T> RwLock<T> {
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, T> {
+        self.0.read()
+    }
+
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, T> {
+        self.0.write()
+    }
+
+    pub(crate) fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        self.0.try_read()
+    }
+
+    pub(crate) fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.0.try_write()
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+}
+
+impl<T> RwLock<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self(Inner::new(value))
+    }
+}
+
+// trades a write guard for a read guard without letting another writer in
+pub(crate) fn downgrade<T>(guard: RwLockWriteGuard<'_, T>) -> RwLockReadGuard<'_, T> {
+    RwLockWriteGuard::downgrade(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guards_behave_alike_on_either_backend() {
+        let lock = RwLock::new(1);
+
+        let read = lock.read();
+        assert!(lock.try_read().is_some());
+        assert!(lock.try_write().is_none());
+        drop(read);
+
+        let mut write = lock.write();
+        *write += 1;
+        let read = downgrade(write);
+        assert_eq!(*read, 2);
+        assert!(lock.try_write().is_none());
+        drop(read);
+
+        assert_eq!(lock.into_inner(), 2);
+    }
+}
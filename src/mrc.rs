@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+// sampled keys tracked at once, the oldest is forgotten past this so memory
+// stays bounded however many keys the cache sees
+const MAX_TRACKED: usize = 1 << 16;
+
+// ticks the tree starts out with, it is renumbered and resized when full
+const MIN_TICKS: usize = 64;
+
+// hit rate curve estimated from a spatial sample of the lookups, after
+// SHARDS (Waldspurger et al., FAST '15)
+//
+// a key is sampled or not depending on its hash alone, so every lookup of a
+// sampled key is seen. the reuse distance of each lookup, the number of
+// other sampled keys used since the key's previous lookup, is scaled back up
+// by the sample rate. a cache of capacity c hits exactly the lookups whose
+// reuse distance is below c
+//
+// every lookup gets the next tick and each tracked key is marked at the tick
+// of its last lookup in a fenwick tree, so a reuse distance is the count of
+// marks after the key's own, in O(log n). at most MAX_TRACKED keys are kept
+// and the least recently used one is dropped for a new key, so capacities
+// beyond MAX_TRACKED / rate read lower than they would
+pub(crate) struct HitRateCurve {
+    rate: f64,
+    threshold: u64,
+    // tick of each tracked key's last lookup
+    last: HashMap<u64, usize>,
+    // key hash by tick, only meaningful where the tree has a mark
+    order: Vec<u64>,
+    marks: Fenwick,
+    next: usize,
+    // lookups by unscaled reuse distance
+    distances: Vec<u64>,
+    lookups: u64,
+}
+
+impl HitRateCurve {
+    // `rate` is the sampled fraction of the key space, in (0, 1]
+    pub(crate) fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            threshold: (rate * u64::MAX as f64) as u64,
+            last: HashMap::new(),
+            order: Vec::new(),
+            marks: Fenwick::new(0),
+            next: 0,
+            distances: Vec::new(),
+            lookups: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, hash: u64) {
+        // the cache hash also picks table buckets, remix it so the sample
+        // does not favour some buckets
+        if splitmix64(hash) > self.threshold {
+            return;
+        }
+        self.lookups += 1;
+
+        if self.next == self.order.len() {
+            self.renumber();
+        }
+        let tick = self.next;
+        self.next += 1;
+        self.order[tick] = hash;
+
+        // a key seen for the first time misses at every capacity
+        let Some(prev) = self.last.insert(hash, tick) else {
+            self.marks.add(tick);
+            if self.last.len() > MAX_TRACKED {
+                let oldest = self.marks.first();
+                self.marks.remove(oldest);
+                self.last.remove(&self.order[oldest]);
+            }
+            return;
+        };
+        let distance = self.last.len() - self.marks.prefix(prev + 1);
+        self.marks.remove(prev);
+        self.marks.add(tick);
+
+        if self.distances.len() <= distance {
+            self.distances.resize(distance + 1, 0);
+        }
+        self.distances[distance] += 1;
+    }
+
+    // packs the tracked keys into the lowest ticks, in order, leaving as
+    // many free as are taken
+    fn renumber(&mut self) {
+        let mut live: Vec<(usize, u64)> = self.last.iter().map(|(&h, &t)| (t, h)).collect();
+        live.sort_unstable();
+        let size = (live.len() * 2).max(MIN_TICKS);
+        self.order = vec![0; size];
+        self.marks = Fenwick::new(size);
+        for (tick, &(_, hash)) in live.iter().enumerate() {
+            self.order[tick] = hash;
+            self.last.insert(hash, tick);
+            self.marks.add(tick);
+        }
+        self.next = live.len();
+    }
+
+    // expected hit rate of an LRU cache holding `capacity` entries, 0 until
+    // a sampled key has been looked up
+    pub(crate) fn hit_rate(&self, capacity: usize) -> f64 {
+        if self.lookups == 0 {
+            return 0.0;
+        }
+        let scaled = (capacity as f64 * self.rate).ceil() as usize;
+        let hits: u64 = self.distances.iter().take(scaled).sum();
+        hits as f64 / self.lookups as f64
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.last.clear();
+        self.order.clear();
+        self.marks = Fenwick::new(0);
+        self.next = 0;
+        self.distances.clear();
+        self.lookups = 0;
+    }
+}
+
+// counts of marked ticks, by prefix
+struct Fenwick(Vec<usize>);
+
+impl Fenwick {
+    fn new(size: usize) -> Self {
+        Self(vec![0; size])
+    }
+
+    fn add(&mut self, mut i: usize) {
+        i += 1;
+        while i <= self.0.len() {
+            self.0[i - 1] += 1;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    fn remove(&mut self, mut i: usize) {
+        i += 1;
+        while i <= self.0.len() {
+            self.0[i - 1] -= 1;
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    // marks below `end`
+    fn prefix(&self, mut end: usize) -> usize {
+        let mut sum = 0;
+        while end > 0 {
+            sum += self.0[end - 1];
+            end &= end - 1;
+        }
+        sum
+    }
+
+    // the lowest marked tick, there has to be one
+    fn first(&self) -> usize {
+        let mut pos = 0;
+        let mut step = self.0.len().next_power_of_two();
+        while step > 0 {
+            if pos + step <= self.0.len() && self.0[pos + step - 1] == 0 {
+                pos += step;
+            }
+            step /= 2;
+        }
+        pos
+    }
+}
+
+pub(crate) fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_sample_matches_exact_lru() {
+        let mut curve = HitRateCurve::new(1.0);
+
+        // a loop over 10 keys hits only once all 10 fit
+        for _ in 0..10 {
+            for key in 0..10 {
+                curve.record(key);
+            }
+        }
+
+        assert_eq!(curve.hit_rate(9), 0.0);
+        assert_eq!(curve.hit_rate(10), 0.9);
+        assert_eq!(curve.hit_rate(100), 0.9);
+    }
+
+    #[test]
+    fn sampled_curve_scales_capacities() {
+        let mut curve = HitRateCurve::new(0.1);
+
+        for _ in 0..20 {
+            for key in 0..2000u64 {
+                curve.record(key);
+            }
+        }
+
+        assert!(curve.hit_rate(1500) < 0.2);
+        assert!(curve.hit_rate(2500) > 0.9);
+    }
+
+    #[test]
+    fn tracking_is_capped_at_the_oldest_keys() {
+        let mut curve = HitRateCurve::new(1.0);
+
+        for key in 0..MAX_TRACKED as u64 + 10 {
+            curve.record(key);
+        }
+        assert_eq!(curve.last.len(), MAX_TRACKED);
+
+        // the first ten were forgotten, the rest are still a reuse
+        curve.record(5);
+        curve.record(20);
+        assert_eq!(
+            curve.hit_rate(usize::MAX),
+            1.0 / (MAX_TRACKED as f64 + 12.0)
+        );
+    }
+}
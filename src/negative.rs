@@ -0,0 +1,78 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::time::Duration;
+
+use crate::LruCache;
+
+// what a negative caching cache stores for a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cached<V> {
+    Found(V),
+    // the backend was asked and had nothing
+    NotFound,
+}
+
+// result of `NegativeLruCache::lookup`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup<V> {
+    Hit(V),
+    // the key is known to be missing upstream, no need to ask again yet
+    NegativeHit,
+    // nothing is known about the key
+    Miss,
+}
+
+// cache that also remembers keys the backend did not have, so repeated
+// lookups of missing keys are absorbed too. negative entries carry their own,
+// usually much shorter, ttl
+pub type NegativeLruCache<K, V, S = RandomState> = LruCache<K, Cached<V>, S>;
+
+impl<K: Eq + Hash, V: Clone, S: BuildHasher> NegativeLruCache<K, V, S> {
+    pub fn put_found(&self, key: K, value: V) -> Option<Cached<V>> {
+        self.put(key, Cached::Found(value))
+    }
+
+    // records the key as missing for `ttl`, independently of the time to
+    // live found values get
+    pub fn put_negative(&self, key: K, ttl: Duration) -> Option<Cached<V>> {
+        self.put_with_ttl(key, Cached::NotFound, ttl)
+    }
+
+    pub fn lookup<Q>(&self, key: &Q) -> Lookup<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.get(key) {
+            Some(Cached::Found(value)) => Lookup::Hit(value),
+            Some(Cached::NotFound) => Lookup::NegativeHit,
+            None => Lookup::Miss,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockClock;
+
+    #[test]
+    fn negative_entries_expire_on_their_own_ttl() {
+        let clock = MockClock::new();
+        let cache: NegativeLruCache<&str, u32> = LruCache::builder()
+            .time_to_live(Duration::from_secs(300))
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+
+        cache.put_found("alice", 1);
+        cache.put_negative("mallory", Duration::from_secs(5));
+        assert_eq!(cache.lookup("alice"), Lookup::Hit(1));
+        assert_eq!(cache.lookup("mallory"), Lookup::NegativeHit);
+        assert_eq!(cache.lookup("bob"), Lookup::Miss);
+
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(cache.lookup("mallory"), Lookup::Miss);
+        assert_eq!(cache.lookup("alice"), Lookup::Hit(1));
+    }
+}
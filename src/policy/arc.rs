@@ -0,0 +1,215 @@
+use super::ghost::Ghosts;
+use super::{EvictionPolicy, Links, List};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Recent,
+    Frequent,
+}
+
+// adaptive replacement cache (Megiddo and Modha, FAST '03)
+//
+// entries seen once sit in a recent list, entries used again move to a
+// frequent list. both lists keep a ghost list of the keys they evicted, and
+// the target size of the recent list follows whichever ghosts get hit: a key
+// coming back from the recent ghosts asks for more room for recency, one
+// from the frequent ghosts for more room for frequency. scans therefore
+// only churn the recent list while loops that just missed grow it
+//
+// the cache picks a victim before it inserts the new entry, so a returning
+// key adjusts the target for the eviction after its own
+#[derive(Debug)]
+pub struct ArcPolicy {
+    capacity: usize,
+    // target size of the recent list
+    target: usize,
+    links: Links,
+    // (list, key hash) per slot
+    slots: Vec<(Segment, u64)>,
+    recent: List,
+    frequent: List,
+    recent_ghosts: Ghosts,
+    frequent_ghosts: Ghosts,
+    // the slot last named by `choose_victim`, its removal leaves a ghost
+    evicting: Option<usize>,
+}
+
+impl ArcPolicy {
+    // the target and the ghosts are bounded by the capacity `on_resize`
+    // hands over
+    pub fn new() -> Self {
+        Self {
+            capacity: 1,
+            target: 0,
+            links: Links::default(),
+            slots: Vec::new(),
+            recent: List::new(),
+            frequent: List::new(),
+            recent_ghosts: Ghosts::default(),
+            frequent_ghosts: Ghosts::default(),
+            evicting: None,
+        }
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        match self.slots[slot].0 {
+            Segment::Recent => self.recent.unlink(&mut self.links, slot),
+            Segment::Frequent => self.frequent.unlink(&mut self.links, slot),
+        }
+    }
+
+    fn push(&mut self, slot: usize, segment: Segment) {
+        self.slots[slot].0 = segment;
+        match segment {
+            Segment::Recent => self.recent.push_back(&mut self.links, slot),
+            Segment::Frequent => self.frequent.push_back(&mut self.links, slot),
+        }
+    }
+
+    // each list and its ghosts together stay within the capacity
+    fn trim_ghosts(&mut self) {
+        let recent = self.capacity.saturating_sub(self.recent.len());
+        self.recent_ghosts.truncate(recent);
+        let frequent = self.capacity.saturating_sub(self.frequent.len());
+        self.frequent_ghosts.truncate(frequent);
+    }
+}
+
+impl Default for ArcPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvictionPolicy for ArcPolicy {
+    fn on_insert(&mut self, slot: usize, hash: u64) {
+        if self.slots.len() <= slot {
+            self.slots.resize(slot + 1, (Segment::Recent, 0));
+        }
+        self.links.track(slot);
+        self.slots[slot].1 = hash;
+
+        let (recent, frequent) = (self.recent_ghosts.len(), self.frequent_ghosts.len());
+        if self.recent_ghosts.remove(hash) {
+            let step = (frequent / recent.max(1)).max(1);
+            self.target = self.target.saturating_add(step).min(self.capacity);
+            self.push(slot, Segment::Frequent);
+        } else if self.frequent_ghosts.remove(hash) {
+            let step = (recent / frequent.max(1)).max(1);
+            self.target = self.target.saturating_sub(step);
+            self.push(slot, Segment::Frequent);
+        } else {
+            self.push(slot, Segment::Recent);
+        }
+        self.trim_ghosts();
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.unlink(slot);
+        self.push(slot, Segment::Frequent);
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.unlink(slot);
+        // only evictions leave a ghost, a key removed on purpose is gone
+        if self.evicting.take() == Some(slot) {
+            let (segment, hash) = self.slots[slot];
+            match segment {
+                Segment::Recent => self.recent_ghosts.push(hash),
+                Segment::Frequent => self.frequent_ghosts.push(hash),
+            }
+            self.trim_ghosts();
+        }
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        let victim = if self.recent.len() > self.target || self.frequent.len() == 0 {
+            self.recent.front()
+        } else {
+            self.frequent.front()
+        };
+        self.evicting = victim;
+        victim
+    }
+
+    fn on_resize(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.target = self.target.min(self.capacity);
+        self.trim_ghosts();
+    }
+
+    fn clear(&mut self) {
+        self.target = 0;
+        self.links.clear();
+        self.slots.clear();
+        self.recent.clear();
+        self.frequent.clear();
+        self.recent_ghosts.clear();
+        self.frequent_ghosts.clear();
+        self.evicting = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    #[test]
+    fn scan_only_churns_the_recent_list() {
+        let cache = LruCache::builder()
+            .capacity(4)
+            .eviction_policy(ArcPolicy::new())
+            .build()
+            .unwrap();
+
+        for _ in 0..2 {
+            cache.get_or_insert_with(1, || 1);
+            cache.get_or_insert_with(2, || 2);
+        }
+        for key in 10..30 {
+            cache.put(key, key);
+        }
+
+        assert!(cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn recent_ghost_hit_grows_the_target() {
+        let mut arc = ArcPolicy::new();
+        arc.on_resize(2);
+        arc.on_insert(0, 1);
+        arc.on_access(0);
+        arc.on_insert(1, 2);
+
+        // key 2 is evicted from the recent list and comes back
+        assert_eq!(arc.choose_victim(), Some(1));
+        arc.on_remove(1);
+        arc.on_insert(1, 3);
+        assert_eq!(arc.choose_victim(), Some(1));
+        arc.on_remove(1);
+        arc.on_insert(1, 2);
+
+        assert_eq!(arc.target, 1);
+        assert_eq!(arc.slots[1].0, Segment::Frequent);
+    }
+
+    #[test]
+    fn shrinking_drops_ghosts_past_the_capacity() {
+        let mut arc = ArcPolicy::new();
+        arc.on_resize(4);
+        for slot in 0..4 {
+            arc.on_insert(slot, slot as u64);
+        }
+        for _ in 0..3 {
+            let victim = arc.choose_victim().unwrap();
+            arc.on_remove(victim);
+        }
+        assert_eq!(arc.recent_ghosts.len(), 3);
+
+        arc.on_resize(2);
+        assert_eq!(arc.recent_ghosts.len(), 1);
+    }
+}
@@ -0,0 +1,91 @@
+use super::{EvictionPolicy, Links, List};
+
+// first in, first out. reads are not tracked at all, which lets `get` run
+// under the read lock as long as nothing else needs a write on every use,
+// see `EvictionPolicy::tracks_access`
+#[derive(Debug)]
+pub struct Fifo {
+    links: Links,
+    order: List,
+}
+
+impl Fifo {
+    pub fn new() -> Self {
+        Self {
+            links: Links::default(),
+            order: List::new(),
+        }
+    }
+}
+
+impl Default for Fifo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvictionPolicy for Fifo {
+    fn on_insert(&mut self, slot: usize, _: u64) {
+        self.links.track(slot);
+        self.order.push_back(&mut self.links, slot);
+    }
+
+    fn on_access(&mut self, _: usize) {}
+
+    fn on_remove(&mut self, slot: usize) {
+        self.order.unlink(&mut self.links, slot);
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        self.order.front()
+    }
+
+    fn clear(&mut self) {
+        self.links.clear();
+        self.order.clear();
+    }
+
+    fn tracks_access(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    fn fifo_cache() -> LruCache<u32, u32> {
+        LruCache::builder()
+            .capacity(2)
+            .eviction_policy(Fifo::new())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_do_not_reorder() {
+        let cache = fifo_cache();
+
+        cache.put(1, 1);
+        cache.put(2, 2);
+        assert_eq!(cache.get(&1), Some(1));
+        cache.put(3, 3);
+
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [3, 2]);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn get_runs_under_the_read_lock() {
+        let cache = fifo_cache();
+        cache.put(1, 1);
+
+        // a get that needed the write lock would never return
+        let _reader = cache.inner.read();
+        assert_eq!(cache.get(&1), Some(1));
+        assert_eq!(cache.get(&2), None);
+    }
+}
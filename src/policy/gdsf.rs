@@ -0,0 +1,158 @@
+use std::collections::BTreeSet;
+
+use super::EvictionPolicy;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Entry {
+    uses: u64,
+    weight: u64,
+    cost: u64,
+    priority: f64,
+    stamp: u64,
+}
+
+// greedy dual size frequency (Cherkasova, HPL-98-69)
+//
+// an entry is worth uses * cost / weight, plus the worth of the last victim
+// at the time it was ranked. cheap and heavy entries go first, small ones
+// that are expensive to recompute stay. the added inflation makes entries
+// that have not been used for a while fall behind newer ones, so a high
+// worth does not pin an entry forever. the costs come from
+// `CacheBuilder::cost` and the weights from the weigher, both default to 1
+#[derive(Debug)]
+pub struct Gdsf {
+    inflation: f64,
+    clock: u64,
+    slots: Vec<Entry>,
+    // (priority bits, stamp, slot), lowest first. priorities are never
+    // negative, so their bits sort like the numbers
+    order: BTreeSet<(u64, u64, usize)>,
+}
+
+impl Gdsf {
+    pub fn new() -> Self {
+        Self {
+            inflation: 0.0,
+            clock: 0,
+            slots: Vec::new(),
+            order: BTreeSet::new(),
+        }
+    }
+
+    fn key(&self, slot: usize) -> (u64, u64, usize) {
+        let entry = &self.slots[slot];
+        (entry.priority.to_bits(), entry.stamp, slot)
+    }
+
+    // untracks the slot, the caller changes it and ranks it again
+    fn untrack(&mut self, slot: usize) {
+        self.order.remove(&self.key(slot));
+    }
+
+    fn rank(&mut self, slot: usize) {
+        self.clock += 1;
+        let entry = &mut self.slots[slot];
+        entry.stamp = self.clock;
+        entry.priority =
+            self.inflation + (entry.uses * entry.cost) as f64 / entry.weight.max(1) as f64;
+        self.order.insert(self.key(slot));
+    }
+}
+
+impl Default for Gdsf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvictionPolicy for Gdsf {
+    fn on_insert(&mut self, slot: usize, _: u64) {
+        if self.slots.len() <= slot {
+            self.slots.resize(slot + 1, Entry::default());
+        }
+        self.slots[slot] = Entry {
+            uses: 1,
+            weight: 1,
+            cost: 1,
+            ..Entry::default()
+        };
+        self.rank(slot);
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.untrack(slot);
+        self.slots[slot].uses += 1;
+        self.rank(slot);
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.untrack(slot);
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        let &(_, _, slot) = self.order.first()?;
+        self.inflation = self.slots[slot].priority;
+        Some(slot)
+    }
+
+    fn clear(&mut self) {
+        self.inflation = 0.0;
+        self.slots.clear();
+        self.order.clear();
+    }
+
+    fn on_weigh(&mut self, slot: usize, weight: u64, cost: u64) {
+        self.untrack(slot);
+        let entry = &mut self.slots[slot];
+        entry.weight = weight;
+        entry.cost = cost;
+        self.rank(slot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    #[test]
+    fn cheap_heavy_entries_go_first() {
+        let cache = LruCache::builder()
+            .max_weight(10)
+            .weigher(|_: &&str, v: &Vec<u8>| v.len() as u64)
+            .cost(|k: &&str, _: &Vec<u8>| if k.starts_with("slow") { 100 } else { 1 })
+            .eviction_policy(Gdsf::new())
+            .build()
+            .unwrap();
+
+        // the oldest entry is the expensive one, LRU would drop it
+        cache.put("slow query", vec![0; 4]);
+        cache.put("cheap blob", vec![0; 4]);
+        cache.put("another blob", vec![0; 4]);
+
+        assert!(cache.contains_key(&"slow query"));
+        assert!(!cache.contains_key(&"cheap blob"));
+        assert!(cache.contains_key(&"another blob"));
+    }
+
+    #[test]
+    fn inflation_lets_stale_worth_age_out() {
+        let cache = LruCache::builder()
+            .capacity(2)
+            .eviction_policy(Gdsf::new())
+            .build()
+            .unwrap();
+
+        cache.put(0, 0);
+        for _ in 0..3 {
+            cache.get(&0);
+        }
+        // each victim raises the bar until the newcomers, ranked only by
+        // their single use, outrank key 0's old uses
+        for key in 1..10 {
+            cache.put(key, key);
+        }
+
+        assert!(!cache.contains_key(&0));
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::{BTreeMap, HashMap};
+
+// hashes of keys that were evicted, oldest first, for policies that adapt
+// when an evicted key comes back
+#[derive(Debug, Default)]
+pub(crate) struct Ghosts {
+    // stamp to hash, and hash to stamp
+    order: BTreeMap<u64, u64>,
+    index: HashMap<u64, u64>,
+    clock: u64,
+}
+
+impl Ghosts {
+    pub(crate) fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    // remember a hash as the newest ghost
+    pub(crate) fn push(&mut self, hash: u64) {
+        self.remove(hash);
+        self.clock += 1;
+        self.order.insert(self.clock, hash);
+        self.index.insert(hash, self.clock);
+    }
+
+    // forget a hash, true if it was remembered
+    pub(crate) fn remove(&mut self, hash: u64) -> bool {
+        match self.index.remove(&hash) {
+            Some(stamp) => {
+                self.order.remove(&stamp);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // drop the oldest ghosts until at most `len` are left
+    pub(crate) fn truncate(&mut self, len: usize) {
+        while self.index.len() > len {
+            let (_, hash) = self.order.pop_first().expect("ghosts are indexed");
+            self.index.remove(&hash);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.order.clear();
+        self.index.clear();
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeSet;
+
+use super::EvictionPolicy;
+
+// default number of inserts and accesses between agings, per tracked entry
+const DECAY_PER_ENTRY: u64 = 10;
+
+// least frequently used, with periodic aging
+//
+// every entry carries a use count and the lowest count is evicted, ties going
+// to the least recently used. after a while all counts are halved, so an
+// entry that was popular long ago loses its lead and can be evicted instead
+// of staying pinned by its old count
+#[derive(Debug)]
+pub struct Lfu {
+    // (uses, stamp) per slot
+    slots: Vec<(u64, u64)>,
+    // (uses, stamp, slot), lowest first
+    order: BTreeSet<(u64, u64, usize)>,
+    clock: u64,
+    // inserts and accesses since the last aging, and how many there may be
+    events: u64,
+    period: Option<u64>,
+}
+
+impl Lfu {
+    // counts are halved after ten inserts or accesses per tracked entry
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            order: BTreeSet::new(),
+            clock: 0,
+            events: 0,
+            period: None,
+        }
+    }
+
+    // halve the counts every `period` inserts and accesses instead, a shorter
+    // period forgets faster
+    pub fn with_decay_period(period: u64) -> Self {
+        Self {
+            period: Some(period.max(1)),
+            ..Self::new()
+        }
+    }
+
+    fn touch(&mut self, slot: usize, uses: u64) {
+        self.clock += 1;
+        self.slots[slot] = (uses, self.clock);
+        self.order.insert((uses, self.clock, slot));
+
+        self.events += 1;
+        let period = self
+            .period
+            .unwrap_or(DECAY_PER_ENTRY * self.order.len() as u64);
+        if self.events >= period {
+            self.age();
+        }
+    }
+
+    fn untrack(&mut self, slot: usize) -> u64 {
+        let (uses, stamp) = self.slots[slot];
+        self.order.remove(&(uses, stamp, slot));
+        uses
+    }
+
+    fn age(&mut self) {
+        self.events = 0;
+        self.order = std::mem::take(&mut self.order)
+            .into_iter()
+            .map(|(uses, stamp, slot)| {
+                self.slots[slot].0 = uses / 2;
+                (uses / 2, stamp, slot)
+            })
+            .collect();
+    }
+}
+
+impl Default for Lfu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvictionPolicy for Lfu {
+    fn on_insert(&mut self, slot: usize, _: u64) {
+        if self.slots.len() <= slot {
+            self.slots.resize(slot + 1, (0, 0));
+        }
+        self.touch(slot, 1);
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        let uses = self.untrack(slot);
+        self.touch(slot, uses + 1);
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.untrack(slot);
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        self.order.first().map(|&(_, _, slot)| slot)
+    }
+
+    fn clear(&mut self) {
+        self.slots.clear();
+        self.order.clear();
+        self.events = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    fn lfu_cache(capacity: usize, policy: Lfu) -> LruCache<u32, u32> {
+        LruCache::builder()
+            .capacity(capacity)
+            .eviction_policy(policy)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn frequent_keys_survive_a_scan() {
+        let cache = lfu_cache(3, Lfu::new());
+
+        for _ in 0..5 {
+            cache.get_or_insert_with(1, || 1);
+            cache.get_or_insert_with(2, || 2);
+        }
+        // one-off keys only ever evict each other
+        for key in 10..20 {
+            cache.put(key, key);
+        }
+
+        assert!(cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+        assert_eq!(cache.len(), 3);
+    }
+
+    #[test]
+    fn old_popularity_ages_out() {
+        let cache = lfu_cache(2, Lfu::with_decay_period(8));
+
+        for _ in 0..3 {
+            cache.get_or_insert_with(1, || 1);
+        }
+        // newcomers are used twice, key 1 stays ahead only until its count
+        // has been halved
+        for key in 10..14 {
+            cache.put(key, key);
+            cache.get(&key);
+        }
+
+        assert!(!cache.contains_key(&1));
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::{BTreeSet, VecDeque};
+
+use super::EvictionPolicy;
+
+// LRU-K (O'Neil et al., SIGMOD '93)
+//
+// the victim is the entry whose k-th most recent access is oldest. an entry
+// accessed fewer than k times has no k-th access and goes before every entry
+// that has one, the least recently used of them first. with k = 2 an entry
+// touched once in a burst cannot displace one that keeps being reused, k = 1
+// is plain LRU
+#[derive(Debug)]
+pub struct LruK {
+    k: usize,
+    clock: u64,
+    // the last k access times per slot, oldest first
+    history: Vec<VecDeque<u64>>,
+    // (k-th most recent access or 0, last access, slot), victim first
+    order: BTreeSet<(u64, u64, usize)>,
+}
+
+impl LruK {
+    // a `k` of 0 is taken as 1
+    pub fn new(k: usize) -> Self {
+        Self {
+            k: k.max(1),
+            clock: 0,
+            history: Vec::new(),
+            order: BTreeSet::new(),
+        }
+    }
+
+    fn rank(&self, slot: usize) -> (u64, u64, usize) {
+        let history = &self.history[slot];
+        let kth = if history.len() == self.k {
+            history[0]
+        } else {
+            0
+        };
+        (kth, *history.back().expect("tracked slots were used"), slot)
+    }
+
+    fn touch(&mut self, slot: usize) {
+        self.clock += 1;
+        let history = &mut self.history[slot];
+        if history.len() == self.k {
+            history.pop_front();
+        }
+        history.push_back(self.clock);
+        self.order.insert(self.rank(slot));
+    }
+}
+
+impl EvictionPolicy for LruK {
+    fn on_insert(&mut self, slot: usize, _: u64) {
+        if self.history.len() <= slot {
+            self.history.resize_with(slot + 1, VecDeque::new);
+        }
+        self.history[slot].clear();
+        self.touch(slot);
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.order.remove(&self.rank(slot));
+        self.touch(slot);
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.order.remove(&self.rank(slot));
+        self.history[slot].clear();
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        self.order.first().map(|&(_, _, slot)| slot)
+    }
+
+    fn clear(&mut self) {
+        self.history.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    fn lru_k_cache(k: usize) -> LruCache<u32, u32> {
+        LruCache::builder()
+            .capacity(3)
+            .eviction_policy(LruK::new(k))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn reused_entries_outlast_a_burst() {
+        let cache = lru_k_cache(2);
+
+        for key in [1, 2, 1, 2] {
+            cache.get_or_insert_with(key, || key);
+        }
+        // every burst key is newer than any use of 1 and 2, yet seen once
+        for key in 10..20 {
+            cache.put(key, key);
+        }
+
+        assert!(cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+    }
+
+    #[test]
+    fn k_of_one_is_lru() {
+        let policy = lru_k_cache(1);
+        let plain = LruCache::new(3);
+
+        for key in [1, 2, 3, 1, 4, 2, 5, 1, 6] {
+            policy.get_or_insert_with(key, || key);
+            plain.get_or_insert_with(key, || key);
+        }
+        let mut keys: Vec<_> = policy.keys().collect();
+        let mut expected: Vec<_> = plain.keys().collect();
+        keys.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(keys, expected);
+    }
+}
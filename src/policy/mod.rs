@@ -0,0 +1,300 @@
+use crate::NIL;
+
+mod arc;
+mod fifo;
+mod gdsf;
+mod ghost;
+mod lfu;
+mod lru_k;
+mod mru;
+mod sampled;
+mod second_chance;
+mod sketch;
+mod slru;
+mod tinylfu;
+mod two_queue;
+
+pub(crate) use ghost::Ghosts;
+
+pub use arc::ArcPolicy;
+pub use fifo::Fifo;
+pub use gdsf::Gdsf;
+pub use lfu::Lfu;
+pub use lru_k::LruK;
+pub use mru::Mru;
+pub use sampled::Sampled;
+pub use second_chance::SecondChance;
+pub use slru::Slru;
+pub use tinylfu::TinyLfu;
+pub use two_queue::TwoQueue;
+
+// decides which entry makes room when the cache is full
+//
+// entries are identified by their slot, a small index that stays the same
+// for as long as the entry is cached and is reused once it is removed, so a
+// policy can keep its bookkeeping in a Vec. an insert also passes the key's
+// hash, which is the same every time that key comes back, for policies that
+// remember keys they no longer hold. every hook runs under the cache write
+// lock. `choose_victim` only names the slot, the cache then evicts it and
+// reports it back through `on_remove` like any other removal
+pub trait EvictionPolicy: Send + Sync {
+    fn on_insert(&mut self, slot: usize, hash: u64);
+    // the entry was read or overwritten
+    fn on_access(&mut self, slot: usize);
+    fn on_remove(&mut self, slot: usize);
+    // None only when no entry is tracked
+    fn choose_victim(&mut self) -> Option<usize>;
+    // every entry is gone at once
+    fn clear(&mut self);
+    // the entry's weight, or its recomputation cost from
+    // `CacheBuilder::cost`, was worked out. follows every insert and every
+    // update in place
+    fn on_weigh(&mut self, slot: usize, weight: u64, cost: u64) {
+        let _ = (slot, weight, cost);
+    }
+    // false for a policy whose `on_access` would do nothing, or that notes
+    // accesses through `on_shared_access` instead. the cache then never
+    // calls `on_access` and leaves its own recency list in insertion order,
+    // and `get` can serve hits under the read lock
+    fn tracks_access(&self) -> bool {
+        true
+    }
+    // an access to an entry, for a policy whose `tracks_access` is false.
+    // may run under the read lock, so whatever it records has to be atomic
+    fn on_shared_access(&self, slot: usize) {
+        let _ = slot;
+    }
+    // the cache's capacity, told at build and whenever it changes after,
+    // through `set_capacity` or `auto_capacity`. usize::MAX when unbounded
+    fn on_resize(&mut self, capacity: usize) {
+        let _ = capacity;
+    }
+}
+
+// `percent` of `capacity`, rounded down, without overflowing for an
+// unbounded cache
+pub(crate) fn share(capacity: usize, percent: usize) -> usize {
+    capacity
+        .checked_mul(percent)
+        .map_or(capacity / 100 * percent, |scaled| scaled / 100)
+}
+
+// (prev, next) per slot, shared by every list a policy threads through its
+// slots. a slot is in at most one of them at a time
+#[derive(Debug, Default)]
+pub(crate) struct Links(Vec<(usize, usize)>);
+
+impl Links {
+    // make room for a slot the table has not seen yet
+    pub(crate) fn track(&mut self, slot: usize) {
+        if self.0.len() <= slot {
+            self.0.resize(slot + 1, (NIL, NIL));
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+// an intrusive list of slots, front first
+#[derive(Debug)]
+pub(crate) struct List {
+    head: usize,
+    tail: usize,
+    len: usize,
+}
+
+impl List {
+    pub(crate) fn new() -> Self {
+        Self {
+            head: NIL,
+            tail: NIL,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn front(&self) -> Option<usize> {
+        (self.head != NIL).then_some(self.head)
+    }
+
+    pub(crate) fn back(&self) -> Option<usize> {
+        (self.tail != NIL).then_some(self.tail)
+    }
+
+    pub(crate) fn push_back(&mut self, links: &mut Links, slot: usize) {
+        links.0[slot] = (self.tail, NIL);
+        match self.tail {
+            NIL => self.head = slot,
+            tail => links.0[tail].1 = slot,
+        }
+        self.tail = slot;
+        self.len += 1;
+    }
+
+    pub(crate) fn unlink(&mut self, links: &mut Links, slot: usize) {
+        let (prev, next) = links.0[slot];
+        match prev {
+            NIL => self.head = next,
+            prev => links.0[prev].1 = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            next => links.0[next].0 = prev,
+        }
+        self.len -= 1;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+// least recently used, the same order the cache keeps without a policy. as
+// a separate policy it costs a second list, so it mostly serves as a
+// starting point
+#[derive(Debug)]
+pub struct Lru {
+    links: Links,
+    order: List,
+}
+
+impl Lru {
+    pub fn new() -> Self {
+        Self {
+            links: Links::default(),
+            order: List::new(),
+        }
+    }
+}
+
+impl Default for Lru {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvictionPolicy for Lru {
+    fn on_insert(&mut self, slot: usize, _: u64) {
+        self.links.track(slot);
+        self.order.push_back(&mut self.links, slot);
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.order.unlink(&mut self.links, slot);
+        self.order.push_back(&mut self.links, slot);
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.order.unlink(&mut self.links, slot);
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        self.order.front()
+    }
+
+    fn clear(&mut self) {
+        self.links.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::LruCache;
+
+    // evicts the entry inserted last, whatever was read since
+    #[derive(Default)]
+    struct Newest(Vec<usize>);
+
+    impl EvictionPolicy for Newest {
+        fn on_insert(&mut self, slot: usize, _: u64) {
+            self.0.push(slot);
+        }
+        fn on_access(&mut self, _: usize) {}
+        fn on_remove(&mut self, slot: usize) {
+            self.0.retain(|&s| s != slot);
+        }
+        fn choose_victim(&mut self) -> Option<usize> {
+            self.0.last().copied()
+        }
+        fn clear(&mut self) {
+            self.0.clear();
+        }
+    }
+
+    #[test]
+    fn custom_policy_picks_the_victim() {
+        let cache = LruCache::builder()
+            .capacity(2)
+            .eviction_policy(Newest::default())
+            .build()
+            .unwrap();
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [3, 1]);
+
+        cache.remove(&3);
+        cache.put(4, "d");
+        cache.put(5, "e");
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [5, 1]);
+    }
+
+    struct Sizes(Arc<Mutex<Vec<usize>>>);
+
+    impl EvictionPolicy for Sizes {
+        fn on_insert(&mut self, _: usize, _: u64) {}
+        fn on_access(&mut self, _: usize) {}
+        fn on_remove(&mut self, _: usize) {}
+        fn choose_victim(&mut self) -> Option<usize> {
+            None
+        }
+        fn clear(&mut self) {}
+        fn on_resize(&mut self, capacity: usize) {
+            self.0.lock().unwrap().push(capacity);
+        }
+    }
+
+    #[test]
+    fn policy_is_told_every_capacity() {
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        let cache = LruCache::<u32, u32>::builder()
+            .capacity(8)
+            .eviction_policy(Sizes(sizes.clone()))
+            .build()
+            .unwrap();
+        cache.set_capacity(4);
+        cache.set_capacity(usize::MAX);
+
+        assert_eq!(*sizes.lock().unwrap(), [8, 4, usize::MAX]);
+    }
+
+    #[test]
+    fn lru_policy_matches_the_builtin_order() {
+        let plain = LruCache::new(3);
+        let policy = LruCache::builder()
+            .capacity(3)
+            .eviction_policy(Lru::new())
+            .build()
+            .unwrap();
+
+        for cache in [&plain, &policy] {
+            for key in [1, 2, 3, 1, 4, 2, 5, 1, 6] {
+                cache.get_or_insert_with(key, || key);
+            }
+        }
+        assert_eq!(
+            plain.keys().collect::<Vec<_>>(),
+            policy.keys().collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use super::{EvictionPolicy, Links, List};
+
+// most recently used goes first. a cache slightly smaller than a dataset
+// that is read in a loop misses every time under LRU, since each key is
+// evicted just before it comes around again. evicting the newest entry
+// instead keeps most of the loop cached
+#[derive(Debug)]
+pub struct Mru {
+    links: Links,
+    order: List,
+}
+
+impl Mru {
+    pub fn new() -> Self {
+        Self {
+            links: Links::default(),
+            order: List::new(),
+        }
+    }
+}
+
+impl Default for Mru {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvictionPolicy for Mru {
+    fn on_insert(&mut self, slot: usize, _: u64) {
+        self.links.track(slot);
+        self.order.push_back(&mut self.links, slot);
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.order.unlink(&mut self.links, slot);
+        self.order.push_back(&mut self.links, slot);
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.order.unlink(&mut self.links, slot);
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        self.order.back()
+    }
+
+    fn clear(&mut self) {
+        self.links.clear();
+        self.order.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    #[test]
+    fn cyclic_scan_keeps_hitting() {
+        let lru = LruCache::new(4);
+        let mru = LruCache::builder()
+            .capacity(4)
+            .eviction_policy(Mru::new())
+            .build()
+            .unwrap();
+
+        for cache in [&lru, &mru] {
+            for _ in 0..10 {
+                for key in 0..5 {
+                    cache.get_or_insert_with(key, || key);
+                }
+            }
+        }
+
+        assert_eq!(lru.stats().hits, 0);
+        assert!(mru.stats().hits >= 30, "{:?}", mru.stats());
+    }
+}
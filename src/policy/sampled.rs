@@ -0,0 +1,182 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::NIL;
+use crate::mrc::splitmix64;
+
+use super::EvictionPolicy;
+
+// Redis samples five keys by default, close to exact LRU at little cost
+const DEFAULT_SAMPLES: usize = 5;
+
+// approximate LRU by sampling, the way Redis evicts
+//
+// every entry only keeps the time of its last access, so an access is a
+// single atomic store and hits stay under the read lock. to evict, a few
+// entries are picked at random and the one least recently used among them
+// goes. more samples come closer to exact LRU
+#[derive(Debug)]
+pub struct Sampled {
+    samples: usize,
+    clock: AtomicU64,
+    // last access per slot
+    stamps: Vec<AtomicU64>,
+    // position in `live` per slot, NIL once removed
+    positions: Vec<usize>,
+    // every tracked slot, in no particular order, to sample from
+    live: Vec<usize>,
+    seed: u64,
+}
+
+impl Sampled {
+    // `samples` of 0 is taken as 1
+    pub fn new(samples: usize) -> Self {
+        Self {
+            samples: samples.max(1),
+            clock: AtomicU64::new(0),
+            stamps: Vec::new(),
+            positions: Vec::new(),
+            live: Vec::new(),
+            seed: 0,
+        }
+    }
+
+    fn random(&mut self) -> usize {
+        self.seed = self.seed.wrapping_add(1);
+        splitmix64(self.seed) as usize
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+impl Default for Sampled {
+    fn default() -> Self {
+        Self::new(DEFAULT_SAMPLES)
+    }
+}
+
+impl EvictionPolicy for Sampled {
+    fn on_insert(&mut self, slot: usize, _: u64) {
+        if self.positions.len() <= slot {
+            self.positions.resize(slot + 1, NIL);
+            self.stamps.resize_with(slot + 1, AtomicU64::default);
+        }
+        *self.stamps[slot].get_mut() = self.tick();
+        self.positions[slot] = self.live.len();
+        self.live.push(slot);
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.on_shared_access(slot);
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        let pos = self.positions[slot];
+        self.live.swap_remove(pos);
+        if let Some(&moved) = self.live.get(pos) {
+            self.positions[moved] = pos;
+        }
+        self.positions[slot] = NIL;
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        if self.live.is_empty() {
+            return None;
+        }
+        let mut victim = NIL;
+        let mut oldest = u64::MAX;
+        for _ in 0..self.samples {
+            let pick = self.random() % self.live.len();
+            let slot = self.live[pick];
+            let stamp = *self.stamps[slot].get_mut();
+            if stamp < oldest {
+                victim = slot;
+                oldest = stamp;
+            }
+        }
+        Some(victim)
+    }
+
+    fn clear(&mut self) {
+        self.stamps.clear();
+        self.positions.clear();
+        self.live.clear();
+    }
+
+    fn tracks_access(&self) -> bool {
+        false
+    }
+
+    fn on_shared_access(&self, slot: usize) {
+        self.stamps[slot].store(self.tick(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    #[test]
+    fn sampling_everything_is_lru() {
+        // with many more samples than entries every entry is seen
+        let sampled = LruCache::builder()
+            .capacity(3)
+            .eviction_policy(Sampled::new(64))
+            .build()
+            .unwrap();
+        let plain = LruCache::new(3);
+
+        for key in [1, 2, 3, 1, 4, 2, 5, 1, 6] {
+            sampled.get_or_insert_with(key, || key);
+            plain.get_or_insert_with(key, || key);
+        }
+        let mut keys: Vec<_> = sampled.keys().collect();
+        let mut expected: Vec<_> = plain.keys().collect();
+        keys.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn few_samples_still_keep_recent_entries() {
+        let cache = LruCache::builder()
+            .capacity(100)
+            .eviction_policy(Sampled::default())
+            .build()
+            .unwrap();
+
+        for key in 0..1000u32 {
+            cache.put(key, key);
+            // the ten newest keys are the freshest of any sample
+            for recent in key.saturating_sub(9)..key {
+                cache.get(&recent);
+            }
+        }
+
+        assert!((990..1000).all(|key| cache.contains_key(&key)));
+        assert_eq!(cache.len(), 100);
+    }
+
+    #[test]
+    fn get_runs_under_the_read_lock() {
+        let cache = LruCache::builder()
+            .capacity(2)
+            .eviction_policy(Sampled::new(64))
+            .build()
+            .unwrap();
+        cache.put(1, 1);
+        cache.put(2, 2);
+
+        // a get that needed the write lock would never return
+        let reader = cache.inner.read();
+        assert_eq!(cache.get(&1), Some(1));
+        drop(reader);
+
+        // the stamp written under the read lock makes 2 the oldest
+        cache.put(3, 3);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+    }
+}
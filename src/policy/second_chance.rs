@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::EvictionPolicy;
+
+// CLOCK, the second chance approximation of LRU. named after the idea
+// rather than the algorithm, `Clock` is the crate's time source
+//
+// slots are frames on a clock face. an access only sets the frame's
+// reference bit, nothing is reordered. to find a victim the hand sweeps the
+// frames, clearing set bits as it passes, and stops at the first cached
+// frame whose bit was already clear. an entry used since the hand last came
+// by therefore gets one more round. the bits are atomic, so hits set them
+// under the read lock
+#[derive(Debug)]
+pub struct SecondChance {
+    cached: Vec<bool>,
+    referenced: Vec<AtomicBool>,
+    hand: usize,
+    len: usize,
+}
+
+impl SecondChance {
+    pub fn new() -> Self {
+        Self {
+            cached: Vec::new(),
+            referenced: Vec::new(),
+            hand: 0,
+            len: 0,
+        }
+    }
+}
+
+impl Default for SecondChance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvictionPolicy for SecondChance {
+    fn on_insert(&mut self, slot: usize, _: u64) {
+        if self.cached.len() <= slot {
+            self.cached.resize(slot + 1, false);
+            self.referenced.resize_with(slot + 1, AtomicBool::default);
+        }
+        self.cached[slot] = true;
+        *self.referenced[slot].get_mut() = false;
+        self.len += 1;
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.on_shared_access(slot);
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.cached[slot] = false;
+        self.len -= 1;
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        // at most two turns, the first clears every bit
+        loop {
+            let slot = self.hand;
+            self.hand = (self.hand + 1) % self.cached.len();
+            if self.cached[slot] && !std::mem::take(self.referenced[slot].get_mut()) {
+                return Some(slot);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.cached.clear();
+        self.referenced.clear();
+        self.hand = 0;
+        self.len = 0;
+    }
+
+    fn tracks_access(&self) -> bool {
+        false
+    }
+
+    fn on_shared_access(&self, slot: usize) {
+        self.referenced[slot].store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    #[test]
+    fn referenced_entries_get_a_second_chance() {
+        let cache = LruCache::builder()
+            .capacity(3)
+            .eviction_policy(SecondChance::new())
+            .build()
+            .unwrap();
+
+        for key in 1..=3 {
+            cache.put(key, key);
+        }
+        cache.get(&1);
+        // the hand clears 1 and takes 2, then takes 3 at the next frame
+        cache.put(4, 4);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+        cache.put(5, 5);
+
+        let mut keys: Vec<_> = cache.keys().collect();
+        keys.sort_unstable();
+        assert_eq!(keys, [1, 4, 5]);
+    }
+
+    #[test]
+    fn get_runs_under_the_read_lock() {
+        let cache = LruCache::builder()
+            .capacity(2)
+            .eviction_policy(SecondChance::new())
+            .build()
+            .unwrap();
+        cache.put(1, 1);
+        cache.put(2, 2);
+
+        // a get that needed the write lock would never return
+        let reader = cache.inner.read();
+        assert_eq!(cache.get(&1), Some(1));
+        drop(reader);
+
+        // the bit set under the read lock still spares 1
+        cache.put(3, 3);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::mrc::splitmix64;
+
+const ROWS: usize = 4;
+// counters saturate here, like the 4 bit counters of TinyLFU
+const MAX_COUNT: u8 = 15;
+// increments per counter in a row before every count is halved
+const SAMPLE_PER_COUNTER: u64 = 10;
+// counters per row at most, so an unbounded cache still gets a sketch
+const MAX_WIDTH: usize = 1 << 20;
+
+// count-min sketch estimating how often key hashes were seen recently
+//
+// each row counts a hash in one counter picked by a differently seeded mix
+// of it, the estimate is the smallest of those counters so collisions only
+// ever overcount. once enough increments were made all counters are halved,
+// which keeps the estimate about the recent past
+#[derive(Debug)]
+pub(crate) struct Sketch {
+    counters: Vec<u8>,
+    mask: usize,
+    additions: u64,
+    sample: u64,
+}
+
+impl Sketch {
+    // sized for about `capacity` distinct keys
+    pub(crate) fn new(capacity: usize) -> Self {
+        let width = Self::width(capacity);
+        Self {
+            counters: vec![0; ROWS * width],
+            mask: width - 1,
+            additions: 0,
+            sample: SAMPLE_PER_COUNTER * width as u64,
+        }
+    }
+
+    fn width(capacity: usize) -> usize {
+        capacity.clamp(16, MAX_WIDTH).next_power_of_two()
+    }
+
+    // starts over at a new size, unless the width stays the same
+    pub(crate) fn resize(&mut self, capacity: usize) {
+        if Self::width(capacity) != self.mask + 1 {
+            *self = Self::new(capacity);
+        }
+    }
+
+    fn index(&self, row: usize, hash: u64) -> usize {
+        let seed = (row as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        row * (self.mask + 1) + (splitmix64(hash ^ seed) as usize & self.mask)
+    }
+
+    pub(crate) fn increment(&mut self, hash: u64) {
+        if self.frequency(hash) == MAX_COUNT {
+            return;
+        }
+        for row in 0..ROWS {
+            let i = self.index(row, hash);
+            self.counters[i] = self.counters[i].saturating_add(1).min(MAX_COUNT);
+        }
+
+        self.additions += 1;
+        if self.additions >= self.sample {
+            self.age();
+        }
+    }
+
+    pub(crate) fn frequency(&self, hash: u64) -> u8 {
+        (0..ROWS)
+            .map(|row| self.counters[self.index(row, hash)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    fn age(&mut self) {
+        for count in &mut self.counters {
+            *count /= 2;
+        }
+        self.additions /= 2;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.counters.fill(0);
+        self.additions = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_saturate_and_halve() {
+        let mut sketch = Sketch::new(16);
+
+        for _ in 0..5 {
+            sketch.increment(1);
+        }
+        assert_eq!(sketch.frequency(1), 5);
+        assert_eq!(sketch.frequency(2), 0);
+
+        for _ in 0..20 {
+            sketch.increment(1);
+        }
+        assert_eq!(sketch.frequency(1), MAX_COUNT);
+        sketch.age();
+        assert_eq!(sketch.frequency(1), 7);
+    }
+}
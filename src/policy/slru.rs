@@ -0,0 +1,164 @@
+use super::{EvictionPolicy, Links, List, share};
+
+// default share of the capacity that is protected, in percent
+const PROTECTED_PERCENT: usize = 80;
+
+// segmented LRU
+//
+// new entries start on probation and move to the protected segment when
+// they are used again. victims come from probation first, so a run of
+// one-off keys only evicts other one-off keys. when the protected segment
+// outgrows its share its least recently used entry falls back to probation
+// and has to be used once more to stay
+#[derive(Debug)]
+pub struct Slru {
+    links: Links,
+    // whether each slot is protected
+    protected_slots: Vec<bool>,
+    probation: List,
+    protected: List,
+    protected_size: usize,
+    // the share of the capacity protected, None for a fixed size
+    protected_percent: Option<usize>,
+}
+
+impl Slru {
+    // protects 80% of the cache's capacity, following it through resizes
+    pub fn new() -> Self {
+        Self {
+            protected_percent: Some(PROTECTED_PERCENT),
+            ..Self::with_protected(0)
+        }
+    }
+
+    // protects at most `entries` entries, whatever the capacity
+    pub fn with_protected(entries: usize) -> Self {
+        Self {
+            links: Links::default(),
+            protected_slots: Vec::new(),
+            probation: List::new(),
+            protected: List::new(),
+            protected_size: entries,
+            protected_percent: None,
+        }
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        if self.protected_slots[slot] {
+            self.protected.unlink(&mut self.links, slot);
+        } else {
+            self.probation.unlink(&mut self.links, slot);
+        }
+    }
+}
+
+impl Default for Slru {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvictionPolicy for Slru {
+    fn on_insert(&mut self, slot: usize, _: u64) {
+        if self.protected_slots.len() <= slot {
+            self.protected_slots.resize(slot + 1, false);
+        }
+        self.links.track(slot);
+        self.protected_slots[slot] = false;
+        self.probation.push_back(&mut self.links, slot);
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.unlink(slot);
+        self.protected_slots[slot] = true;
+        self.protected.push_back(&mut self.links, slot);
+
+        if self.protected.len() > self.protected_size {
+            let demoted = self.protected.front().expect("protected has entries");
+            self.protected.unlink(&mut self.links, demoted);
+            self.protected_slots[demoted] = false;
+            self.probation.push_back(&mut self.links, demoted);
+        }
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.unlink(slot);
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        self.probation.front().or(self.protected.front())
+    }
+
+    // an overfull protected segment shrinks on the next access
+    fn on_resize(&mut self, capacity: usize) {
+        if let Some(percent) = self.protected_percent {
+            self.protected_size = share(capacity, percent);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.links.clear();
+        self.protected_slots.clear();
+        self.probation.clear();
+        self.protected.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    fn slru_cache(policy: Slru) -> LruCache<u32, u32> {
+        LruCache::builder()
+            .capacity(4)
+            .eviction_policy(policy)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn second_hit_protects_from_one_off_keys() {
+        let cache = slru_cache(Slru::new());
+
+        for key in [1, 2, 1, 2] {
+            cache.get_or_insert_with(key, || key);
+        }
+        for key in 10..20 {
+            cache.put(key, key);
+        }
+
+        assert!(cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+    }
+
+    #[test]
+    fn only_the_default_share_follows_the_capacity() {
+        let mut share = Slru::new();
+        let mut fixed = Slru::with_protected(1);
+        for slru in [&mut share, &mut fixed] {
+            slru.on_resize(10);
+        }
+        assert_eq!((share.protected_size, fixed.protected_size), (8, 1));
+
+        share.on_resize(usize::MAX);
+        assert_eq!(share.protected_size, usize::MAX / 100 * 80);
+    }
+
+    #[test]
+    fn protected_overflow_falls_back_to_probation() {
+        let cache = slru_cache(Slru::with_protected(1));
+
+        // 2 pushes 1 out of the protected segment, then 1 is the oldest
+        // entry on probation
+        for key in [1, 2, 1, 2, 3] {
+            cache.get_or_insert_with(key, || key);
+        }
+        for key in [4, 5] {
+            cache.put(key, key);
+        }
+
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+    }
+}
@@ -0,0 +1,227 @@
+use super::sketch::Sketch;
+use super::{EvictionPolicy, Links, List, share};
+
+// share of the capacity that is the admission window, in percent
+const WINDOW_PERCENT: usize = 1;
+// share of the main segment that is protected, in percent
+const PROTECTED_PERCENT: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    Window,
+    Probation,
+    Protected,
+}
+
+// window TinyLFU, as in Caffeine (Einziger et al., "TinyLFU: A Highly
+// Efficient Cache Admission Policy")
+//
+// new entries land in a small LRU window. once the window is full its oldest
+// entry is a candidate for the main segment and has to beat the main
+// segment's own victim on estimated frequency, the loser is evicted. the
+// frequencies come from a count-min sketch over key hashes, so keys keep
+// their history while they are not cached, and a burst of one-off keys only
+// ever evicts itself. the main segment is a segmented LRU: entries start on
+// probation and move to the protected part once they are used again
+#[derive(Debug)]
+pub struct TinyLfu {
+    sketch: Sketch,
+    links: Links,
+    // (segment, key hash) per slot
+    slots: Vec<(Segment, u64)>,
+    window: List,
+    probation: List,
+    protected: List,
+    window_size: usize,
+    protected_size: usize,
+}
+
+impl TinyLfu {
+    // the window, the protected segment and the sketch are sized by
+    // `on_resize`, which the cache calls with its capacity
+    pub fn new() -> Self {
+        Self {
+            sketch: Sketch::new(0),
+            links: Links::default(),
+            slots: Vec::new(),
+            window: List::new(),
+            probation: List::new(),
+            protected: List::new(),
+            window_size: 1,
+            protected_size: 0,
+        }
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let list = match self.slots[slot].0 {
+            Segment::Window => &mut self.window,
+            Segment::Probation => &mut self.probation,
+            Segment::Protected => &mut self.protected,
+        };
+        list.unlink(&mut self.links, slot);
+    }
+
+    fn push(&mut self, slot: usize, segment: Segment) {
+        self.slots[slot].0 = segment;
+        let list = match segment {
+            Segment::Window => &mut self.window,
+            Segment::Probation => &mut self.probation,
+            Segment::Protected => &mut self.protected,
+        };
+        list.push_back(&mut self.links, slot);
+    }
+
+    fn move_to(&mut self, slot: usize, segment: Segment) {
+        self.unlink(slot);
+        self.push(slot, segment);
+    }
+
+    fn frequency(&self, slot: usize) -> u8 {
+        self.sketch.frequency(self.slots[slot].1)
+    }
+
+    // the entry the main segment would give up first
+    fn main_victim(&self) -> Option<usize> {
+        self.probation.front().or(self.protected.front())
+    }
+}
+
+impl Default for TinyLfu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvictionPolicy for TinyLfu {
+    fn on_insert(&mut self, slot: usize, hash: u64) {
+        if self.slots.len() <= slot {
+            self.slots.resize(slot + 1, (Segment::Window, 0));
+        }
+        self.links.track(slot);
+        self.slots[slot].1 = hash;
+        self.sketch.increment(hash);
+        self.push(slot, Segment::Window);
+
+        // while the cache fills up nothing is evicted, entries pushed out of
+        // the window go straight to probation
+        while self.window.len() > self.window_size {
+            let oldest = self.window.front().expect("an overfull window has entries");
+            self.move_to(oldest, Segment::Probation);
+        }
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        self.sketch.increment(self.slots[slot].1);
+        match self.slots[slot].0 {
+            Segment::Window => self.move_to(slot, Segment::Window),
+            Segment::Probation => {
+                self.move_to(slot, Segment::Protected);
+                if self.protected.len() > self.protected_size {
+                    let demoted = self.protected.front().expect("protected has entries");
+                    self.move_to(demoted, Segment::Probation);
+                }
+            }
+            Segment::Protected => self.move_to(slot, Segment::Protected),
+        }
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        self.unlink(slot);
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        let Some(candidate) = self.window.front() else {
+            return self.main_victim();
+        };
+        if self.window.len() < self.window_size {
+            return self.main_victim().or(Some(candidate));
+        }
+        let Some(victim) = self.main_victim() else {
+            return Some(candidate);
+        };
+
+        // the candidate is admitted only when it is strictly more popular,
+        // ties keep the entry that already earned its place
+        if self.frequency(candidate) > self.frequency(victim) {
+            self.move_to(candidate, Segment::Probation);
+            Some(victim)
+        } else {
+            Some(candidate)
+        }
+    }
+
+    // segments over their new share shrink on the next insert or access
+    fn on_resize(&mut self, capacity: usize) {
+        self.window_size = share(capacity, WINDOW_PERCENT).max(1);
+        let main = capacity.saturating_sub(self.window_size);
+        self.protected_size = share(main, PROTECTED_PERCENT);
+        self.sketch.resize(capacity);
+    }
+
+    fn clear(&mut self) {
+        self.sketch.clear();
+        self.links.clear();
+        self.slots.clear();
+        self.window.clear();
+        self.probation.clear();
+        self.protected.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    #[test]
+    fn skewed_workload_beats_lru() {
+        let lru = LruCache::new(20);
+        let tiny = LruCache::builder()
+            .capacity(20)
+            .eviction_policy(TinyLfu::new())
+            .build()
+            .unwrap();
+
+        for cache in [&lru, &tiny] {
+            // ten hot keys every round, separated by more one-off keys than
+            // the cache holds
+            for round in 0..50u32 {
+                for key in 0..10 {
+                    cache.get_or_insert_with(key, || key);
+                }
+                for key in 0..30 {
+                    let cold = 1000 + round * 30 + key;
+                    cache.get_or_insert_with(cold, || cold);
+                }
+            }
+        }
+
+        assert_eq!(lru.stats().hits, 0);
+        // all but the first round and a few misses while counts build up
+        assert!(tiny.stats().hits >= 450, "{:?}", tiny.stats());
+    }
+
+    #[test]
+    fn follows_the_cache_capacity() {
+        let cache = LruCache::builder()
+            .capacity(1000)
+            .eviction_policy(TinyLfu::new())
+            .build()
+            .unwrap();
+        cache.set_capacity(10);
+
+        // still sized for 1000 entries the window would take every key,
+        // leaving a scan to evict the hot ones as plain LRU does
+        for key in 0..10u32 {
+            cache.put(key, key);
+        }
+        for key in 0..5 {
+            cache.get(&key);
+        }
+        for key in 100..120 {
+            cache.put(key, key);
+        }
+        assert_eq!(cache.len(), 10);
+        assert!((0..5).all(|key| cache.contains_key(&key)));
+    }
+}
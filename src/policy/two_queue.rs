@@ -0,0 +1,160 @@
+use super::ghost::Ghosts;
+use super::{EvictionPolicy, Links, List, share};
+
+// shares of the capacity given to the first-use queue and to its ghosts, in
+// percent, the values the paper recommends
+const IN_PERCENT: usize = 25;
+const OUT_PERCENT: usize = 50;
+
+// 2Q (Johnson and Shasha, VLDB '94)
+//
+// a key seen for the first time goes to a FIFO queue, A1in, and reads do not
+// move it there. keys evicted from A1in are remembered in a ghost queue,
+// A1out, and a key that comes back while still remembered has been used
+// twice, so it goes to the main LRU, Am. keys used once never reach Am,
+// which makes the main list immune to scans
+#[derive(Debug)]
+pub struct TwoQueue {
+    links: Links,
+    // (in main, key hash) per slot
+    slots: Vec<(bool, u64)>,
+    fresh: List,
+    main: List,
+    ghosts: Ghosts,
+    fresh_size: usize,
+    ghost_size: usize,
+    // the slot last named by `choose_victim`
+    evicting: Option<usize>,
+}
+
+impl TwoQueue {
+    // A1in and A1out are sized by `on_resize` from the cache's capacity
+    pub fn new() -> Self {
+        Self {
+            links: Links::default(),
+            slots: Vec::new(),
+            fresh: List::new(),
+            main: List::new(),
+            ghosts: Ghosts::default(),
+            fresh_size: 1,
+            ghost_size: 1,
+            evicting: None,
+        }
+    }
+}
+
+impl Default for TwoQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvictionPolicy for TwoQueue {
+    fn on_insert(&mut self, slot: usize, hash: u64) {
+        if self.slots.len() <= slot {
+            self.slots.resize(slot + 1, (false, 0));
+        }
+        self.links.track(slot);
+        let main = self.ghosts.remove(hash);
+        self.slots[slot] = (main, hash);
+        if main {
+            self.main.push_back(&mut self.links, slot);
+        } else {
+            self.fresh.push_back(&mut self.links, slot);
+        }
+    }
+
+    fn on_access(&mut self, slot: usize) {
+        if self.slots[slot].0 {
+            self.main.unlink(&mut self.links, slot);
+            self.main.push_back(&mut self.links, slot);
+        }
+    }
+
+    fn on_remove(&mut self, slot: usize) {
+        let evicted = self.evicting.take() == Some(slot);
+        let (main, hash) = self.slots[slot];
+        if main {
+            self.main.unlink(&mut self.links, slot);
+            return;
+        }
+        self.fresh.unlink(&mut self.links, slot);
+        // only keys evicted from A1in are remembered
+        if evicted {
+            self.ghosts.push(hash);
+            self.ghosts.truncate(self.ghost_size);
+        }
+    }
+
+    fn choose_victim(&mut self) -> Option<usize> {
+        let victim = if self.fresh.len() > self.fresh_size || self.main.len() == 0 {
+            self.fresh.front()
+        } else {
+            self.main.front()
+        };
+        self.evicting = victim;
+        victim
+    }
+
+    fn on_resize(&mut self, capacity: usize) {
+        self.fresh_size = share(capacity, IN_PERCENT).max(1);
+        self.ghost_size = share(capacity, OUT_PERCENT).max(1);
+        self.ghosts.truncate(self.ghost_size);
+    }
+
+    fn clear(&mut self) {
+        self.links.clear();
+        self.slots.clear();
+        self.fresh.clear();
+        self.main.clear();
+        self.ghosts.clear();
+        self.evicting = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LruCache;
+
+    #[test]
+    fn returning_keys_reach_the_main_queue() {
+        let cache = LruCache::builder()
+            .capacity(4)
+            .eviction_policy(TwoQueue::new())
+            .build()
+            .unwrap();
+
+        // 1 and 2 are pushed out of A1in and come back from A1out
+        for key in [1, 2, 3, 4, 5, 1, 2] {
+            cache.put(key, key);
+        }
+        // reads of first-use keys do not help them, scans only cycle A1in
+        for key in 10..20 {
+            cache.put(key, key);
+            cache.get(&key);
+        }
+
+        assert!(cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn shrinking_forgets_the_oldest_ghosts() {
+        let mut two_q = TwoQueue::new();
+        two_q.on_resize(16);
+        for slot in 0..16 {
+            two_q.on_insert(slot, slot as u64);
+        }
+        for _ in 0..8 {
+            let victim = two_q.choose_victim().unwrap();
+            two_q.on_remove(victim);
+        }
+        assert_eq!(two_q.ghosts.len(), 8);
+
+        two_q.on_resize(8);
+        assert_eq!((two_q.fresh_size, two_q.ghost_size), (2, 4));
+        assert_eq!(two_q.ghosts.len(), 4);
+    }
+}
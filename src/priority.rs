@@ -0,0 +1,86 @@
+use crate::policy::{Links, List};
+
+// eviction class of an entry. every low entry goes before any normal one,
+// and every normal one before any high one. within a class the usual order
+// applies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+const CLASSES: [Priority; 3] = [Priority::Low, Priority::Normal, Priority::High];
+
+// one LRU list per class, threaded through the slots. only set up once an
+// entry is given a priority other than normal, until then every entry is
+// normal and the recency list alone decides
+pub(crate) struct Classes {
+    links: Links,
+    class: Vec<Priority>,
+    lists: [List; 3],
+}
+
+impl Classes {
+    pub(crate) fn new() -> Self {
+        Self {
+            links: Links::default(),
+            class: Vec::new(),
+            lists: [List::new(), List::new(), List::new()],
+        }
+    }
+
+    // track a slot as the most recently used of its class
+    pub(crate) fn insert(&mut self, slot: usize, priority: Priority) {
+        if self.class.len() <= slot {
+            self.class.resize(slot + 1, Priority::Normal);
+        }
+        self.links.track(slot);
+        self.class[slot] = priority;
+        self.lists[priority as usize].push_back(&mut self.links, slot);
+    }
+
+    // the class of a slot kept out of the lists, it joins once inserted
+    pub(crate) fn assign(&mut self, slot: usize, priority: Priority) {
+        if self.class.len() <= slot {
+            self.class.resize(slot + 1, Priority::Normal);
+        }
+        self.class[slot] = priority;
+    }
+
+    pub(crate) fn remove(&mut self, slot: usize) {
+        let priority = self.class[slot];
+        self.lists[priority as usize].unlink(&mut self.links, slot);
+    }
+
+    // move a slot to the most recently used end of `priority`
+    pub(crate) fn set(&mut self, slot: usize, priority: Priority) {
+        self.remove(slot);
+        self.insert(slot, priority);
+    }
+
+    pub(crate) fn touch(&mut self, slot: usize) {
+        self.set(slot, self.class[slot]);
+    }
+
+    pub(crate) fn class(&self, slot: usize) -> Priority {
+        self.class[slot]
+    }
+
+    // whether entries of more than one class are tracked
+    pub(crate) fn mixed(&self) -> bool {
+        self.lists.iter().filter(|list| list.len() > 0).count() > 1
+    }
+
+    // the least recently used slot of the lowest class that has any
+    pub(crate) fn victim(&self) -> Option<(Priority, usize)> {
+        CLASSES
+            .into_iter()
+            .find_map(|priority| Some((priority, self.lists[priority as usize].front()?)))
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
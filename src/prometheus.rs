@@ -0,0 +1,191 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, Mutex, Weak};
+
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::MetricFamily;
+use prometheus::{IntCounter, IntGauge, Opts, Registry};
+
+use crate::lock::RwLock;
+use crate::{CacheState, CacheStats, HeapSize, LruCache, ShardedLruCache};
+
+// reads the stats of one or more cache states at scrape time. the states are
+// held weakly, so a registry never keeps a dropped cache alive, its metrics
+// just stop being reported
+struct CacheCollector<K, V, S> {
+    states: Vec<Weak<RwLock<CacheState<K, V, S>>>>,
+    hits: IntCounter,
+    misses: IntCounter,
+    evictions: IntCounter,
+    entries: IntGauge,
+    memory: IntGauge,
+    // two scrapes at once would interleave their resets and increments
+    scrape: Mutex<()>,
+}
+
+impl<K, V, S> CacheCollector<K, V, S> {
+    fn new(states: Vec<Weak<RwLock<CacheState<K, V, S>>>>, name: &str) -> prometheus::Result<Self> {
+        let opts = |metric: &str, help: &str| {
+            Opts::new(format!("lru_cache_{metric}"), help).const_label("cache", name)
+        };
+
+        Ok(Self {
+            states,
+            hits: IntCounter::with_opts(opts("hits_total", "lookups that found a live entry"))?,
+            misses: IntCounter::with_opts(opts("misses_total", "lookups that found nothing"))?,
+            evictions: IntCounter::with_opts(opts(
+                "evictions_total",
+                "entries evicted to stay within capacity or weight",
+            ))?,
+            entries: IntGauge::with_opts(opts("entries", "live entries in the cache"))?,
+            memory: IntGauge::with_opts(opts("memory_bytes", "estimated bytes held by the cache"))?,
+            scrape: Mutex::new(()),
+        })
+    }
+}
+
+impl<K, V, S> Collector for CacheCollector<K, V, S>
+where
+    K: Eq + Hash + HeapSize + Send + Sync,
+    V: HeapSize + Send + Sync,
+    S: BuildHasher + Send + Sync,
+{
+    fn desc(&self) -> Vec<&Desc> {
+        [
+            self.hits.desc(),
+            self.misses.desc(),
+            self.evictions.desc(),
+            self.entries.desc(),
+            self.memory.desc(),
+        ]
+        .concat()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let _scrape = self.scrape.lock().unwrap();
+
+        let states: Vec<_> = self.states.iter().filter_map(Weak::upgrade).collect();
+        if states.is_empty() {
+            return Vec::new();
+        }
+
+        let mut stats = CacheStats::default();
+        let mut entries = 0;
+        let mut memory = 0;
+        for state in &states {
+            let state = state.read();
+            stats = stats + state.stats.snapshot();
+            entries += state.live_len();
+            memory += size_of::<LruCache<K, V, S>>() + state.memory_usage();
+        }
+
+        // the totals can drop after `reset_stats`, so the counters are set
+        // rather than bumped by a difference
+        for (counter, value) in [
+            (&self.hits, stats.hits),
+            (&self.misses, stats.misses),
+            (&self.evictions, stats.evictions),
+        ] {
+            counter.reset();
+            counter.inc_by(value);
+        }
+        self.entries.set(i64::try_from(entries).unwrap_or(i64::MAX));
+        self.memory.set(i64::try_from(memory).unwrap_or(i64::MAX));
+
+        [
+            self.hits.collect(),
+            self.misses.collect(),
+            self.evictions.collect(),
+            self.entries.collect(),
+            self.memory.collect(),
+        ]
+        .concat()
+    }
+}
+
+impl<K, V, S> LruCache<K, V, S>
+where
+    K: Eq + Hash + HeapSize + Send + Sync + 'static,
+    V: HeapSize + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    // publishes hits, misses and evictions as counters and the entry count
+    // and `memory_usage` as gauges, all labelled `cache="<name>"`. the values
+    // are read on every scrape, registering two caches under the same name
+    // fails
+    pub fn register_metrics(&self, registry: &Registry, name: &str) -> prometheus::Result<()> {
+        let collector = CacheCollector::new(vec![Arc::downgrade(&self.inner)], name)?;
+        registry.register(Box::new(collector))
+    }
+}
+
+impl<K, V, S> ShardedLruCache<K, V, S>
+where
+    K: Eq + Hash + HeapSize + Send + Sync + 'static,
+    V: HeapSize + Send + Sync + 'static,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    // like `LruCache::register_metrics`, reporting the totals over all shards
+    pub fn register_metrics(&self, registry: &Registry, name: &str) -> prometheus::Result<()> {
+        let states = self
+            .shards
+            .iter()
+            .map(|shard| Arc::downgrade(&shard.inner))
+            .collect();
+        registry.register(Box::new(CacheCollector::new(states, name)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::{Encoder, Registry, TextEncoder};
+
+    use crate::{LruCache, ShardedLruCache};
+
+    fn scrape(registry: &Registry) -> String {
+        let mut out = Vec::new();
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn scrape_reads_current_stats() {
+        let registry = Registry::new();
+        let cache = LruCache::new(2);
+        cache.register_metrics(&registry, "users").unwrap();
+
+        cache.put(1u32, 1u32);
+        cache.put(2, 2);
+        cache.put(3, 3);
+        cache.get(&3);
+        cache.get(&1);
+
+        let text = scrape(&registry);
+        assert!(text.contains(r#"lru_cache_hits_total{cache="users"} 1"#));
+        assert!(text.contains(r#"lru_cache_misses_total{cache="users"} 1"#));
+        assert!(text.contains(r#"lru_cache_evictions_total{cache="users"} 1"#));
+        assert!(text.contains(r#"lru_cache_entries{cache="users"} 2"#));
+
+        drop(cache);
+        assert_eq!(scrape(&registry), "");
+    }
+
+    #[test]
+    fn names_keep_caches_apart() {
+        let registry = Registry::new();
+        let sharded = ShardedLruCache::new(64, 4);
+        let other = LruCache::<u32, u32>::new(4);
+
+        sharded.register_metrics(&registry, "a").unwrap();
+        other.register_metrics(&registry, "b").unwrap();
+        assert!(other.register_metrics(&registry, "b").is_err());
+
+        for i in 0..10u32 {
+            sharded.put(i, i);
+        }
+        let text = scrape(&registry);
+        assert!(text.contains(r#"lru_cache_entries{cache="a"} 10"#));
+        assert!(text.contains(r#"lru_cache_entries{cache="b"} 0"#));
+    }
+}
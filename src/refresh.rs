@@ -0,0 +1,58 @@
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+// reloads entries in the background once they are older than `after`
+//
+// a single worker thread runs the loader, so the results do not need to know
+// about the cache at all. they are queued up and applied by the cache the
+// next time it takes its write lock, the stale value is served until then
+pub(crate) struct Refresh<K, V> {
+    pub(crate) after: Duration,
+    request: Box<dyn Fn(&K) + Send + Sync>,
+    done: Mutex<Receiver<(K, Option<V>)>>,
+}
+
+impl<K, V> Refresh<K, V> {
+    // the worker exits once the cache, and with it the request side, is gone
+    pub(crate) fn start<F>(after: Duration, loader: F) -> Self
+    where
+        K: Clone + Send + 'static,
+        V: Send + 'static,
+        F: Fn(&K) -> Option<V> + Send + 'static,
+    {
+        let (requests, pending) = mpsc::channel::<K>();
+        let (finished, done) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("lru-cache-refresh".into())
+            .spawn(move || {
+                for key in pending {
+                    let value = loader(&key);
+                    if finished.send((key, value)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("failed to spawn the refresh thread");
+
+        Self {
+            after,
+            request: Box::new(move |key| {
+                // only fails once the worker is gone, then nothing refreshes
+                let _ = requests.send(key.clone());
+            }),
+            done: Mutex::new(done),
+        }
+    }
+
+    pub(crate) fn request(&self, key: &K) {
+        (self.request)(key);
+    }
+
+    // reloads finished since the last call
+    pub(crate) fn finished(&self) -> Vec<(K, Option<V>)> {
+        self.done.lock().unwrap().try_iter().collect()
+    }
+}
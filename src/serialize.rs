@@ -0,0 +1,258 @@
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::LruCache;
+
+// serde form of a cache, its capacity and entries from least to most
+// recently used. `LruCache` serializes to the same shape, so either one can
+// be deserialized from the other's output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheSnapshot<K, V> {
+    pub capacity: usize,
+    pub entries: Vec<(K, V)>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher> LruCache<K, V, S> {
+    // copies the live entries out, least recently used first
+    pub fn snapshot(&self) -> CacheSnapshot<K, V> {
+        let state = self.read();
+        let mut entries: Vec<_> = state
+            .iter_live(state.expiry_cutoff())
+            .map(|node| (node.key.clone(), node.value.clone()))
+            .collect();
+        entries.reverse();
+        CacheSnapshot {
+            capacity: state.capacity,
+            entries,
+        }
+    }
+}
+
+// the rest of the configuration, ttls, weigher or policy, is not part of a
+// snapshot. build a cache with it and `put` the entries in order instead.
+// the entries go in the way `warm_up_from_store` puts them, quietly
+impl<K: Eq + Hash, V, S: BuildHasher + Default> From<CacheSnapshot<K, V>> for LruCache<K, V, S> {
+    fn from(snapshot: CacheSnapshot<K, V>) -> Self {
+        let cache = LruCache::with_hasher(snapshot.capacity, S::default());
+        cache.restore(
+            snapshot
+                .entries
+                .into_iter()
+                .map(|(key, value)| (key, value, None)),
+        );
+        cache
+    }
+}
+
+// serialized under the read lock, entries are not copied
+impl<K, V, S> Serialize for LruCache<K, V, S>
+where
+    K: Eq + Hash + Serialize,
+    V: Serialize,
+    S: BuildHasher,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        let state = self.read();
+        let mut entries: Vec<_> = state
+            .iter_live(state.expiry_cutoff())
+            .map(|node| (&node.key, &node.value))
+            .collect();
+        entries.reverse();
+
+        let mut snapshot = serializer.serialize_struct("CacheSnapshot", 2)?;
+        snapshot.serialize_field("capacity", &state.capacity)?;
+        snapshot.serialize_field("entries", &entries)?;
+        snapshot.end()
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for LruCache<K, V, S>
+where
+    K: Eq + Hash + Deserialize<'de>,
+    V: Deserialize<'de>,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        CacheSnapshot::deserialize(deserializer).map(LruCache::from)
+    }
+}
+
+// what `dump_json` writes, meant for people reading it
+#[derive(Serialize, Deserialize)]
+struct Dump<K, V> {
+    capacity: usize,
+    len: usize,
+    weight: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    entries: Vec<DumpEntry<K, V>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DumpEntry<K, V> {
+    // 0 is the most recently used, the highest rank goes next
+    rank: usize,
+    key: K,
+    value: V,
+    // since the last put or reload
+    age_ms: u64,
+    expires_in_ms: Option<u64>,
+    weight: u64,
+    pinned: bool,
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> LruCache<K, V, S> {
+    // pretty printed JSON of the live entries, most recently used first, and
+    // some stats, for looking into a cache by hand. the document is built
+    // under the read lock and written out after it is released
+    pub fn dump_json<W: Write>(&self, mut writer: W) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let json = {
+            let state = self.read();
+            let now = state.clock.now();
+            let stats = state.stats.snapshot();
+            let entries = state
+                .iter_live(state.expiry_cutoff())
+                .enumerate()
+                .map(|(rank, node)| DumpEntry {
+                    rank,
+                    key: &node.key,
+                    value: &node.value,
+                    age_ms: millis(now.saturating_duration_since(node.written_at)),
+                    expires_in_ms: node
+                        .expires_at
+                        .map(|at| millis(at.saturating_duration_since(now))),
+                    weight: node.weight,
+                    pinned: node.pinned,
+                })
+                .collect();
+            serde_json::to_vec_pretty(&Dump {
+                capacity: state.capacity,
+                len: state.live_len(),
+                weight: state.weight,
+                hits: stats.hits,
+                misses: stats.misses,
+                evictions: stats.evictions,
+                entries,
+            })?
+        };
+        writer.write_all(&json)?;
+        writer.flush()
+    }
+
+    // puts the entries of a `dump_json` document back, in their recency
+    // order and with the time they had left to live. the rest of the document
+    // is informational and ignored. returns how many entries were put. like
+    // `warm_up_from_store` it neither writes to the backing store nor tells
+    // the listener or subscribers
+    pub fn load_json<R: Read>(&self, reader: R) -> io::Result<usize>
+    where
+        K: for<'de> Deserialize<'de>,
+        V: for<'de> Deserialize<'de>,
+    {
+        let mut dump: Dump<K, V> = serde_json::from_reader(reader)?;
+        dump.entries
+            .sort_by_key(|entry| std::cmp::Reverse(entry.rank));
+        let count = dump.entries.len();
+        self.restore(dump.entries.into_iter().map(|entry| {
+            let ttl = entry.expires_in_ms.map(Duration::from_millis);
+            (entry.key, entry.value, ttl)
+        }));
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_capacity_and_order() {
+        let cache = LruCache::new(3);
+        cache.put("a".to_string(), 1);
+        cache.put("b".to_string(), 2);
+        cache.put("c".to_string(), 3);
+        cache.get("a");
+
+        let json = serde_json::to_string(&cache).unwrap();
+        assert_eq!(
+            json,
+            r#"{"capacity":3,"entries":[["b",2],["c",3],["a",1]]}"#
+        );
+
+        let restored: LruCache<String, i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.capacity(), 3);
+        assert_eq!(restored.snapshot(), cache.snapshot());
+        // b is still the first to go
+        restored.put("d".to_string(), 4);
+        assert!(!restored.contains_key("b"));
+    }
+
+    #[test]
+    fn dump_shows_rank_and_age_and_loads_back() {
+        let clock = crate::MockClock::new();
+        let cache = LruCache::builder()
+            .capacity(4)
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        cache.put(1, "old");
+        clock.advance(Duration::from_secs(5));
+        cache.put_with_ttl(2, "new", Duration::from_secs(60));
+        cache.get(&1);
+
+        let mut json = Vec::new();
+        cache.dump_json(&mut json).unwrap();
+        let dump: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(dump["len"], 2);
+        assert_eq!(dump["hits"], 1);
+        assert_eq!(dump["entries"][0]["key"], 1);
+        assert_eq!(dump["entries"][0]["age_ms"], 5000);
+        assert_eq!(dump["entries"][1]["rank"], 1);
+        assert_eq!(dump["entries"][1]["expires_in_ms"], 60000);
+
+        let restored: LruCache<u32, String> = LruCache::new(4);
+        assert_eq!(restored.load_json(&json[..]).unwrap(), 2);
+        assert_eq!(restored.keys().collect::<Vec<_>>(), [1, 2]);
+        assert!(restored.ttl(&2).is_some());
+    }
+
+    #[test]
+    fn loading_a_dump_skips_the_store() {
+        struct NoWrites;
+
+        impl crate::CacheStore<u32, u32> for NoWrites {
+            fn write(&self, key: &u32, _: &u32) {
+                panic!("{key} written to the store");
+            }
+
+            fn delete(&self, _: &u32) {}
+        }
+
+        let dumped = LruCache::new(2);
+        dumped.put(1u32, 1u32);
+        dumped.put(2, 2);
+        let mut json = Vec::new();
+        dumped.dump_json(&mut json).unwrap();
+
+        let cache = LruCache::builder()
+            .capacity(2)
+            .write_through(NoWrites)
+            .build()
+            .unwrap();
+        assert_eq!(cache.load_json(&json[..]).unwrap(), 2);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [2, 1]);
+    }
+}
@@ -0,0 +1,517 @@
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "async")]
+use crate::EvictionStream;
+use crate::events::Subscriber;
+use crate::{CacheEvent, CacheStats, HeapSize, LockContention, LruCache, MaybeStale, Priority};
+
+// cache split into independently locked shards
+//
+// every key is hashed to exactly one shard, so threads working on different
+// shards never contend on the same lock. LRU order is kept per shard, which
+// makes eviction approximate across the whole cache.
+pub struct ShardedLruCache<K, V, S = RandomState> {
+    pub(crate) shards: Box<[LruCache<K, V, S>]>,
+    hasher: S,
+}
+
+// one shard's share of the load, from `ShardedLruCache::shard_stats`. a
+// shard with far more entries, lookups or lock wait than the others points
+// at a skewed key distribution
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardStats {
+    pub len: usize,
+    pub stats: CacheStats,
+    pub lock: LockContention,
+}
+
+impl<K: Eq + Hash, V: Clone> ShardedLruCache<K, V> {
+    // capacity is split as evenly as possible, a shard never gets less than
+    // one slot so the shard count is capped by the capacity. a zero capacity
+    // cache gets a single empty shard
+    pub fn new(capacity: usize, shards: usize) -> Self {
+        Self::with_hasher(capacity, shards, RandomState::new())
+    }
+
+    // a few shards per available core keeps the odds of two threads
+    // colliding on the same lock low
+    pub fn with_default_shards(capacity: usize) -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::new(capacity, cores * 4)
+    }
+}
+
+impl<K: Eq + Hash, V: Clone, S: BuildHasher + Clone> ShardedLruCache<K, V, S> {
+    // every shard gets a copy of the hasher for its own map
+    pub fn with_hasher(capacity: usize, shards: usize, hasher: S) -> Self {
+        assert!(shards > 0);
+
+        let shards = shards.min(capacity).max(1);
+
+        Self {
+            shards: split_capacity(capacity, shards)
+                .map(|capacity| LruCache::with_hasher(capacity, hasher.clone()))
+                .collect(),
+            hasher,
+        }
+    }
+
+    // shards built elsewhere, each with a copy of `hasher`
+    pub(crate) fn from_shards(shards: Box<[LruCache<K, V, S>]>, hasher: S) -> Self {
+        Self { shards, hasher }
+    }
+
+    // Borrow guarantees a borrowed key hashes like the owned one, so both
+    // land on the same shard
+    fn shard_index<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        // the shard maps index buckets by the low bits of the same hash, so
+        // pick the shard from the high bits to keep the two independent
+        let hash = self.hasher.hash_one(key) >> 32;
+        (hash % self.shards.len() as u64) as usize
+    }
+
+    fn shard<Q>(&self, key: &Q) -> &LruCache<K, V, S>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        &self.shards[self.shard_index(key)]
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).get(key)
+    }
+
+    // keys are grouped per shard so each shard lock is taken at most once
+    pub fn get_many<Q>(&self, keys: &[Q]) -> Vec<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Clone,
+    {
+        let mut groups: Vec<Vec<usize>> = vec![Vec::new(); self.shards.len()];
+        for (pos, key) in keys.iter().enumerate() {
+            groups[self.shard_index(key)].push(pos);
+        }
+
+        let mut out = vec![None; keys.len()];
+        for (shard, positions) in self.shards.iter().zip(groups) {
+            if positions.is_empty() {
+                continue;
+            }
+
+            let shard_keys: Vec<Q> = positions.iter().map(|&pos| keys[pos].clone()).collect();
+            for (pos, value) in positions.into_iter().zip(shard.get_many(&shard_keys)) {
+                out[pos] = value;
+            }
+        }
+        out
+    }
+
+    pub fn get_stale_ok<Q>(&self, key: &Q) -> Option<MaybeStale<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).get_stale_ok(key)
+    }
+
+    pub fn peek<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).peek(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).contains_key(key)
+    }
+
+    pub fn expires_at<Q>(&self, key: &Q) -> Option<Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).expires_at(key)
+    }
+
+    pub fn ttl<Q>(&self, key: &Q) -> Option<Duration>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).ttl(key)
+    }
+
+    pub fn put(&self, key: K, value: V) -> Option<V> {
+        self.shard(&key).put(key, value)
+    }
+
+    pub fn put_with_ttl(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.shard(&key).put_with_ttl(key, value, ttl)
+    }
+
+    // classes only order eviction within the key's shard
+    pub fn put_with_priority(&self, key: K, value: V, priority: Priority) -> Option<V> {
+        self.shard(&key).put_with_priority(key, value, priority)
+    }
+
+    pub fn put_many<I>(&self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut groups: Vec<Vec<(K, V)>> = (0..self.shards.len()).map(|_| Vec::new()).collect();
+        for (key, value) in entries {
+            groups[self.shard_index(&key)].push((key, value));
+        }
+
+        for (shard, group) in self.shards.iter().zip(groups) {
+            if !group.is_empty() {
+                shard.put_many(group);
+            }
+        }
+    }
+
+    pub fn put_if_absent(&self, key: K, value: V) -> Option<V> {
+        self.shard(&key).put_if_absent(key, value)
+    }
+
+    pub fn replace_if_present(&self, key: &K, value: V) -> Option<V> {
+        self.shard(key).replace_if_present(key, value)
+    }
+
+    pub fn get_or_insert_with<F>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce() -> V,
+    {
+        self.shard(&key).get_or_insert_with(key, f)
+    }
+
+    pub fn get_or_load<F>(&self, key: K, load: F) -> V
+    where
+        K: Clone,
+        F: FnOnce() -> V,
+    {
+        self.shard(&key).get_or_load(key, load)
+    }
+
+    pub fn get_or_try_insert_with<F, E>(&self, key: K, f: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        self.shard(&key).get_or_try_insert_with(key, f)
+    }
+
+    pub fn with_value_mut<Q, R, F>(&self, key: &Q, f: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&mut V) -> R,
+    {
+        self.shard(key).with_value_mut(key, f)
+    }
+
+    pub fn compare_and_swap<Q>(&self, key: &Q, expected: &V, new: V) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: PartialEq,
+    {
+        self.shard(key).compare_and_swap(key, expected, new)
+    }
+
+    pub fn set_ttl<Q>(&self, key: &Q, ttl: Duration) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).set_ttl(key, ttl)
+    }
+
+    pub fn touch_ttl<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).touch_ttl(key)
+    }
+
+    pub fn pin<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).pin(key)
+    }
+
+    pub fn unpin<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).unpin(key)
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.shard(key).remove(key)
+    }
+
+    pub fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        for shard in &self.shards {
+            shard.retain(&mut f);
+        }
+    }
+
+    // runs `LruCache::cleanup` on every shard, returning the total number
+    // of expired entries
+    pub fn cleanup(&self) -> usize {
+        self.shards.iter().map(LruCache::cleanup).sum()
+    }
+
+    // one receiver for the events of every shard
+    pub fn subscribe(&self) -> Receiver<CacheEvent<K, V>>
+    where
+        K: Clone,
+    {
+        let (events, receiver) = mpsc::channel();
+        for shard in &self.shards {
+            shard.add_subscriber(Subscriber::Events(events.clone()));
+        }
+        receiver
+    }
+
+    // one stream for the evictions of every shard
+    #[cfg(feature = "async")]
+    pub fn evictions(&self) -> EvictionStream<K, V>
+    where
+        K: Clone,
+    {
+        let (evictions, receiver) = futures_channel::mpsc::unbounded();
+        for shard in &self.shards {
+            shard.add_subscriber(Subscriber::Evictions(evictions.clone()));
+        }
+        EvictionStream::new(receiver)
+    }
+
+    // totals over all shards
+    pub fn stats(&self) -> CacheStats {
+        self.shards.iter().map(LruCache::stats).sum()
+    }
+
+    // one entry per shard, in shard order
+    pub fn shard_stats(&self) -> Vec<ShardStats> {
+        self.shards
+            .iter()
+            .map(|shard| ShardStats {
+                len: shard.len(),
+                stats: shard.stats(),
+                lock: shard.lock_contention(),
+            })
+            .collect()
+    }
+
+    // also clears the lock contention totals
+    pub fn reset_stats(&self) {
+        self.shards.iter().for_each(LruCache::reset_stats);
+    }
+
+    // summed over all shards, the max wait is the longest on any one shard
+    pub fn lock_contention(&self) -> LockContention {
+        self.shards.iter().map(LruCache::lock_contention).sum()
+    }
+
+    // clears shard by shard, entries put concurrently into an already
+    // cleared shard survive
+    pub fn clear(&self) {
+        self.shards.iter().for_each(LruCache::clear);
+    }
+
+    // the new capacity is split across the existing shards the same way
+    // `new` splits it, each shard trims its own LRU entries. shards left
+    // with no capacity simply stop caching
+    pub fn set_capacity(&self, capacity: usize) {
+        let split = split_capacity(capacity, self.shards.len());
+        for (shard, capacity) in self.shards.iter().zip(split) {
+            shard.set_capacity(capacity);
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        // unbounded shards make for an unbounded cache
+        self.shards
+            .iter()
+            .map(LruCache::capacity)
+            .fold(0, usize::saturating_add)
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    // takes each shard lock in turn, so the total is not a consistent
+    // snapshot while other threads are writing
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(LruCache::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(LruCache::is_empty)
+    }
+
+    // sum of the per shard estimates, see `LruCache::memory_usage`
+    pub fn memory_usage(&self) -> usize
+    where
+        K: HeapSize,
+        V: HeapSize,
+    {
+        size_of::<Self>()
+            + self
+                .shards
+                .iter()
+                .map(LruCache::memory_usage)
+                .sum::<usize>()
+    }
+}
+
+// spreads `capacity` over `shards` as evenly as possible
+pub(crate) fn split_capacity(capacity: usize, shards: usize) -> impl Iterator<Item = usize> {
+    let base = capacity / shards;
+    let extra = capacity % shards;
+
+    (0..shards).map(move |i| base + usize::from(i < extra))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn capacity_is_split_across_shards() {
+        let cache: ShardedLruCache<u32, u32> = ShardedLruCache::new(10, 4);
+
+        assert_eq!(cache.shard_count(), 4);
+        assert_eq!(cache.capacity(), 10);
+
+        let small: ShardedLruCache<u32, u32> = ShardedLruCache::new(3, 8);
+        assert_eq!(small.shard_count(), 3);
+        assert_eq!(small.capacity(), 3);
+    }
+
+    #[test]
+    fn insert_and_get_across_shards() {
+        // every shard can hold all keys, so skew never causes an eviction
+        let cache = ShardedLruCache::new(128, 4);
+
+        for i in 0..32 {
+            cache.put(i, i * 2);
+        }
+
+        for i in 0..32 {
+            assert_eq!(cache.get(&i), Some(i * 2));
+        }
+        assert_eq!(cache.len(), 32);
+    }
+
+    #[test]
+    fn borrowed_key_finds_owned_shard() {
+        let cache = ShardedLruCache::new(256, 8);
+
+        for i in 0..32 {
+            cache.put(format!("key-{i}"), i);
+        }
+
+        for i in 0..32 {
+            assert_eq!(cache.get(format!("key-{i}").as_str()), Some(i));
+        }
+    }
+
+    #[test]
+    fn batch_ops_keep_input_order() {
+        let cache = ShardedLruCache::new(256, 8);
+
+        cache.put_many((0..32).map(|i| (i, i * 3)));
+
+        let keys: Vec<i32> = (0..40).rev().collect();
+        let expected: Vec<_> = keys.iter().map(|&k| (k < 32).then_some(k * 3)).collect();
+        assert_eq!(cache.get_many(&keys), expected);
+    }
+
+    #[test]
+    fn shared_hasher_spreads_keys() {
+        use std::hash::{BuildHasherDefault, DefaultHasher};
+
+        let cache: ShardedLruCache<u32, u32, BuildHasherDefault<DefaultHasher>> =
+            ShardedLruCache::with_hasher(1024, 8, BuildHasherDefault::default());
+
+        cache.put_many((0..512).map(|i| (i, i)));
+
+        // every shard should see some of the keys
+        assert!(cache.shards.iter().all(|shard| !shard.is_empty()));
+        assert_eq!(cache.get(&7), Some(7));
+    }
+
+    #[test]
+    fn concurrent_usage_stays_bounded() {
+        let cache = Arc::new(ShardedLruCache::new(16, 4));
+        let mut handles = vec![];
+
+        for t in 0..8 {
+            let c = Arc::clone(&cache);
+            handles.push(thread::spawn(move || {
+                for i in 0..500 {
+                    c.put(i, t);
+                    let _ = c.get(&i);
+                }
+            }));
+        }
+
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        assert!(cache.len() <= 16);
+    }
+
+    #[test]
+    fn shard_stats_cover_every_shard() {
+        let cache = ShardedLruCache::new(64, 4);
+
+        for i in 0..16 {
+            cache.put(i, i);
+        }
+        for i in 0..20 {
+            cache.get(&i);
+        }
+
+        let shards = cache.shard_stats();
+        assert_eq!(shards.len(), 4);
+        assert_eq!(shards.iter().map(|s| s.len).sum::<usize>(), 16);
+        let total: CacheStats = shards.iter().map(|s| s.stats).sum();
+        assert_eq!(total, cache.stats());
+        assert_eq!((total.hits, total.misses), (16, 4));
+    }
+}
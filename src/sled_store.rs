@@ -0,0 +1,121 @@
+use std::marker::PhantomData;
+use std::path::Path;
+
+use crate::{CacheStore, Tier};
+
+// keys and values kept in a sled tree, which survives crashes and restarts
+//
+// usable both as the store behind `CacheBuilder::persist_to` and as the
+// second tier of a `TieredCache`. keys and values are stored as their bytes.
+// sled errors are not surfaced, a failed write leaves the key as it was and
+// a failed read is a miss
+pub struct SledStore<K, V> {
+    tree: sled::Tree,
+    _marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> SledStore<K, V> {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self {
+            tree,
+            _marker: PhantomData,
+        }
+    }
+
+    // opens, or creates, the database at `path` and uses its default tree
+    pub fn open(path: impl AsRef<Path>) -> sled::Result<Self> {
+        let db = sled::open(path)?;
+        Ok(Self::new(sled::Tree::clone(&db)))
+    }
+
+    pub fn tree(&self) -> &sled::Tree {
+        &self.tree
+    }
+}
+
+impl<K: AsRef<[u8]>, V: AsRef<[u8]>> CacheStore<K, V> for SledStore<K, V> {
+    fn write(&self, key: &K, value: &V) {
+        let _ = self.tree.insert(key.as_ref(), value.as_ref());
+    }
+
+    fn delete(&self, key: &K) {
+        let _ = self.tree.remove(key.as_ref());
+    }
+
+    // one atomic sled batch, a crash applies all of it or none
+    fn write_all(&self, batch: &[(K, Option<V>)]) {
+        let mut writes = sled::Batch::default();
+        for (key, value) in batch {
+            match value {
+                Some(value) => writes.insert(key.as_ref(), value.as_ref()),
+                None => writes.remove(key.as_ref()),
+            }
+        }
+        let _ = self.tree.apply_batch(writes);
+    }
+
+    // sled syncs to disk on its own every so often, this waits for it
+    fn flush(&self) {
+        let _ = self.tree.flush();
+    }
+}
+
+impl<K, V> Tier<K, V> for SledStore<K, V>
+where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]> + From<Vec<u8>>,
+{
+    fn get(&self, key: &K) -> Option<V> {
+        let bytes = self.tree.get(key.as_ref()).ok()??;
+        Some(V::from(bytes.to_vec()))
+    }
+
+    fn put(&self, key: K, value: V) {
+        CacheStore::write(self, &key, &value);
+    }
+
+    fn remove(&self, key: &K) {
+        CacheStore::delete(self, key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LruCache, TieredCache};
+
+    fn temporary() -> sled::Tree {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        sled::Tree::clone(&db)
+    }
+
+    #[test]
+    fn persisted_cache_writes_through() {
+        let tree = temporary();
+        let cache = LruCache::builder()
+            .capacity(1)
+            .persist_to(tree.clone())
+            .build()
+            .unwrap();
+
+        cache.put("a", "1");
+        cache.put("b", "2");
+        cache.remove(&"b");
+        cache.flush();
+
+        assert_eq!(tree.get("a").unwrap().as_deref(), Some(&b"1"[..]));
+        assert_eq!(tree.get("b").unwrap(), None);
+    }
+
+    #[test]
+    fn sled_serves_as_the_second_tier() {
+        let cache = TieredCache::new(1, SledStore::new(temporary()));
+
+        cache.put("a", b"1".to_vec());
+        cache.put("b", b"2".to_vec());
+
+        assert_eq!(cache.l2().tree().len(), 1);
+        assert_eq!(cache.get(&"a"), Some(b"1".to_vec()));
+        assert!(cache.l2().tree().contains_key("b").unwrap());
+    }
+}
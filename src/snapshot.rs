@@ -0,0 +1,304 @@
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::LruCache;
+
+// every snapshot starts with this, followed by a version byte
+const MAGIC: &[u8; 8] = b"LRUSNAP\0";
+const VERSION: u8 = 1;
+
+// binary form of keys and values in a snapshot, see `LruCache::save_to`
+//
+// integers are little endian, strings and sequences are prefixed with their
+// length. implement it for the key and value types a cache is saved with
+pub trait Encode: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    // reads one value back, advancing `input` past it
+    fn decode(input: &mut &[u8]) -> io::Result<Self>;
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if input.len() < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "snapshot cut short",
+        ));
+    }
+    let (head, rest) = input.split_at(len);
+    *input = rest;
+    Ok(head)
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+
+macro_rules! encode_int {
+    ($($ty:ty),* $(,)?) => {
+        $(impl Encode for $ty {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend(self.to_le_bytes());
+            }
+
+            fn decode(input: &mut &[u8]) -> io::Result<Self> {
+                let bytes = take(input, size_of::<$ty>())?;
+                Ok(<$ty>::from_le_bytes(bytes.try_into().unwrap()))
+            }
+        })*
+    };
+}
+
+encode_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+// sizes are saved as u64 so snapshots move between 32 and 64 bit hosts
+impl Encode for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        usize::try_from(u64::decode(input)?).map_err(|_| invalid("size out of range"))
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(u8::from(*self));
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        match u8::decode(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("not a bool")),
+        }
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        let len = usize::decode(input)?;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("string is not utf-8"))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for item in self {
+            item.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        let len = usize::decode(input)?;
+        // the length is untrusted, the vec grows as items actually decode
+        let mut items = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            items.push(T::decode(input)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.is_some().encode(out);
+        if let Some(value) = self {
+            value.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        if bool::decode(input)? {
+            T::decode(input).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: Encode> Encode for Arc<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (**self).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        T::decode(input).map(Arc::new)
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> io::Result<Self> {
+        Ok((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+// a snapshot is the header, the entry count, then every entry as key, value
+// and remaining time to live in milliseconds, least recently used first
+impl<K: Eq + Hash + Encode, V: Encode, S: BuildHasher> LruCache<K, V, S> {
+    // writes the live entries along with their recency order and time left
+    // to live. the entries are encoded under the read lock and written out
+    // once it is released, so a slow writer does not hold up the cache
+    pub fn save_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        {
+            let state = self.read();
+            let cutoff = state.expiry_cutoff();
+            let now = state.clock.now();
+            let live: Vec<_> = state.iter_live(cutoff).collect();
+
+            live.len().encode(&mut out);
+            for node in live.into_iter().rev() {
+                node.key.encode(&mut out);
+                node.value.encode(&mut out);
+                node.expires_at
+                    .map(|at| at.saturating_duration_since(now).as_millis() as u64)
+                    .encode(&mut out);
+            }
+        }
+        writer.write_all(&out)?;
+        writer.flush()
+    }
+
+    // puts every entry of a snapshot written by `save_to`, oldest first, so
+    // they come back in the same recency order and expire when they would
+    // have. returns how many entries were read, a cache smaller than the
+    // saved one keeps the most recent of them. like `warm_up_from_store` it
+    // neither writes to the backing store nor tells the listener or
+    // subscribers
+    pub fn load_from<R: Read>(&self, mut reader: R) -> io::Result<usize> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut input = &bytes[..];
+        if take(&mut input, MAGIC.len() + 1)? != [MAGIC.as_slice(), &[VERSION]].concat() {
+            return Err(invalid("not a cache snapshot"));
+        }
+
+        // decoded in full first, a corrupt snapshot leaves the cache alone
+        let count = usize::decode(&mut input)?;
+        let mut entries = Vec::with_capacity(count.min(input.len()));
+        for _ in 0..count {
+            let key = K::decode(&mut input)?;
+            let value = V::decode(&mut input)?;
+            let ttl = Option::<u64>::decode(&mut input)?;
+            entries.push((key, value, ttl));
+        }
+        self.restore(
+            entries
+                .into_iter()
+                .map(|(key, value, ttl)| (key, value, ttl.map(Duration::from_millis))),
+        );
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::MockClock;
+
+    #[test]
+    fn restores_entries_in_recency_order() {
+        let cache = LruCache::new(3);
+        cache.put(1u32, "one".to_string());
+        cache.put(2, "two".to_string());
+        cache.put(3, "three".to_string());
+        cache.get(&1);
+
+        let mut saved = Vec::new();
+        cache.save_to(&mut saved).unwrap();
+
+        let restored: LruCache<u32, String> = LruCache::new(3);
+        assert_eq!(restored.load_from(&saved[..]).unwrap(), 3);
+        assert_eq!(restored.keys().collect::<Vec<_>>(), [1, 3, 2]);
+        assert_eq!(restored.peek(&3), Some("three".to_string()));
+    }
+
+    #[test]
+    fn time_left_to_live_carries_over() {
+        let clock = MockClock::new();
+        let cache = LruCache::builder().clock(clock.clone()).build().unwrap();
+        cache.put_with_ttl(1u32, 10u64, Duration::from_secs(60));
+        cache.put(2, 20);
+        clock.advance(Duration::from_secs(45));
+
+        let mut saved = Vec::new();
+        cache.save_to(&mut saved).unwrap();
+        let restored: LruCache<u32, u64> =
+            LruCache::builder().clock(clock.clone()).build().unwrap();
+        restored.load_from(&saved[..]).unwrap();
+
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(restored.get(&1), None);
+        assert_eq!(restored.get(&2), Some(20));
+    }
+
+    #[test]
+    fn loading_is_quiet_and_skips_the_store() {
+        struct NoWrites;
+
+        impl crate::CacheStore<u32, u32> for NoWrites {
+            fn write(&self, key: &u32, _: &u32) {
+                panic!("{key} written to the store");
+            }
+
+            fn delete(&self, _: &u32) {}
+        }
+
+        let saved_from = LruCache::new(4);
+        for i in 0..4u32 {
+            saved_from.put(i, i);
+        }
+        let mut saved = Vec::new();
+        saved_from.save_to(&mut saved).unwrap();
+
+        let told = Arc::new(AtomicUsize::new(0));
+        let counter = told.clone();
+        let cache = LruCache::builder()
+            .capacity(2)
+            .write_through(NoWrites)
+            .eviction_listener(move |_, _, _| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            })
+            .build()
+            .unwrap();
+
+        assert_eq!(cache.load_from(&saved[..]).unwrap(), 4);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [3, 2]);
+        assert_eq!(told.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn foreign_and_truncated_input_is_rejected() {
+        let cache: LruCache<u32, u32> = LruCache::new(4);
+        assert!(cache.load_from(&b"definitely not a snapshot"[..]).is_err());
+
+        let full = LruCache::new(4);
+        full.put(1u32, 1u32);
+        full.put(2, 2);
+        let mut saved = Vec::new();
+        full.save_to(&mut saved).unwrap();
+        saved.truncate(saved.len() - 3);
+
+        assert!(cache.load_from(&saved[..]).is_err());
+        assert!(cache.is_empty());
+    }
+}
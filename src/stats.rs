@@ -0,0 +1,518 @@
+use std::iter::Sum;
+use std::ops::Add;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::Clock;
+
+// counters since the cache was created or the last `reset_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    // new keys only, overwriting a value is not counted
+    pub insertions: u64,
+    // entries pushed out for capacity or weight, explicit removals are not
+    // counted
+    pub evictions: u64,
+    pub expirations: u64,
+    // lookups within the configured hit rate window, both stay 0 without one
+    pub recent_hits: u64,
+    pub recent_lookups: u64,
+    // misses on keys that were evicted for room not long before, would have
+    // been hits in a larger cache. 0 unless `CacheBuilder::track_ghosts`
+    pub ghost_hits: u64,
+}
+
+// span the recent hit rate is measured over, see
+// `CacheBuilder::hit_rate_window`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitRateWindow {
+    // the last n lookups
+    Samples(usize),
+    // lookups within this long, tracked in a few buckets so the window
+    // moves in steps of a tenth of its length
+    Duration(Duration),
+}
+
+impl CacheStats {
+    // share of lookups that hit, 0 before the first lookup
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+
+    // hit rate within the window, None without a window or recent lookups
+    pub fn recent_hit_rate(&self) -> Option<f64> {
+        (self.recent_lookups > 0).then(|| self.recent_hits as f64 / self.recent_lookups as f64)
+    }
+}
+
+impl Add for CacheStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            hits: self.hits + other.hits,
+            misses: self.misses + other.misses,
+            insertions: self.insertions + other.insertions,
+            evictions: self.evictions + other.evictions,
+            expirations: self.expirations + other.expirations,
+            recent_hits: self.recent_hits + other.recent_hits,
+            recent_lookups: self.recent_lookups + other.recent_lookups,
+            ghost_hits: self.ghost_hits + other.ghost_hits,
+        }
+    }
+}
+
+impl Sum for CacheStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+// live counters, atomic so removals, and lookups served under the read
+// lock, can be counted from code holding only a shared reference to the
+// state. the window needs the write lock, a cache with one never serves
+// lookups under the read lock
+#[derive(Default)]
+pub(crate) struct StatsCounter {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    insertions: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+    ghost_hits: AtomicU64,
+    pub(crate) window: Option<Window>,
+    // label for the `metrics` handles, which are resolved on first use
+    #[cfg(feature = "metrics")]
+    pub(crate) name: Option<String>,
+    #[cfg(feature = "metrics")]
+    emitter: std::sync::OnceLock<Emitter>,
+}
+
+impl StatsCounter {
+    #[cfg(feature = "metrics")]
+    fn emitter(&self) -> &Emitter {
+        self.emitter
+            .get_or_init(|| Emitter::new(self.name.as_deref()))
+    }
+
+    pub(crate) fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.emitter().hits.increment(1);
+    }
+
+    pub(crate) fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.emitter().misses.increment(1);
+    }
+
+    // the recent hit rate is the only part of a lookup count that needs the
+    // write lock
+    pub(crate) fn record_recent(&mut self, hit: bool) {
+        if let Some(window) = &mut self.window {
+            window.record(hit);
+        }
+    }
+
+    pub(crate) fn insertion(&self) {
+        self.insertions.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.emitter().insertions.increment(1);
+    }
+
+    pub(crate) fn eviction(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.emitter().evictions.increment(1);
+    }
+
+    pub(crate) fn expiration(&self) {
+        self.expirations.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        self.emitter().expirations.increment(1);
+    }
+
+    pub(crate) fn ghost_hit(&self) {
+        self.ghost_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // entry count changes only feed the `metrics` gauge, `len` is computed
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub(crate) fn entries_added(&self, n: usize) {
+        #[cfg(feature = "metrics")]
+        self.emitter().entries.increment(n as f64);
+    }
+
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub(crate) fn entries_removed(&self, n: usize) {
+        #[cfg(feature = "metrics")]
+        self.emitter().entries.decrement(n as f64);
+    }
+
+    pub(crate) fn snapshot(&self) -> CacheStats {
+        let (recent_hits, recent_lookups) = self.window.as_ref().map_or((0, 0), Window::totals);
+
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            insertions: self.insertions.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            recent_hits,
+            recent_lookups,
+            ghost_hits: self.ghost_hits.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        if let Some(window) = &mut self.window {
+            window.clear();
+        }
+        for counter in [
+            &self.hits,
+            &self.misses,
+            &self.insertions,
+            &self.evictions,
+            &self.expirations,
+            &self.ghost_hits,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+// handles into the `metrics` facade, resolved against whatever recorder is
+// installed when the cache first reports something. the entry gauge moves by
+// increments, so the shards of a `ShardedLruCache` add up under one name
+#[cfg(feature = "metrics")]
+pub(crate) struct Emitter {
+    hits: metrics::Counter,
+    misses: metrics::Counter,
+    insertions: metrics::Counter,
+    evictions: metrics::Counter,
+    expirations: metrics::Counter,
+    entries: metrics::Gauge,
+}
+
+#[cfg(feature = "metrics")]
+impl Emitter {
+    // labelled `cache=<name>` when the cache was given a name
+    pub(crate) fn new(name: Option<&str>) -> Self {
+        let labels: Vec<metrics::Label> = name
+            .map(|name| metrics::Label::new("cache", name.to_owned()))
+            .into_iter()
+            .collect();
+
+        Self {
+            hits: metrics::counter!("lru_cache.hits", labels.clone()),
+            misses: metrics::counter!("lru_cache.misses", labels.clone()),
+            insertions: metrics::counter!("lru_cache.insertions", labels.clone()),
+            evictions: metrics::counter!("lru_cache.evictions", labels.clone()),
+            expirations: metrics::counter!("lru_cache.expirations", labels.clone()),
+            entries: metrics::gauge!("lru_cache.entries", labels),
+        }
+    }
+}
+
+// time threads spent blocked on a cache lock. kept outside the lock, so
+// waiting for it can be counted without holding it
+#[derive(Default)]
+pub(crate) struct LockWaits {
+    nanos: AtomicU64,
+    max_nanos: AtomicU64,
+    waits: AtomicU64,
+}
+
+impl LockWaits {
+    pub(crate) fn record(&self, waited: Duration) {
+        let nanos = u64::try_from(waited.as_nanos()).unwrap_or(u64::MAX);
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+        self.waits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> LockContention {
+        LockContention {
+            contended: self.waits.load(Ordering::Relaxed),
+            total_wait: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+            max_wait: Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        for counter in [&self.nanos, &self.max_nanos, &self.waits] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+// how much blocking on a cache lock has cost, from
+// `LruCache::lock_contention`. only acquisitions that found the lock taken
+// are counted, uncontended ones are never timed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockContention {
+    // acquisitions that had to wait
+    pub contended: u64,
+    pub total_wait: Duration,
+    // longest single wait
+    pub max_wait: Duration,
+}
+
+impl LockContention {
+    // mean wait of the acquisitions that blocked
+    pub fn mean_wait(&self) -> Duration {
+        match u32::try_from(self.contended) {
+            Ok(0) => Duration::ZERO,
+            Ok(n) => self.total_wait / n,
+            Err(_) => Duration::from_nanos(
+                u64::try_from(self.total_wait.as_nanos() / u128::from(self.contended))
+                    .unwrap_or(u64::MAX),
+            ),
+        }
+    }
+}
+
+// totals add up, the max is the largest of the parts
+impl Add for LockContention {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        LockContention {
+            contended: self.contended + other.contended,
+            total_wait: self.total_wait + other.total_wait,
+            max_wait: self.max_wait.max(other.max_wait),
+        }
+    }
+}
+
+impl Sum for LockContention {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), Add::add)
+    }
+}
+
+// buckets a time window is split into
+const BUCKETS: u64 = 10;
+
+// recent lookups, either the last n outcomes or per-bucket counts
+pub(crate) enum Window {
+    Samples {
+        outcomes: Vec<bool>,
+        next: usize,
+        filled: usize,
+        hits: usize,
+    },
+    Duration {
+        clock: Arc<dyn Clock>,
+        start: Instant,
+        bucket: Duration,
+        // (bucket number, hits, lookups), indexed by bucket number modulo
+        // the bucket count
+        buckets: Box<[(u64, u64, u64)]>,
+    },
+}
+
+impl Window {
+    pub(crate) fn new(window: HitRateWindow, clock: Arc<dyn Clock>) -> Self {
+        match window {
+            HitRateWindow::Samples(n) => Window::Samples {
+                outcomes: vec![false; n],
+                next: 0,
+                filled: 0,
+                hits: 0,
+            },
+            HitRateWindow::Duration(span) => Window::Duration {
+                start: clock.now(),
+                clock,
+                bucket: span / BUCKETS as u32,
+                buckets: vec![(0, 0, 0); BUCKETS as usize].into(),
+            },
+        }
+    }
+
+    fn record(&mut self, hit: bool) {
+        match self {
+            Window::Samples {
+                outcomes,
+                next,
+                filled,
+                hits,
+            } => {
+                // the oldest outcome drops out once the window is full
+                if *filled == outcomes.len() {
+                    *hits -= usize::from(outcomes[*next]);
+                } else {
+                    *filled += 1;
+                }
+                outcomes[*next] = hit;
+                *hits += usize::from(hit);
+                *next = (*next + 1) % outcomes.len();
+            }
+            Window::Duration {
+                clock,
+                start,
+                bucket,
+                buckets,
+            } => {
+                let current = bucket_number(clock.now(), *start, *bucket);
+                let slot = &mut buckets[(current % BUCKETS) as usize];
+                if slot.0 != current {
+                    *slot = (current, 0, 0);
+                }
+                slot.1 += u64::from(hit);
+                slot.2 += 1;
+            }
+        }
+    }
+
+    // (hits, lookups) within the window
+    fn totals(&self) -> (u64, u64) {
+        match self {
+            Window::Samples { filled, hits, .. } => (*hits as u64, *filled as u64),
+            Window::Duration {
+                clock,
+                start,
+                bucket,
+                buckets,
+            } => {
+                let current = bucket_number(clock.now(), *start, *bucket);
+                buckets
+                    .iter()
+                    .filter(|(number, _, lookups)| *lookups > 0 && current - number < BUCKETS)
+                    .fold((0, 0), |(hits, lookups), (_, h, l)| (hits + h, lookups + l))
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Window::Samples {
+                next, filled, hits, ..
+            } => {
+                *next = 0;
+                *filled = 0;
+                *hits = 0;
+            }
+            Window::Duration { buckets, .. } => buckets.fill((0, 0, 0)),
+        }
+    }
+}
+
+fn bucket_number(now: Instant, start: Instant, bucket: Duration) -> u64 {
+    (now.saturating_duration_since(start).as_nanos() / bucket.as_nanos().max(1)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MockClock;
+
+    #[test]
+    fn sample_window_keeps_the_last_outcomes() {
+        let mut window = Window::new(HitRateWindow::Samples(4), Arc::new(MockClock::new()));
+
+        for hit in [true, true, true, true, false, false] {
+            window.record(hit);
+        }
+        assert_eq!(window.totals(), (2, 4));
+    }
+
+    #[test]
+    fn lock_contention_adds_up() {
+        let wait = |contended, total, max| LockContention {
+            contended,
+            total_wait: Duration::from_millis(total),
+            max_wait: Duration::from_millis(max),
+        };
+
+        let sum: LockContention = [wait(2, 30, 20), wait(1, 50, 50), wait(0, 0, 0)]
+            .into_iter()
+            .sum();
+        assert_eq!(sum, wait(3, 80, 50));
+        assert_eq!(sum.mean_wait(), Duration::from_nanos(26_666_666));
+        assert_eq!(LockContention::default().mean_wait(), Duration::ZERO);
+    }
+
+    #[test]
+    fn time_window_forgets_old_buckets() {
+        let clock = MockClock::new();
+        let mut window = Window::new(
+            HitRateWindow::Duration(Duration::from_secs(10)),
+            Arc::new(clock.clone()),
+        );
+
+        window.record(false);
+        window.record(false);
+        clock.advance(Duration::from_secs(5));
+        window.record(true);
+        assert_eq!(window.totals(), (1, 3));
+
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(window.totals(), (1, 1));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics_follow_the_cache() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        use crate::LruCache;
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let cache = LruCache::builder()
+            .capacity(2)
+            .name("users")
+            .build()
+            .unwrap();
+
+        // the handles are resolved on first use, so only the calls need the
+        // recorder in scope
+        metrics::with_local_recorder(&recorder, || {
+            cache.put(1, 1);
+            cache.put(2, 2);
+            cache.put(3, 3);
+            cache.get(&3);
+            cache.get(&1);
+        });
+
+        let mut seen: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let key = key.key();
+                assert!(
+                    key.labels()
+                        .any(|l| l.key() == "cache" && l.value() == "users")
+                );
+                let value = match value {
+                    DebugValue::Counter(n) => n as f64,
+                    DebugValue::Gauge(n) => n.into_inner(),
+                    DebugValue::Histogram(_) => unreachable!(),
+                };
+                (key.name().to_owned(), value)
+            })
+            .collect();
+        seen.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            seen,
+            [
+                ("lru_cache.entries".to_owned(), 2.0),
+                ("lru_cache.evictions".to_owned(), 1.0),
+                ("lru_cache.expirations".to_owned(), 0.0),
+                ("lru_cache.hits".to_owned(), 1.0),
+                ("lru_cache.insertions".to_owned(), 3.0),
+                ("lru_cache.misses".to_owned(), 1.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// system of record behind a write-through cache
+//
+// set with `CacheBuilder::write_through`, every value the cache is given is
+// written here and every key removed by name deleted: `remove`, `delete`,
+// `CacheOp::Remove` and an entry's `remove`, but not `invalidate`. both run
+// under the cache's write lock so the store sees writes to a key in the
+// same order the cache does, at the price of holding the lock for the round
+// trip. evictions,
+// expirations and the bulk removals, `pop_lru`, `pop_mru`, `retain`, `drain`
+// and `clear`, only drop the cached copy and never reach the store
+pub trait CacheStore<K, V>: Send + Sync {
+    fn write(&self, key: &K, value: &V);
+
+    fn delete(&self, key: &K);
+
+    // applies a batch queued up by write-behind in order, a None value is a
+    // delete. stores with a bulk api should override this
+    fn write_all(&self, batch: &[(K, Option<V>)]) {
+        for (key, value) in batch {
+            match value {
+                Some(value) => self.write(key, value),
+                None => self.delete(key),
+            }
+        }
+    }
+
+    // returns once everything accepted so far has reached the store
+    fn flush(&self) {}
+}
+
+enum Queued<K, V> {
+    Op(K, Option<V>),
+    Flush(SyncSender<()>),
+}
+
+// write-behind adapter, set with `CacheBuilder::write_behind`
+//
+// writes and deletes are queued instead and a worker thread hands them to
+// the wrapped store in batches, every `interval` or once `limit` ops have
+// piled up. the queue holds at most `limit` ops as well, a put finding it
+// full waits for the worker instead of letting it grow. the worker writes
+// what is left and exits when the cache is dropped
+pub(crate) struct WriteBehind<K, V> {
+    backlog: Arc<Backlog<K, V>>,
+}
+
+// the worker's queue and the ops that found it full. those are queued under
+// the cache lock, so they are set aside in order and the writer waits for
+// the worker only once it has let go of the lock, in `relieve`. ops left
+// over by an entry handle wait for the next write
+pub(crate) struct Backlog<K, V> {
+    queue: SyncSender<Queued<K, V>>,
+    spill: Mutex<Spill<K, V>>,
+    // whether the spill may hold anything, checked before taking its lock
+    spilled: AtomicBool,
+    // held while handing spilled ops over, one writer at a time
+    relieving: Mutex<()>,
+}
+
+struct Spill<K, V> {
+    ops: VecDeque<Queued<K, V>>,
+    // an op taken off `ops` is on its way to the queue, later ones have to
+    // wait behind it
+    draining: bool,
+}
+
+impl<K, V> Backlog<K, V> {
+    // never blocks, an op that does not fit is spilled
+    fn push(&self, item: Queued<K, V>) {
+        let mut spill = self.spill.lock().unwrap();
+        let item = if spill.ops.is_empty() && !spill.draining {
+            match self.queue.try_send(item) {
+                // only disconnected once the worker is gone, which outlives
+                // the cache
+                Ok(()) | Err(TrySendError::Disconnected(_)) => return,
+                Err(TrySendError::Full(item)) => item,
+            }
+        } else {
+            item
+        };
+        spill.ops.push_back(item);
+        self.spilled.store(true, Ordering::Release);
+    }
+
+    // hands the spilled ops to the worker, waiting while its queue is full.
+    // must not be called under the cache lock
+    pub(crate) fn relieve(&self) {
+        if !self.spilled.load(Ordering::Acquire) {
+            return;
+        }
+        let _turn = self.relieving.lock().unwrap();
+        loop {
+            let item = {
+                let mut spill = self.spill.lock().unwrap();
+                let Some(item) = spill.ops.pop_front() else {
+                    spill.draining = false;
+                    self.spilled.store(false, Ordering::Release);
+                    return;
+                };
+                spill.draining = true;
+                item
+            };
+            let _ = self.queue.send(item);
+        }
+    }
+}
+
+// nothing spilled is lost with the cache, the worker still gets it
+impl<K, V> Drop for Backlog<K, V> {
+    fn drop(&mut self) {
+        let spill = self.spill.get_mut().unwrap();
+        for item in spill.ops.drain(..) {
+            let _ = self.queue.send(item);
+        }
+    }
+}
+
+impl<K, V> WriteBehind<K, V>
+where
+    K: Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    pub(crate) fn start<T>(store: T, interval: Duration, limit: usize) -> Self
+    where
+        T: CacheStore<K, V> + 'static,
+    {
+        let limit = limit.max(1);
+        let (queue, pending) = mpsc::sync_channel(limit);
+        thread::Builder::new()
+            .name("lru-cache-write-behind".into())
+            .spawn(move || drain(store, pending, interval, limit))
+            .expect("failed to spawn the write-behind thread");
+        let backlog = Backlog {
+            queue,
+            spill: Mutex::new(Spill {
+                ops: VecDeque::new(),
+                draining: false,
+            }),
+            spilled: AtomicBool::new(false),
+            relieving: Mutex::new(()),
+        };
+        Self {
+            backlog: Arc::new(backlog),
+        }
+    }
+
+    // for the cache to relieve once its lock is released
+    pub(crate) fn backlog(&self) -> Arc<Backlog<K, V>> {
+        Arc::clone(&self.backlog)
+    }
+}
+
+fn drain<K, V, T: CacheStore<K, V>>(
+    store: T,
+    pending: Receiver<Queued<K, V>>,
+    interval: Duration,
+    limit: usize,
+) {
+    let mut batch = Vec::new();
+    let mut due = Instant::now() + interval;
+    loop {
+        let wait = due.saturating_duration_since(Instant::now());
+        match pending.recv_timeout(wait) {
+            Ok(Queued::Op(key, value)) => {
+                batch.push((key, value));
+                if batch.len() < limit {
+                    continue;
+                }
+            }
+            Ok(Queued::Flush(done)) => {
+                store.write_all(&batch);
+                batch.clear();
+                store.flush();
+                let _ = done.send(());
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                store.write_all(&batch);
+                store.flush();
+                return;
+            }
+        }
+        if !batch.is_empty() {
+            store.write_all(&batch);
+            batch.clear();
+        }
+        due = Instant::now() + interval;
+    }
+}
+
+impl<K, V> CacheStore<K, V> for WriteBehind<K, V>
+where
+    K: Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    fn write(&self, key: &K, value: &V) {
+        self.backlog
+            .push(Queued::Op(key.clone(), Some(value.clone())));
+    }
+
+    fn delete(&self, key: &K) {
+        self.backlog.push(Queued::Op(key.clone(), None));
+    }
+
+    // queued behind every op accepted before it, so the worker has written
+    // them all by the time it answers. called without the cache lock
+    fn flush(&self) {
+        let (done, flushed) = mpsc::sync_channel(1);
+        self.backlog.push(Queued::Flush(done));
+        self.backlog.relieve();
+        let _ = flushed.recv();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{CacheOp, Entry, LruCache};
+
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<String>>>);
+
+    impl CacheStore<u32, &'static str> for Log {
+        fn write(&self, key: &u32, value: &&'static str) {
+            self.0.lock().unwrap().push(format!("write {key} {value}"));
+        }
+
+        fn delete(&self, key: &u32) {
+            self.0.lock().unwrap().push(format!("delete {key}"));
+        }
+    }
+
+    #[test]
+    fn puts_and_removes_reach_the_store() {
+        let log = Log::default();
+        let cache = LruCache::builder()
+            .capacity(1)
+            .write_through(log.clone())
+            .build()
+            .unwrap();
+
+        cache.put(1, "a");
+        cache.put(1, "b");
+        // evicting 1 keeps it in the store
+        cache.put(2, "c");
+        cache.remove(&2);
+        // evicted, a borrowed key leaves the store alone
+        cache.remove(&1);
+        cache.delete(&1);
+        cache.apply([CacheOp::Remove(3)]);
+
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            [
+                "write 1 a",
+                "write 1 b",
+                "write 2 c",
+                "delete 2",
+                "delete 1",
+                "delete 3"
+            ]
+        );
+    }
+
+    #[test]
+    fn entry_and_conditional_writes_reach_the_store() {
+        let log = Log::default();
+        let cache = LruCache::builder()
+            .capacity(4)
+            .write_through(log.clone())
+            .build()
+            .unwrap();
+
+        assert_eq!(cache.put_if_absent(1, "a"), None);
+        assert_eq!(cache.put_if_absent(1, "b"), Some("a"));
+        cache.entry(2).or_insert("c");
+        cache.entry(2).and_modify(|v| *v = "d").or_insert("e");
+        cache.replace_if_present(&2, "f");
+        cache.compare_and_swap(&2, &"f", "g");
+        cache.with_value_mut(&1, |v| *v = "h");
+        if let Entry::Occupied(entry) = cache.entry(1) {
+            entry.remove();
+        }
+
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            [
+                "write 1 a",
+                "write 2 c",
+                "write 2 d",
+                "write 2 f",
+                "write 2 g",
+                "write 1 h",
+                "delete 1"
+            ]
+        );
+    }
+
+    #[test]
+    fn bulk_removals_leave_the_store_alone() {
+        let log = Log::default();
+        let cache = LruCache::builder()
+            .capacity(8)
+            .write_through(log.clone())
+            .build()
+            .unwrap();
+        for key in 0..6 {
+            cache.put(key, "v");
+        }
+        log.0.lock().unwrap().clear();
+
+        cache.pop_lru();
+        cache.pop_mru();
+        cache.retain(|&key, _| key != 2);
+        drop(cache.drain());
+        cache.put(9, "v");
+        cache.clear();
+
+        assert_eq!(*log.0.lock().unwrap(), ["write 9 v"]);
+    }
+
+    #[test]
+    fn loaded_values_are_not_written_back() {
+        let log = Log::default();
+        let cache = LruCache::builder()
+            .capacity(4)
+            .write_through(log.clone())
+            .build_loading(|_: &u32| Some("loaded"))
+            .unwrap();
+
+        assert_eq!(cache.get(&1), Some("loaded"));
+        cache.put(2, "put");
+
+        assert_eq!(*log.0.lock().unwrap(), ["write 2 put"]);
+    }
+
+    #[test]
+    fn warm_up_writes_through_unless_told_otherwise() {
+        let log = Log::default();
+        let cache = LruCache::builder()
+            .capacity(4)
+            .write_through(log.clone())
+            .build()
+            .unwrap();
+
+        cache.warm_up([(1, "a"), (2, "b")]);
+        cache.warm_up_from_store([(3, "c")]);
+
+        assert_eq!(*log.0.lock().unwrap(), ["write 2 b", "write 1 a"]);
+        assert_eq!(cache.keys().collect::<Vec<_>>(), [3, 1, 2]);
+    }
+
+    #[test]
+    fn write_behind_queues_until_flushed() {
+        let log = Log::default();
+        let cache = LruCache::builder()
+            .capacity(4)
+            .write_behind(log.clone(), Duration::from_secs(3600), 16)
+            .build()
+            .unwrap();
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.remove(&1);
+        assert!(log.0.lock().unwrap().is_empty());
+
+        cache.flush();
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            ["write 1 a", "write 2 b", "delete 1"]
+        );
+    }
+
+    #[test]
+    fn a_full_batch_is_written_without_waiting() {
+        let log = Log::default();
+        let cache = LruCache::builder()
+            .capacity(4)
+            .write_behind(log.clone(), Duration::from_secs(3600), 2)
+            .build()
+            .unwrap();
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        for _ in 0..100 {
+            if log.0.lock().unwrap().len() == 2 {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the batch was never written");
+    }
+
+    // writes block until released, the first one tells that it started
+    struct Gate {
+        log: Log,
+        started: Mutex<mpsc::Sender<()>>,
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl CacheStore<u32, &'static str> for Gate {
+        fn write(&self, key: &u32, value: &&'static str) {
+            let _ = self.started.lock().unwrap().send(());
+            self.release.lock().unwrap().recv().unwrap();
+            self.log.write(key, value);
+        }
+
+        fn delete(&self, key: &u32) {
+            self.log.delete(key);
+        }
+    }
+
+    #[test]
+    fn a_full_queue_holds_up_the_writer_not_the_cache() {
+        let log = Log::default();
+        let (started, first_write) = mpsc::channel();
+        let (release, gate) = mpsc::channel();
+        let store = Gate {
+            log: log.clone(),
+            started: Mutex::new(started),
+            release: Mutex::new(gate),
+        };
+        let cache = Arc::new(
+            LruCache::builder()
+                .capacity(4)
+                .write_behind(store, Duration::from_secs(3600), 1)
+                .build()
+                .unwrap(),
+        );
+
+        // the worker is stuck writing 1 and 2 fills the queue, so 3 has to
+        // wait for it
+        let writer = {
+            let cache = Arc::clone(&cache);
+            thread::spawn(move || {
+                for key in 1..=3 {
+                    cache.put(key, "v");
+                }
+            })
+        };
+        first_write.recv().unwrap();
+        while cache.len() < 3 {
+            thread::yield_now();
+        }
+        assert_eq!(cache.peek(&3), Some("v"));
+        assert!(!writer.is_finished());
+
+        for _ in 0..3 {
+            release.send(()).unwrap();
+        }
+        writer.join().unwrap();
+        cache.flush();
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            ["write 1 v", "write 2 v", "write 3 v"]
+        );
+    }
+}
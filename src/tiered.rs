@@ -0,0 +1,181 @@
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::sync::Arc;
+
+use crate::{BuildError, CacheBuilder, LruCache, RemovalCause};
+
+// the larger, slower level of a `TieredCache`, another cache or anything
+// that can hold entries for a while, e.g. a local disk store. entries may be
+// dropped by it at any time
+pub trait Tier<K, V> {
+    fn get(&self, key: &K) -> Option<V>;
+
+    fn put(&self, key: K, value: V);
+
+    fn remove(&self, key: &K);
+}
+
+impl<K: Eq + Hash, V: Clone, S: BuildHasher> Tier<K, V> for LruCache<K, V, S> {
+    fn get(&self, key: &K) -> Option<V> {
+        LruCache::get(self, key)
+    }
+
+    fn put(&self, key: K, value: V) {
+        LruCache::put(self, key, value);
+    }
+
+    fn remove(&self, key: &K) {
+        LruCache::remove(self, key);
+    }
+}
+
+// a small in-memory LRU in front of a larger second tier
+//
+// an entry lives in one level at a time. L1 evicting an entry for room
+// demotes it into L2, and an L2 hit moves the entry back up into L1,
+// possibly demoting another. entries expiring or explicitly removed from L1
+// are not demoted
+pub struct TieredCache<K, V, T, S = RandomState> {
+    l1: LruCache<K, V, S>,
+    l2: Arc<T>,
+}
+
+impl<K, V, T> TieredCache<K, V, T>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    T: Tier<K, V> + Send + Sync + 'static,
+{
+    pub fn new(l1_capacity: usize, l2: T) -> Self {
+        Self::with_builder(LruCache::builder().capacity(l1_capacity), l2)
+            .expect("a plain capacity always builds")
+    }
+}
+
+impl<K, V, T, S> TieredCache<K, V, T, S>
+where
+    K: Eq + Hash + Clone + 'static,
+    V: Clone + 'static,
+    T: Tier<K, V> + Send + Sync + 'static,
+    S: BuildHasher,
+{
+    // L1 is built from `builder`, an eviction listener set on it still
+    // sees every removal before the entry is demoted
+    pub fn with_builder(builder: CacheBuilder<K, V, S>, l2: T) -> Result<Self, BuildError>
+    where
+        K: Send + Sync,
+        V: Send + Sync,
+        S: Send + Sync + 'static,
+    {
+        let l2 = Arc::new(l2);
+        let demote = Arc::clone(&l2);
+        let l1 = builder
+            .chain_listener(move |key: &K, value: &V, cause| {
+                if cause == RemovalCause::CapacityEvicted {
+                    demote.put(key.clone(), value.clone());
+                }
+            })
+            .build()?;
+        Ok(Self { l1, l2 })
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        if let Some(value) = self.l1.get(key) {
+            return Some(value);
+        }
+        let value = self.l2.get(key)?;
+        self.l2.remove(key);
+        // a put racing with the promotion has the fresher value, keep it
+        match self.l1.put_if_absent(key.clone(), value.clone()) {
+            Some(current) => Some(current),
+            None => Some(value),
+        }
+    }
+
+    // new values go to L1, an older copy in L2 is dropped
+    pub fn put(&self, key: K, value: V) {
+        self.l2.remove(&key);
+        self.l1.put(key, value);
+    }
+
+    pub fn remove(&self, key: &K) {
+        self.l1.remove(key);
+        self.l2.remove(key);
+    }
+
+    pub fn l1(&self) -> &LruCache<K, V, S> {
+        &self.l1
+    }
+
+    pub fn l2(&self) -> &T {
+        &self.l2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{OnceLock, Weak};
+
+    use super::*;
+
+    #[test]
+    fn evictions_demote_and_hits_promote() {
+        let cache = TieredCache::new(2, LruCache::new(8));
+
+        cache.put(1, "a");
+        cache.put(2, "b");
+        cache.put(3, "c");
+        assert!(!cache.l1().contains_key(&1));
+        assert_eq!(cache.l2().peek(&1), Some("a"));
+
+        // 1 moves back up and pushes 2 down
+        assert_eq!(cache.get(&1), Some("a"));
+        assert!(cache.l1().contains_key(&1));
+        assert!(!cache.l2().contains_key(&1));
+        assert_eq!(cache.l2().peek(&2), Some("b"));
+
+        cache.remove(&2);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.l1().len() + cache.l2().len(), 2);
+    }
+
+    // an L2 that lets a put into L1 slip in while its hit is promoted
+    struct Racing {
+        cache: OnceLock<Weak<TieredCache<u32, &'static str, Racing>>>,
+    }
+
+    impl Tier<u32, &'static str> for Racing {
+        fn get(&self, key: &u32) -> Option<&'static str> {
+            let cache = self.cache.get()?.upgrade()?;
+            cache.l1().put(*key, "fresh");
+            Some("stale")
+        }
+
+        fn put(&self, _: u32, _: &'static str) {}
+
+        fn remove(&self, _: &u32) {}
+    }
+
+    #[test]
+    fn promotion_never_overwrites_a_fresher_put() {
+        let cache = Arc::new(TieredCache::new(
+            2,
+            Racing {
+                cache: OnceLock::new(),
+            },
+        ));
+        let _ = cache.l2().cache.set(Arc::downgrade(&cache));
+
+        assert_eq!(cache.get(&1), Some("fresh"));
+        assert_eq!(cache.l1().peek(&1), Some("fresh"));
+    }
+
+    #[test]
+    fn explicit_removals_are_not_demoted() {
+        let cache = TieredCache::new(2, LruCache::new(8));
+
+        cache.put(1, "a");
+        cache.l1().remove(&1);
+
+        assert!(cache.l2().is_empty());
+    }
+}
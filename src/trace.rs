@@ -0,0 +1,153 @@
+use std::hash::BuildHasher;
+use std::io::{self, BufWriter, Read, Write};
+
+use crate::{CacheStats, LruCache};
+
+// every trace starts with this, followed by a version byte
+const MAGIC: &[u8; 8] = b"LRUTRACE";
+const VERSION: u8 = 1;
+
+const GET: u8 = 0;
+const PUT: u8 = 1;
+
+// one recorded access. keys are recorded by their hash in the recording
+// cache, which is all a replay needs to tell them apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceOp {
+    Get(u64),
+    Put(u64),
+}
+
+// appends accesses as an op byte and the little endian key hash. the first
+// failed write stops the recording, the error is kept for `flush`
+pub(crate) struct TraceWriter {
+    out: Option<BufWriter<Box<dyn Write + Send + Sync>>>,
+    error: Option<io::Error>,
+}
+
+impl TraceWriter {
+    pub(crate) fn new(out: Box<dyn Write + Send + Sync>) -> Self {
+        let mut writer = Self {
+            out: Some(BufWriter::new(out)),
+            error: None,
+        };
+        writer.write(&[MAGIC.as_slice(), &[VERSION]].concat());
+        writer
+    }
+
+    pub(crate) fn record(&mut self, op: TraceOp) {
+        let (code, hash) = match op {
+            TraceOp::Get(hash) => (GET, hash),
+            TraceOp::Put(hash) => (PUT, hash),
+        };
+        let mut record = [0; 9];
+        record[0] = code;
+        record[1..].copy_from_slice(&hash.to_le_bytes());
+        self.write(&record);
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let Some(out) = &mut self.out else {
+            return;
+        };
+        if let Err(err) = out.write_all(bytes) {
+            self.out = None;
+            self.error = Some(err);
+        }
+    }
+
+    // pushes out buffered records, or reports what stopped the recording
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        match &mut self.out {
+            Some(out) => out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// reads back a trace written by `CacheBuilder::record_trace`
+pub struct TraceReader<R> {
+    input: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    // fails unless the input starts with a trace header
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut header = [0; 9];
+        input.read_exact(&mut header)?;
+        if header[..8] != *MAGIC || header[8] != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a cache trace",
+            ));
+        }
+        Ok(Self { input })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceOp>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = [0; 9];
+        // a clean end of input falls between records
+        match self.input.read(&mut record[..1]) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(err) => return Some(Err(err)),
+        }
+        if let Err(err) = self.input.read_exact(&mut record[1..]) {
+            return Some(Err(err));
+        }
+
+        let hash = u64::from_le_bytes(record[1..].try_into().unwrap());
+        Some(match record[0] {
+            GET => Ok(TraceOp::Get(hash)),
+            PUT => Ok(TraceOp::Put(hash)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown trace op",
+            )),
+        })
+    }
+}
+
+// feeds a recorded trace through `cache` and returns its stats afterwards.
+// build the cache with the capacity, weigher or policy being evaluated,
+// entries are keyed by the recorded hashes and carry no value
+pub fn replay<R: Read, S: BuildHasher>(
+    trace: R,
+    cache: &LruCache<u64, (), S>,
+) -> io::Result<CacheStats> {
+    for op in TraceReader::new(trace)? {
+        match op? {
+            TraceOp::Get(hash) => {
+                cache.get(&hash);
+            }
+            TraceOp::Put(hash) => {
+                cache.put(hash, ());
+            }
+        }
+    }
+    Ok(cache.stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_rejects_foreign_input() {
+        assert!(TraceReader::new(&b"not a trace at all"[..]).is_err());
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([VERSION, 7]);
+        bytes.extend(1u64.to_le_bytes());
+        let ops: Vec<_> = TraceReader::new(&bytes[..]).unwrap().collect();
+        assert_eq!(ops.len(), 1);
+        assert!(ops[0].is_err());
+    }
+}
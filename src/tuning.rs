@@ -0,0 +1,115 @@
+// lookups between two capacity decisions
+const PERIOD: u64 = 1000;
+// share of a period's lookups that were ghost hits above which the cache
+// grows, and below which it may shrink
+const GROW_ABOVE: f64 = 0.05;
+const SHRINK_BELOW: f64 = 0.001;
+// a shrink that costs more hit rate than this is not repeated
+const HIT_RATE_SLACK: f64 = 0.01;
+// capacity steps as a share of the current capacity, at least one entry
+const GROW_STEP: f64 = 0.10;
+const SHRINK_STEP: f64 = 0.05;
+
+// moves the capacity between bounds by what the lookups say
+//
+// ghost hits are misses a bigger cache would have served, many of them mean
+// the cache is thrashing and it grows. when there are hardly any the cache
+// is bigger than its working set and shrinks step by step, until a shrink
+// costs hit rate or ghost hits show up again
+pub(crate) struct Tuner {
+    min: usize,
+    max: usize,
+    lookups: u64,
+    hits: u64,
+    ghost_hits: u64,
+    last_hit_rate: f64,
+}
+
+impl Tuner {
+    pub(crate) fn new(min: usize, max: usize) -> Self {
+        Self {
+            min,
+            max,
+            lookups: 0,
+            hits: 0,
+            ghost_hits: 0,
+            last_hit_rate: 0.0,
+        }
+    }
+
+    // counts a lookup. at the end of a period returns the capacity to
+    // switch to, if it changes
+    pub(crate) fn observe(&mut self, capacity: usize, hit: bool, ghost_hit: bool) -> Option<usize> {
+        self.lookups += 1;
+        self.hits += u64::from(hit);
+        self.ghost_hits += u64::from(ghost_hit);
+        if self.lookups < PERIOD {
+            return None;
+        }
+
+        let lookups = self.lookups as f64;
+        let ghost_rate = self.ghost_hits as f64 / lookups;
+        let hit_rate = self.hits as f64 / lookups;
+        let held = hit_rate + HIT_RATE_SLACK >= self.last_hit_rate;
+        (self.lookups, self.hits, self.ghost_hits) = (0, 0, 0);
+        self.last_hit_rate = hit_rate;
+
+        let step = |share: f64| ((capacity as f64 * share) as usize).max(1);
+        let next = if ghost_rate >= GROW_ABOVE {
+            capacity.saturating_add(step(GROW_STEP))
+        } else if ghost_rate < SHRINK_BELOW && held {
+            capacity.saturating_sub(step(SHRINK_STEP))
+        } else {
+            capacity
+        }
+        .clamp(self.min, self.max);
+        (next != capacity).then_some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LruCache;
+
+    #[test]
+    fn thrashing_cache_grows() {
+        let cache = LruCache::builder()
+            .capacity(10)
+            .auto_capacity(10, 100)
+            .build()
+            .unwrap();
+
+        // a loop over 50 keys misses every time below 50 entries
+        for _ in 0..600 {
+            for key in 0..50 {
+                cache.get_or_insert_with(key, || key);
+            }
+        }
+
+        assert!(cache.capacity() >= 50, "{}", cache.capacity());
+        assert!(cache.capacity() <= 100);
+        cache.reset_stats();
+        for key in 0..50 {
+            cache.get(&key);
+        }
+        assert_eq!(cache.stats().hits, 50);
+    }
+
+    #[test]
+    fn oversized_cache_shrinks_to_the_minimum() {
+        let cache = LruCache::builder()
+            .capacity(100)
+            .auto_capacity(10, 100)
+            .build()
+            .unwrap();
+
+        for _ in 0..20_000 {
+            for key in 0..5 {
+                cache.get_or_insert_with(key, || key);
+            }
+        }
+
+        assert_eq!(cache.capacity(), 10);
+        assert!((0..5).all(|key| cache.contains_key(&key)));
+    }
+}